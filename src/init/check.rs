@@ -0,0 +1,173 @@
+//! `check-config`: validate a config file without running the daemon.
+//!
+//! The daemon itself is forgiving on purpose: unknown keys are skipped,
+//! out-of-range values are clamped, and an unparseable file falls back to
+//! the defaults, all so that a bad config still leaves a working
+//! touchpad. The flip side is that a typo like `entryDebouce` does
+//! nothing, quietly. This module says so out loud, and is strict enough
+//! (a non-zero exit on anything that isn't doing what the user wrote) to
+//! serve as a pre-reload hook.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+
+use super::config::{parse_config_str_with, ConfigFormat, Configuration};
+
+/// Keys the config used to have, with what to do about them now.
+const DEPRECATED_KEYS: &[(&str, &str)] = &[(
+    "responseTime",
+    "the event loop is event-driven now, so there is no poll interval; remove it",
+)];
+
+pub struct Report {
+    pub errors: Vec<String>,
+    pub warnings: Vec<String>,
+    /// The configuration the daemon would run with (after clamping), if
+    /// the file parsed at all.
+    pub effective: Option<Configuration>,
+}
+
+impl Report {
+    pub fn ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    pub fn render(&self, path: &Path) -> String {
+        let mut s = String::new();
+        let _ = writeln!(s, "Checking {}", path.display());
+        for e in &self.errors {
+            let _ = writeln!(s, "error: {e}");
+        }
+        for w in &self.warnings {
+            let _ = writeln!(s, "warning: {w}");
+        }
+        if let Some(cfg) = &self.effective {
+            let _ = writeln!(s, "\nEffective configuration:\n{cfg:#?}");
+        }
+        let _ = writeln!(
+            s,
+            "\n{} error(s), {} warning(s)",
+            self.errors.len(),
+            self.warnings.len()
+        );
+        s
+    }
+}
+
+pub fn check(path: &Path) -> Report {
+    let mut report = Report {
+        errors: Vec::new(),
+        warnings: Vec::new(),
+        effective: None,
+    };
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) => {
+            report.errors.push(format!("cannot read the file: {e}"));
+            return report;
+        }
+    };
+
+    let mut unknown = Vec::new();
+    let cfg = match parse_config_str_with(&text, ConfigFormat::of(path), |key| unknown.push(key)) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            report.errors.push(e);
+            return report;
+        }
+    };
+
+    let known = known_keys();
+    for key in unknown {
+        if let Some((_, advice)) = DEPRECATED_KEYS.iter().find(|(old, _)| *old == key) {
+            report
+                .warnings
+                .push(format!("`{key}` is deprecated and ignored: {advice}"));
+        } else if let Some(suggestion) = did_you_mean(&key, known) {
+            report.errors.push(format!(
+                "unknown key `{key}` is ignored (did you mean `{suggestion}`?)"
+            ));
+        } else {
+            report
+                .errors
+                .push(format!("unknown key `{key}` is ignored"));
+        }
+    }
+
+    let (cfg, adjustments) = cfg.sanitized();
+    report
+        .warnings
+        .extend(adjustments.iter().map(|adj| adj.to_string()));
+    report.effective = Some(cfg);
+    report
+}
+
+/// The closest known key to a misspelled one, if any is close enough to
+/// be a plausible typo. Case, `_` and `-` are ignored, so snake_case and
+/// kebab-case spellings of a key are always recognized.
+fn did_you_mean<'k>(key: &str, known: &[&'k str]) -> Option<&'k str> {
+    let norm = |k: &str| -> Vec<char> {
+        k.chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let key = norm(key);
+    known
+        .iter()
+        .map(|k| (edit_distance(&key, &norm(k)), *k))
+        .filter(|(d, _)| *d <= 2.max(key.len() / 4))
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, k)| k)
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitute = prev[j] + usize::from(ca != cb);
+            cur[j + 1] = substitute.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
+
+/// The config's key names, read straight off its serde schema, so a new
+/// knob can never be missing from the suggestions. (Derived
+/// `Deserialize` impls hand their field list to `deserialize_struct`;
+/// this deserializer just keeps it and bails out.)
+fn known_keys() -> &'static [&'static str] {
+    struct FieldNames<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for FieldNames<'_> {
+        type Error = de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Self::Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Self::Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("field names captured"))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = Configuration::deserialize(FieldNames(&mut fields));
+    fields
+}
@@ -1,22 +1,24 @@
 use serde::Deserialize;
-use serde_json::from_str;
 use std::{
-    fs::{read_to_string, File, OpenOptions},
+    fs::read_to_string,
     io::ErrorKind,
-    path::PathBuf,
+    path::{Path, PathBuf},
     time::Duration,
 };
 
-use tracing_subscriber::{
-    filter::LevelFilter,
-    fmt::{
-        format::{DefaultFields, Format, Full},
-        time::ChronoLocal,
-        SubscriberBuilder,
-    },
-};
+use tracing_subscriber::filter::LevelFilter;
+
+use super::selector::DeviceFilter;
+use crate::runtime::gesture::{ClickAction, Timing, PX_PER_MM};
 
-use crate::runtime::gesture::{Timing, PX_PER_MM};
+// The ceilings `Configuration::sanitize` clamps to. Public so the
+// adaptive learner (runtime/adaptive.rs) can be held to exactly the
+// same limits as a hand-written config.
+pub const MAX_PROBE_DELAY: Duration = Duration::from_millis(200);
+pub const MAX_ENTRY_DEBOUNCE: Duration = Duration::from_millis(500);
+pub const MAX_PRESS_GRACE: Duration = Duration::from_millis(1000);
+pub const MAX_DRAG_END_DELAY: Duration = Duration::from_millis(5000);
+pub const MAX_LOG_KEEP: u32 = 100;
 
 // This is simply a wrapper to allow deserialization of the
 // logLevel field into a tracing LevelFilter, albeit in
@@ -62,6 +64,29 @@ pub struct Configuration {
     #[serde(default = "default_info")]
     pub log_level: LogLevel,
 
+    // Where to write the per-touch decision trace as JSON lines (one
+    // object per state transition, see runtime/gesture.rs), independent
+    // of logLevel. Unset (the default) writes no trace file.
+    #[serde(default)]
+    pub trace_file: Option<String>,
+
+    // Built-in rotation of logFile and traceFile (nothing to rotate when
+    // logging to stdout). A file is rotated to <file>.1 before it would
+    // grow past logMaxSize MiB, or once it's logMaxAge hours old (0
+    // disables either limit), and logKeep rotated generations are kept,
+    // gzipped if logCompress is set. See init/rotate.rs.
+    #[serde(default = "default_10")]
+    pub log_max_size: u64, // in MiB
+
+    #[serde(default)]
+    pub log_max_age: u64, // in hours
+
+    #[serde(default = "default_3")]
+    pub log_keep: u32,
+
+    #[serde(default = "default_false")]
+    pub log_compress: bool,
+
     // NOTE: the old `responseTime` knob is gone: the event loop is now
     // fully event-driven (it sleeps on the device fd and on exact
     // decision deadlines), so there is no poll interval to configure.
@@ -103,6 +128,28 @@ pub struct Configuration {
     #[serde(default = "default_75ms")]
     #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
     pub press_grace: Duration, // in milliseconds
+
+    // Let the daemon tune entryDebounce and pressGrace to the user's own
+    // finger stagger as it runs (see runtime/adaptive.rs). The values
+    // above become the starting point; what's learned is persisted per
+    // device under $XDG_STATE_HOME and picked up again on restart.
+    #[serde(default = "default_false")]
+    pub adaptive_timing: bool,
+
+    // What pressing the clickpad down in the middle of a 3-finger drag
+    // does: "ignore", "drop" the drag, "toggle-lock" it (held after
+    // lifting until the next touch), or switch it to a "right-drag".
+    // The click itself is never passed on (see runtime/gesture.rs).
+    #[serde(default)]
+    pub click_during_drag: ClickAction,
+
+    // Which touchpad(s) discovery may pick, by device selector (see
+    // init/selector.rs): `include` lists the acceptable ones in order of
+    // preference (empty: any touchpad), `exclude` the ones never to
+    // touch. `--device` on the command line replaces `include`, and
+    // `--exclude` adds to `exclude`. Applies at the next (re)discovery.
+    #[serde(default)]
+    pub devices: DeviceFilter,
 }
 
 impl Configuration {
@@ -126,9 +173,17 @@ impl Default for Configuration {
             drag_end_delay: Duration::from_millis(0),
             log_file: "stdout".to_string(),
             log_level: LogLevel::INFO,
+            trace_file: None,
+            log_max_size: 10,
+            log_max_age: 0,
+            log_keep: 3,
+            log_compress: false,
             entry_debounce: Duration::from_millis(50),
             probe_delay: Duration::from_millis(15),
             press_grace: Duration::from_millis(75),
+            adaptive_timing: false,
+            click_during_drag: ClickAction::Ignore,
+            devices: DeviceFilter::default(),
         }
     }
 }
@@ -152,14 +207,34 @@ fn default_75ms() -> Duration {
 fn default_50ms() -> Duration {
     Duration::from_millis(50)
 }
+fn default_10() -> u64 {
+    10
+}
+fn default_3() -> u32 {
+    3
+}
 fn default_stdout() -> String {
     "stdout".to_string()
 }
 fn default_info() -> LogLevel {
     LogLevel::INFO
 }
+fn default_false() -> bool {
+    false
+}
 
-pub fn get_config_file_path() -> Result<PathBuf, std::io::Error> {
+/// The config file names looked for, in order of precedence: if more
+/// than one exists, the first one found wins and the others are ignored
+/// (with a warning). TOML and YAML come first because they allow
+/// comments next to each knob; JSON, the original format, comes last.
+pub const CONFIG_FILE_NAMES: [&str; 4] = [
+    "3fd-config.toml",
+    "3fd-config.yaml",
+    "3fd-config.yml",
+    "3fd-config.json",
+];
+
+pub fn get_config_dir() -> Result<PathBuf, std::io::Error> {
     let config_folder = match std::env::var_os("XDG_CONFIG_HOME") {
         Some(config_dir) => PathBuf::from(config_dir),
         None => {
@@ -174,12 +249,167 @@ pub fn get_config_file_path() -> Result<PathBuf, std::io::Error> {
             }
         }
     };
-    let filepath = config_folder.join("linux-3-finger-drag/3fd-config.json");
-    Ok(filepath)
+    Ok(config_folder.join("linux-3-finger-drag"))
+}
+
+/// The config file in effect: the highest-precedence one of
+/// [`CONFIG_FILE_NAMES`] that exists, or the JSON one if none do.
+pub fn get_config_file_path() -> Result<PathBuf, std::io::Error> {
+    Ok(config_files_in(&get_config_dir()?).0)
+}
+
+/// The config file in effect (see [`get_config_file_path`]), and the
+/// ones that exist too but are ignored.
+pub fn get_config_files() -> Result<(PathBuf, Vec<PathBuf>), std::io::Error> {
+    Ok(config_files_in(&get_config_dir()?))
+}
+
+fn config_files_in(config_dir: &Path) -> (PathBuf, Vec<PathBuf>) {
+    let mut present: Vec<PathBuf> = CONFIG_FILE_NAMES
+        .iter()
+        .map(|name| config_dir.join(name))
+        .filter(|path| path.exists())
+        .collect();
+    if present.is_empty() {
+        return (
+            config_dir.join(CONFIG_FILE_NAMES[CONFIG_FILE_NAMES.len() - 1]),
+            present,
+        );
+    }
+    let chosen = present.remove(0);
+    (chosen, present)
+}
+
+/// Where persistent runtime state (as opposed to configuration) lives:
+/// `$XDG_STATE_HOME/linux-3-finger-drag`, per the XDG base directory
+/// spec's fallback of `~/.local/state`.
+pub fn get_state_dir() -> Result<PathBuf, std::io::Error> {
+    let state_folder = match std::env::var_os("XDG_STATE_HOME") {
+        Some(state_dir) => PathBuf::from(state_dir),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local/state"),
+            None => {
+                return Err(std::io::Error::new(
+                    ErrorKind::NotFound,
+                    "Neither $XDG_STATE_HOME or $HOME defined in environment",
+                ))
+            }
+        },
+    };
+    Ok(state_folder.join("linux-3-finger-drag"))
+}
+
+/// Where per-session runtime files (sockets, locks) live:
+/// `$XDG_RUNTIME_DIR/linux-3-finger-drag`. There's no sensible fallback
+/// for a per-user, per-boot, private directory, so its absence is an error.
+pub fn get_runtime_dir() -> Result<PathBuf, std::io::Error> {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Ok(PathBuf::from(dir).join("linux-3-finger-drag")),
+        None => Err(std::io::Error::new(
+            ErrorKind::NotFound,
+            "$XDG_RUNTIME_DIR not defined in environment",
+        )),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    /// By file extension; anything unrecognized is read as JSON, the
+    /// original format.
+    pub fn of(path: &Path) -> ConfigFormat {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => ConfigFormat::Toml,
+            Some("yaml" | "yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+/// 1-based line and column of byte `offset` in `text`.
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, col)
+}
+
+/// Deserializes `text` in the given format. Errors read
+/// `line:col: message`, whatever the format, so a typo can be found
+/// without knowing which parser complained.
+pub fn parse_config_str(text: &str, format: ConfigFormat) -> Result<Configuration, String> {
+    parse_config_str_with(text, format, |_| {})
+}
+
+/// [`parse_config_str`], also handing every key the schema doesn't know
+/// (serde silently skips those) to `unknown`, as a dotted path.
+pub fn parse_config_str_with(
+    text: &str,
+    format: ConfigFormat,
+    mut unknown: impl FnMut(String),
+) -> Result<Configuration, String> {
+    let mut on_ignored = |path: serde_ignored::Path| unknown(path.to_string());
+    match format {
+        ConfigFormat::Json => {
+            let mut de = serde_json::Deserializer::from_str(text);
+            serde_ignored::deserialize(&mut de, &mut on_ignored)
+                .and_then(|cfg| de.end().map(|()| cfg))
+                .map_err(|e| {
+                    // serde_json appends " at line L column C" itself; keep
+                    // just the message
+                    let msg = e.to_string();
+                    let msg = msg.split(" at line ").next().unwrap_or(&msg).to_string();
+                    format!("{}:{}: {msg}", e.line(), e.column())
+                })
+        }
+        ConfigFormat::Toml => {
+            serde_ignored::deserialize(toml::Deserializer::new(text), &mut on_ignored).map_err(
+                |e| {
+                    let (line, col) = line_col(text, e.span().map_or(0, |span| span.start));
+                    format!("{line}:{col}: {}", e.message())
+                },
+            )
+        }
+        ConfigFormat::Yaml => {
+            // an empty YAML document is "null", not an empty mapping, but
+            // an empty config file should mean "all defaults" in every format
+            let text = if text.trim().is_empty() { "{}" } else { text };
+            serde_ignored::deserialize(serde_yaml::Deserializer::from_str(text), &mut on_ignored)
+                .map_err(|e| match e.location() {
+                    Some(loc) => {
+                        let msg = e.to_string();
+                        let msg = msg.split(" at line ").next().unwrap_or(&msg).to_string();
+                        format!("{}:{}: {msg}", loc.line(), loc.column())
+                    }
+                    None => format!("1:1: {e}"),
+                })
+        }
+    }
+}
+
+pub fn parse_config_at(filepath: &Path) -> Result<Configuration, std::io::Error> {
+    let text = read_to_string(filepath).map_err(|_|
+            // more descriptive error
+            std::io::Error::new(
+                ErrorKind::NotFound,
+                format!("Unable to locate config file at {:?} ", filepath)
+            ))?;
+
+    parse_config_str(&text, ConfigFormat::of(filepath)).map_err(|e| {
+        std::io::Error::new(
+            ErrorKind::InvalidData,
+            format!("{}:{e}", filepath.display()),
+        )
+    })
 }
 
 // Configs are so optional that their absence should not crash the program,
-// So if there is any issue with the JSON config file,
+// So if there is any issue with the config file,
 // the following default values will be returned:
 //
 // {
@@ -194,18 +424,25 @@ pub fn get_config_file_path() -> Result<PathBuf, std::io::Error> {
 // The user is also warned about this, so they can address the issues
 // if they want to configure the way the program runs.
 pub fn parse_config_file() -> Result<Configuration, std::io::Error> {
-    let filepath = get_config_file_path()?;
-    let jsonfile = read_to_string(&filepath).map_err(|_|
-            // more descriptive error
-            std::io::Error::new(
-                ErrorKind::NotFound,
-                format!("Unable to locate JSON file at {:?} ", filepath)
-            ))?;
+    parse_config_at(&get_config_file_path()?)
+}
 
-    // use serde's error as is
-    let config = from_str::<Configuration>(&jsonfile)?;
+/// One value [`Configuration::sanitize`] had to clamp.
+#[derive(Debug, Clone)]
+pub struct Adjustment {
+    pub key: &'static str,
+    pub before: String,
+    pub after: String,
+}
 
-    Ok(config)
+impl std::fmt::Display for Adjustment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "config `{}` = {} is out of range; using {}",
+            self.key, self.before, self.after
+        )
+    }
 }
 
 impl Configuration {
@@ -215,11 +452,20 @@ impl Configuration {
     /// (a bad `acceleration` inverting drags, a `dragEndDelay` of an
     /// hour holding the button down, a `probeDelay` longer than the
     /// entry window starving classification...).
-    fn sanitize(mut self) -> Configuration {
-        let fix = |what: &str, before: String, after: String| {
-            println!(
-                "[PRE-LOG: WARNING]: config `{what}` = {before} is out of range; using {after}"
-            );
+    fn sanitize(self) -> Configuration {
+        let (cfg, adjustments) = self.sanitized();
+        for adj in adjustments {
+            println!("[PRE-LOG: WARNING]: {adj}");
+        }
+        cfg
+    }
+
+    /// [`sanitize`](Self::sanitize) without the printing: the clamped
+    /// configuration, plus what was clamped (for `check-config`).
+    pub fn sanitized(mut self) -> (Configuration, Vec<Adjustment>) {
+        let mut adjustments = Vec::new();
+        let mut fix = |key: &'static str, before: String, after: String| {
+            adjustments.push(Adjustment { key, before, after });
         };
         if !self.acceleration.is_finite() || self.acceleration <= 0.0 {
             fix(
@@ -237,21 +483,21 @@ impl Configuration {
             );
             self.acceleration = clamped;
         }
-        if self.probe_delay > Duration::from_millis(200) {
+        if self.probe_delay > MAX_PROBE_DELAY {
             fix(
                 "probeDelay",
                 format!("{:?}", self.probe_delay),
-                "200ms".into(),
+                format!("{:?}", MAX_PROBE_DELAY),
             );
-            self.probe_delay = Duration::from_millis(200);
+            self.probe_delay = MAX_PROBE_DELAY;
         }
-        if self.entry_debounce > Duration::from_millis(500) {
+        if self.entry_debounce > MAX_ENTRY_DEBOUNCE {
             fix(
                 "entryDebounce",
                 format!("{:?}", self.entry_debounce),
-                "500ms".into(),
+                format!("{:?}", MAX_ENTRY_DEBOUNCE),
             );
-            self.entry_debounce = Duration::from_millis(500);
+            self.entry_debounce = MAX_ENTRY_DEBOUNCE;
         }
         if self.entry_debounce < self.probe_delay {
             fix(
@@ -261,26 +507,49 @@ impl Configuration {
             );
             self.entry_debounce = self.probe_delay;
         }
-        if self.press_grace > Duration::from_millis(1000) {
+        if self.press_grace > MAX_PRESS_GRACE {
             fix(
                 "pressGrace",
                 format!("{:?}", self.press_grace),
-                "1000ms".into(),
+                format!("{:?}", MAX_PRESS_GRACE),
             );
-            self.press_grace = Duration::from_millis(1000);
+            self.press_grace = MAX_PRESS_GRACE;
         }
-        if self.drag_end_delay > Duration::from_millis(5000) {
+        if self.drag_end_delay > MAX_DRAG_END_DELAY {
             fix(
                 "dragEndDelay",
                 format!("{:?}", self.drag_end_delay),
-                "5000ms".into(),
+                format!("{:?}", MAX_DRAG_END_DELAY),
+            );
+            self.drag_end_delay = MAX_DRAG_END_DELAY;
+        }
+        if self.log_keep > MAX_LOG_KEEP {
+            fix(
+                "logKeep",
+                format!("{}", self.log_keep),
+                format!("{MAX_LOG_KEEP}"),
             );
-            self.drag_end_delay = Duration::from_millis(5000);
+            self.log_keep = MAX_LOG_KEEP;
         }
-        self
+        (self, adjustments)
     }
 }
 
+/// The configuration at startup: [`init_cfg`], after a warning about
+/// config files that are shadowed by another -- given once, here, rather
+/// than on every reload.
+pub fn load_config() -> Configuration {
+    if let Ok((chosen, ignored)) = get_config_files() {
+        for ignored in ignored {
+            println!(
+                "[PRE-LOG: WARNING]: Both {:?} and {:?} exist; only the first is used.",
+                chosen, ignored
+            );
+        }
+    }
+    init_cfg()
+}
+
 pub fn init_cfg() -> Configuration {
     println!("[PRE-LOG: INFO]: Loading configuration...");
     let configs = match parse_config_file() {
@@ -302,47 +571,5 @@ pub fn init_cfg() -> Configuration {
     configs
 }
 
-pub fn init_file_logger(
-    cfg: Configuration,
-) -> Option<SubscriberBuilder<DefaultFields, Format<Full, ChronoLocal>, LevelFilter, File>> {
-    let log_level: LevelFilter = cfg.log_level.into();
-
-    // If the log file is either "stdout" or an invalid file,
-    // bypass this block and go to the end, initializing a
-    // SimpleLogger (for console logging)
-    if cfg.log_file == "stdout" {
-        return None;
-    }
-
-    // create(true): a fresh install has no log file yet, and failing to
-    // *create* one shouldn't silently demote logging to stdout
-    match OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&cfg.log_file)
-    {
-        Ok(log_file) => {
-            let file_logger = tracing_subscriber::fmt()
-                .with_writer(log_file)
-                .with_max_level(log_level)
-                .with_timer(ChronoLocal::rfc_3339());
-            println!(
-                "[PRE-LOG: INFO]: Logging to '{}' at {}-level verbosity.",
-                cfg.log_file, log_level
-            );
-            Some(file_logger)
-        }
-
-        Err(open_err) => {
-            println!(
-                "[PRE-LOG: WARN]: Failed to open logfile '{}' \
-                due to the the following error: {}, {}.",
-                cfg.log_file,
-                open_err.kind(),
-                open_err
-            );
-            println!("[PRE-LOG: WARN]: Logging to stdout at {log_level}-level verbosity.");
-            None
-        }
-    }
-}
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,219 @@
+//! Config loading: every format reads the same schema, and every format
+//! reports where a mistake is.
+
+use super::*;
+
+#[test]
+fn every_format_reads_the_same_schema() {
+    let json = r#"{ "acceleration": 1.5, "pressGrace": 90, "logLevel": "debug" }"#;
+    let toml = "# drag a bit faster\nacceleration = 1.5\npressGrace = 90\nlogLevel = \"debug\"\n";
+    let yaml = "# drag a bit faster\nacceleration: 1.5\npressGrace: 90\nlogLevel: debug\n";
+    for (text, format) in [
+        (json, ConfigFormat::Json),
+        (toml, ConfigFormat::Toml),
+        (yaml, ConfigFormat::Yaml),
+    ] {
+        let cfg = parse_config_str(text, format).unwrap();
+        assert_eq!(cfg.acceleration, 1.5, "{format:?}");
+        assert_eq!(cfg.press_grace, Duration::from_millis(90), "{format:?}");
+        assert!(matches!(cfg.log_level, LogLevel::DEBUG), "{format:?}");
+        // unspecified knobs keep their defaults
+        assert_eq!(cfg.entry_debounce, Duration::from_millis(50), "{format:?}");
+    }
+}
+
+#[test]
+fn empty_files_mean_all_defaults() {
+    for format in [ConfigFormat::Toml, ConfigFormat::Yaml] {
+        let cfg = parse_config_str("", format).unwrap();
+        assert_eq!(cfg.probe_delay, Duration::from_millis(15), "{format:?}");
+    }
+}
+
+#[test]
+fn parse_errors_carry_line_and_column() {
+    let cases = [
+        (
+            ConfigFormat::Json,
+            "{\n    \"acceleration\": 1.0,\n    \"pressGrace\": \"lots\"\n}",
+        ),
+        (
+            ConfigFormat::Toml,
+            "acceleration = 1.0\npressGrace = \"lots\"\n",
+        ),
+        (ConfigFormat::Yaml, "acceleration: 1.0\npressGrace: lots\n"),
+    ];
+    for (format, text) in cases {
+        let err = parse_config_str(text, format).unwrap_err();
+        let mut parts = err.splitn(3, ':');
+        let line = parts.next().unwrap();
+        let col: usize = parts.next().unwrap().parse().unwrap();
+        assert!(
+            matches!((format, line), (ConfigFormat::Json, "3") | (_, "2")),
+            "{format:?}: {err}"
+        );
+        assert!(col >= 1, "{format:?}: {err}");
+    }
+}
+
+#[test]
+fn format_follows_the_extension() {
+    assert_eq!(
+        ConfigFormat::of(Path::new("a/3fd-config.toml")),
+        ConfigFormat::Toml
+    );
+    assert_eq!(
+        ConfigFormat::of(Path::new("3fd-config.yml")),
+        ConfigFormat::Yaml
+    );
+    assert_eq!(
+        ConfigFormat::of(Path::new("3fd-config.yaml")),
+        ConfigFormat::Yaml
+    );
+    assert_eq!(
+        ConfigFormat::of(Path::new("3fd-config.json")),
+        ConfigFormat::Json
+    );
+}
+
+#[test]
+fn the_first_config_file_wins_and_the_others_are_reported() {
+    let dir = std::env::temp_dir().join(format!("3fd-config-files-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    assert_eq!(
+        config_files_in(&dir),
+        (dir.join("3fd-config.json"), Vec::new())
+    );
+
+    for name in ["3fd-config.json", "3fd-config.yaml"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+    assert_eq!(
+        config_files_in(&dir),
+        (
+            dir.join("3fd-config.yaml"),
+            vec![dir.join("3fd-config.json")]
+        )
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn shipped_sample_configs_parse() {
+    for (text, format) in [
+        (include_str!("../../../3fd-config.json"), ConfigFormat::Json),
+        (include_str!("../../../3fd-config.toml"), ConfigFormat::Toml),
+    ] {
+        parse_config_str(text, format).unwrap();
+    }
+}
+
+/// Writes `text` to a scratch file named `name` and runs `check-config`
+/// on it.
+fn check_file(name: &str, text: &str) -> crate::init::check::Report {
+    let dir = std::env::temp_dir().join(format!("3fd-check-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, text).unwrap();
+    crate::init::check::check(&path)
+}
+
+#[test]
+fn check_config_flags_typos_with_suggestions() {
+    let report = check_file(
+        "typo.toml",
+        "entryDebouce = 60\npress_grace = 80\nresponseTime = 5\n",
+    );
+    assert!(!report.ok());
+    assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+    assert!(report.errors[0].contains("did you mean `entryDebounce`"));
+    assert!(report.errors[1].contains("did you mean `pressGrace`"));
+    assert_eq!(report.warnings.len(), 1, "{:?}", report.warnings);
+    assert!(report.warnings[0].contains("`responseTime` is deprecated"));
+}
+
+#[test]
+fn check_config_lists_clamped_values_and_the_effective_result() {
+    let report = check_file(
+        "clamp.json",
+        r#"{ "pressGrace": 5000, "acceleration": -1 }"#,
+    );
+    assert!(report.ok(), "{:?}", report.errors);
+    assert_eq!(report.warnings.len(), 2, "{:?}", report.warnings);
+    let cfg = report.effective.unwrap();
+    assert_eq!(cfg.press_grace, MAX_PRESS_GRACE);
+    assert_eq!(cfg.acceleration, 1.0);
+}
+
+#[test]
+fn check_config_fails_on_syntax_errors_and_missing_files() {
+    let report = check_file("broken.yaml", "acceleration: [1.0\n");
+    assert!(!report.ok());
+    assert!(report.effective.is_none());
+
+    let report = crate::init::check::check(Path::new("/nonexistent/3fd-config.json"));
+    assert!(!report.ok());
+}
+
+#[test]
+fn device_selection_reads_in_every_format_and_bad_selectors_are_errors() {
+    let toml = "[devices]\ninclude = [\"*Touchpad*\", \"06cb:cd8b\"]\nexclude = [\"phys:usb-*\"]\n";
+    let yaml =
+        "devices:\n  include: [\"*Touchpad*\", \"06cb:cd8b\"]\n  exclude: [\"phys:usb-*\"]\n";
+    let json =
+        r#"{ "devices": { "include": ["*Touchpad*", "06cb:cd8b"], "exclude": ["phys:usb-*"] } }"#;
+    for (text, format) in [
+        (toml, ConfigFormat::Toml),
+        (yaml, ConfigFormat::Yaml),
+        (json, ConfigFormat::Json),
+    ] {
+        let cfg = parse_config_str(text, format).unwrap();
+        assert_eq!(cfg.devices.include.len(), 2, "{format:?}");
+        assert_eq!(
+            cfg.devices.exclude[0].to_string(),
+            "phys:usb-*",
+            "{format:?}"
+        );
+    }
+    assert!(parse_config_str("", ConfigFormat::Toml)
+        .unwrap()
+        .devices
+        .is_empty());
+
+    let report = check_file("selector.toml", "[devices]\ninclude = [\"name:\"]\n");
+    assert!(!report.ok());
+    assert!(
+        report.errors[0].contains("empty name pattern"),
+        "{:?}",
+        report.errors
+    );
+}
+
+/// Hot reload must see both ways editors save: in place, and by renaming
+/// a temporary file over the config.
+#[test]
+fn config_watch_sees_in_place_and_atomic_rename_saves() {
+    use crate::runtime::config_watch::ConfigWatch;
+
+    let dir = std::env::temp_dir().join(format!("3fd-watch-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("3fd-config.toml");
+    std::fs::write(&file, "acceleration = 1.0\n").unwrap();
+    let mut watch = ConfigWatch::new(&dir, &file).unwrap();
+    assert!(!watch.changed().unwrap());
+
+    std::fs::write(&file, "acceleration = 1.5\n").unwrap();
+    assert!(watch.changed().unwrap(), "in-place write");
+
+    let tmp = dir.join(".3fd-config.toml.swp");
+    std::fs::write(&tmp, "acceleration = 2.0\n").unwrap();
+    assert!(!watch.changed().unwrap(), "unrelated file");
+    std::fs::rename(&tmp, &file).unwrap();
+    assert!(watch.changed().unwrap(), "rename over the config");
+    watch.rearm(&file);
+
+    std::fs::write(&file, "acceleration = 2.5\n").unwrap();
+    assert!(watch.changed().unwrap(), "in-place write after rearm");
+    let _ = std::fs::remove_dir_all(&dir);
+}
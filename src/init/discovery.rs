@@ -5,101 +5,337 @@
 //! libinput itself uses to classify one:
 //!
 //! * `INPUT_PROP_POINTER` (it moves a cursor, i.e. not a touchscreen)
-//! * multitouch slots + positions (`ABS_MT_SLOT`, `ABS_MT_POSITION_X/Y`)
+//! * multitouch positions (`ABS_MT_POSITION_X/Y`), in slots
+//!   (`ABS_MT_SLOT`, protocol B) or as anonymous contacts (protocol A,
+//!   which the proxy tracks into slots itself)
 //! * `BTN_TOOL_FINGER` (finger-count reporting)
 //!
+//! Besides passing those, a touchpad must be able to tell three fingers
+//! apart for a drag to ever be recognized: with 3+ slots, or -- on the
+//! many older pads with only 2 slots (or `INPUT_PROP_SEMI_MT`) -- with
+//! `BTN_TOOL_TRIPLETAP`. One that can't is still proxied (it's the
+//! user's choice of touchpad), but discovery says so.
+//!
 //! Doing this directly (instead of going through libinput's udev seat
 //! enumeration, as earlier versions did) needs no C library, no seat
 //! assignment, and -- crucially -- lets us skip our *own* synthetic
 //! clone, which impersonates the real touchpad's identity exactly and
 //! is only distinguishable by the `phys` marker we stamp on it. That
 //! matters when re-discovering after the real device re-enumerates.
+//!
+//! Of the touchpads found, only those the [`DeviceFilter`] lets through
+//! are returned, in its order of preference.
 
 use std::fs::OpenOptions;
 use std::io::{Error, ErrorKind};
 use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
 
 use input_linux::{AbsoluteAxis, EvdevHandle, InputProperty, Key};
 use libc::O_NONBLOCK;
-use tracing::{debug, error, info};
+use serde::Serialize;
+use tracing::{debug, error, info, warn};
 
+use super::selector::{DeviceFilter, DeviceInfo};
+use crate::runtime::gesture::MAX_SLOTS;
 use crate::runtime::mt_proxy::CLONE_PHYS_MARKER;
 
-/// Finds every real multitouch touchpad, returning `/dev/input/eventN`
-/// paths. The caller opens these directly so it can exclusively grab
-/// and proxy the raw event stream.
-pub fn find_real_trackpads() -> Result<Vec<String>, Error> {
-    let mut found = Vec::new();
-    let mut denied = 0usize;
-    let mut inspected = 0usize;
+/// One of the tests a device must pass to count as a touchpad, in the
+/// order they're listed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Check {
+    /// `INPUT_PROP_POINTER`: it moves a cursor (not a touchscreen).
+    PointerProp,
+    MtPositionX,
+    MtPositionY,
+    /// `BTN_TOOL_FINGER`: it reports finger counts.
+    BtnToolFinger,
+    /// It isn't our own synthetic clone (by its phys marker).
+    NotOurClone,
+}
+
+impl Check {
+    pub const ALL: [Check; 5] = [
+        Check::PointerProp,
+        Check::MtPositionX,
+        Check::MtPositionY,
+        Check::BtnToolFinger,
+        Check::NotOurClone,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Check::PointerProp => "INPUT_PROP_POINTER",
+            Check::MtPositionX => "ABS_MT_POSITION_X",
+            Check::MtPositionY => "ABS_MT_POSITION_Y",
+            Check::BtnToolFinger => "BTN_TOOL_FINGER",
+            Check::NotOurClone => "not our own clone",
+        }
+    }
+}
+
+/// What discovery made of one `/dev/input/event*` device.
+#[derive(Debug, Clone, Serialize)]
+pub struct Probe {
+    #[serde(flatten)]
+    pub info: DeviceInfo,
+    /// Why it couldn't be inspected at all (e.g. permission denied); the
+    /// checks are then empty.
+    pub error: Option<String>,
+    #[serde(serialize_with = "checks_as_map")]
+    pub checks: Vec<(Check, bool)>,
+    /// How it counts fingers, if it could be inspected.
+    pub fingers: Option<Fingers>,
+}
+
+/// How a device counts fingers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Fingers {
+    /// Its ABS_MT_SLOT range: how many fingers it tracks individually.
+    pub slots: usize,
+    /// No slots at all: multitouch protocol A, whose contacts the proxy
+    /// tracks itself (as many as it has slots for).
+    pub protocol_a: bool,
+    /// `INPUT_PROP_SEMI_MT`: its slots hold a bounding box, not fingers.
+    pub semi_mt: bool,
+    /// The most its `BTN_TOOL_*` bits can count (`BTN_TOOL_FINGER` 1 up
+    /// to `BTN_TOOL_QUINTTAP` 5).
+    pub tool_fingers: usize,
+}
+
+impl Fingers {
+    /// How many fingers it can tell are down, by slots or tool bits.
+    pub fn max(&self) -> usize {
+        if self.protocol_a {
+            return MAX_SLOTS;
+        }
+        self.slots.max(self.tool_fingers)
+    }
+
+    /// Whether a 3-finger touch is recognizable on it at all.
+    pub fn counts_three(&self) -> bool {
+        self.max() >= 3
+    }
+}
+
+impl std::fmt::Display for Fingers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.protocol_a {
+            return write!(f, "MT protocol A, contacts tracked into slots");
+        }
+        write!(f, "{} slot(s)", self.slots)?;
+        if self.semi_mt {
+            write!(f, ", semi-MT")?;
+        }
+        if self.tool_fingers > self.slots {
+            write!(f, ", counts up to {} by BTN_TOOL_*", self.tool_fingers)?;
+        }
+        Ok(())
+    }
+}
+
+impl Probe {
+    pub fn denied(&self) -> bool {
+        self.error.as_deref() == Some(PERMISSION_DENIED)
+    }
+
+    /// Passed every check: a real multitouch touchpad.
+    pub fn is_touchpad(&self) -> bool {
+        self.error.is_none() && self.checks.iter().all(|&(_, passed)| passed)
+    }
+}
+
+/// `{"pointer-prop": true, ...}`, in check order.
+fn checks_as_map<S: serde::Serializer>(
+    checks: &[(Check, bool)],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(checks.iter().map(|(check, passed)| (check, passed)))
+}
 
-    let mut entries: Vec<_> = std::fs::read_dir("/dev/input")?
+const PERMISSION_DENIED: &str = "permission denied";
+
+/// Inspects the device at `path`, running every check (not stopping at
+/// the first failure, so a listing can show them all).
+pub fn probe(path: PathBuf) -> Probe {
+    let mut probe = Probe {
+        info: DeviceInfo {
+            path,
+            ..Default::default()
+        },
+        error: None,
+        checks: Vec::new(),
+        fingers: None,
+    };
+    let file = match OpenOptions::new()
+        .read(true)
+        .custom_flags(O_NONBLOCK)
+        .open(&probe.info.path)
+    {
+        Ok(f) => f,
+        Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+            probe.error = Some(PERMISSION_DENIED.to_string());
+            return probe;
+        }
+        Err(e) => {
+            probe.error = Some(e.to_string());
+            return probe;
+        }
+    };
+    let dev = EvdevHandle::new(file);
+
+    let text = |bytes: Vec<u8>| {
+        String::from_utf8_lossy(&bytes)
+            .trim_end_matches('\0')
+            .to_string()
+    };
+    probe.info.name = dev
+        .device_name()
+        .map(text)
+        .unwrap_or_else(|_| "<unknown>".to_string());
+    probe.info.phys = dev.physical_location().map(text).unwrap_or_default();
+    if let Ok(id) = dev.device_id() {
+        probe.info.vendor = id.vendor;
+        probe.info.product = id.product;
+    }
+
+    // a failed ioctl fails whatever it was asked for
+    let props = dev.device_properties().ok();
+    let abs = dev.absolute_bits().ok();
+    let keys = dev.key_bits().ok();
+    for check in Check::ALL {
+        let passed = match check {
+            Check::PointerProp => props
+                .as_ref()
+                .is_some_and(|p| p.get(InputProperty::Pointer)),
+            Check::MtPositionX => abs
+                .as_ref()
+                .is_some_and(|a| a.get(AbsoluteAxis::MultitouchPositionX)),
+            Check::MtPositionY => abs
+                .as_ref()
+                .is_some_and(|a| a.get(AbsoluteAxis::MultitouchPositionY)),
+            Check::BtnToolFinger => keys.as_ref().is_some_and(|k| k.get(Key::ButtonToolFinger)),
+            // Never proxy our own synthetic clone: it passes every check
+            // above by design (it impersonates the real device), and is
+            // recognizable only by the phys marker stamped on it.
+            Check::NotOurClone => !probe.info.phys.starts_with(CLONE_PHYS_MARKER),
+        };
+        probe.checks.push((check, passed));
+    }
+    let has_slots = abs
+        .as_ref()
+        .is_some_and(|a| a.get(AbsoluteAxis::MultitouchSlot));
+    probe.fingers = Some(Fingers {
+        protocol_a: !has_slots,
+        slots: dev
+            .absolute_info(AbsoluteAxis::MultitouchSlot)
+            .ok()
+            .filter(|_| has_slots)
+            .map_or(0, |i| (i.maximum.max(0) as usize) + 1),
+        semi_mt: props
+            .as_ref()
+            .is_some_and(|p| p.get(InputProperty::SemiMultiTouch)),
+        tool_fingers: [
+            (Key::ButtonToolFinger, 1),
+            (Key::ButtonToolDoubleTap, 2),
+            (Key::ButtonToolTripleTap, 3),
+            (Key::ButtonToolQuadtap, 4),
+            (Key::ButtonToolQuintTap, 5),
+        ]
+        .into_iter()
+        .filter(|&(key, _)| keys.as_ref().is_some_and(|k| k.get(key)))
+        .map(|(_, n)| n)
+        .max()
+        .unwrap_or(0),
+    });
+    probe
+}
+
+/// Probes every `/dev/input/event*` device, in path order.
+pub fn probe_all() -> Result<Vec<Probe>, Error> {
+    let mut entries: Vec<_> = std::fs::read_dir("/dev/input")
+        .map_err(|e| Error::new(e.kind(), format!("cannot list /dev/input: {e}")))?
         .filter_map(|e| e.ok())
         .filter(|e| e.file_name().to_string_lossy().starts_with("event"))
         .map(|e| e.path())
         .collect();
     entries.sort();
+    Ok(entries.into_iter().map(probe).collect())
+}
 
-    for path in entries {
-        let file = match OpenOptions::new()
-            .read(true)
-            .custom_flags(O_NONBLOCK)
-            .open(&path)
-        {
-            Ok(f) => f,
-            Err(e) if e.kind() == ErrorKind::PermissionDenied => {
-                denied += 1;
-                continue;
-            }
-            Err(_) => continue,
-        };
-        inspected += 1;
-        let dev = EvdevHandle::new(file);
+/// Finds every real multitouch touchpad that `filter` selects, returning
+/// `/dev/input/eventN` paths, most preferred first. The caller opens
+/// these directly so it can exclusively grab and proxy the raw event
+/// stream.
+pub fn find_real_trackpads(filter: &DeviceFilter) -> Result<Vec<String>, Error> {
+    let mut found = Vec::new();
+    let mut unselected = 0usize;
+    let mut denied = 0usize;
+    let mut inspected = 0usize;
 
-        let Ok(props) = dev.device_properties() else {
+    for probe in probe_all()? {
+        if probe.denied() {
+            denied += 1;
             continue;
-        };
-        if !props.get(InputProperty::Pointer) {
-            continue; // touchscreens, keyboards, mice without the prop
         }
-        let Ok(abs) = dev.absolute_bits() else {
+        if probe.error.is_some() {
             continue;
-        };
-        if !(abs.get(AbsoluteAxis::MultitouchSlot)
-            && abs.get(AbsoluteAxis::MultitouchPositionX)
-            && abs.get(AbsoluteAxis::MultitouchPositionY))
-        {
-            continue; // pointer device without multitouch (plain mouse)
         }
-        let Ok(keys) = dev.key_bits() else { continue };
-        if !keys.get(Key::ButtonToolFinger) {
+        inspected += 1;
+        if !probe.is_touchpad() {
+            if probe.checks.contains(&(Check::NotOurClone, false)) {
+                debug!(
+                    "Skipping our own synthetic clone at {}.",
+                    probe.info.path.display()
+                );
+            }
             continue;
         }
-
-        // Never proxy our own synthetic clone: it passes every check
-        // above by design (it impersonates the real device), and is
-        // recognizable only by the phys marker stamped on it.
-        if let Ok(phys) = dev.physical_location() {
-            if phys.starts_with(CLONE_PHYS_MARKER.as_bytes()) {
-                debug!("Skipping our own synthetic clone at {}.", path.display());
-                continue;
-            }
+        let fingers = probe.fingers.unwrap_or_default();
+        let info = probe.info;
+        let Some(rank) = filter.rank(&info) else {
+            info!(
+                "Touchpad found: \"{}\" at {}, but not selected ({filter}).",
+                info.name,
+                info.path.display()
+            );
+            unselected += 1;
+            continue;
+        };
+        info!(
+            "Touchpad found: \"{}\" at {} ({fingers}).",
+            info.name,
+            info.path.display()
+        );
+        if !fingers.counts_three() {
+            warn!(
+                "\"{}\" can only tell {} finger(s) apart (no 3rd slot and no \
+                BTN_TOOL_TRIPLETAP), so 3-finger drags can't be recognized on it.",
+                info.name,
+                fingers.max()
+            );
         }
-
-        let name = dev
-            .device_name()
-            .map(|n| {
-                String::from_utf8_lossy(&n)
-                    .trim_end_matches('\0')
-                    .to_string()
-            })
-            .unwrap_or_else(|_| "<unknown>".to_string());
-        info!("Touchpad found: \"{}\" at {}.", name, path.display());
-        found.push(path.to_string_lossy().into_owned());
+        found.push((rank, info.path.to_string_lossy().into_owned()));
     }
 
     if !found.is_empty() {
-        return Ok(found);
+        // stable: equally preferred touchpads stay in /dev/input order
+        found.sort_by_key(|(rank, _)| *rank);
+        return Ok(found.into_iter().map(|(_, path)| path).collect());
+    }
+
+    if unselected > 0 {
+        error!(
+            "{unselected} touchpad(s) found, but none matches the device \
+            selection ({filter}). Check `devices` in the config file and \
+            any --device/--exclude arguments."
+        );
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            "no touchpad matches the device selection",
+        ));
     }
 
     // Nothing found: produce the most useful error we can.
@@ -124,7 +360,8 @@ pub fn find_real_trackpads() -> Result<Vec<String>, Error> {
         devices ({} unreadable). If this machine really has a touchpad, \
         please open an issue at \
         https://github.com/lmr97/linux-3-finger-drag/issues and include \
-        the output of `libinput list-devices`.",
+        the output of `linux-3-finger-drag list-devices --json`, which \
+        shows the checks each device failed.",
         inspected, denied
     );
     Err(Error::new(
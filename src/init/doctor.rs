@@ -0,0 +1,455 @@
+//! `doctor`: diagnose the installation, one check at a time.
+//!
+//! Nearly every "it doesn't work" comes down to setup rather than the
+//! gesture logic: the `input` group added but not yet in effect, the
+//! uinput module or its udev rule missing, a leftover `61-hotplug.rules`
+//! still carrying its `<username here>` placeholder, the service never
+//! enabled, another program holding an exclusive grab on the touchpad.
+//! Each check here says pass or fail and, when it fails, what to run
+//! about it. Nothing is changed; the only side effects are a moment's
+//! open of `/dev/uinput` and a grab of the touchpad, released at once.
+
+use std::fmt::Write;
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use input_linux::EvdevHandle;
+use libc::O_NONBLOCK;
+
+use super::discovery;
+use super::selector::DeviceFilter;
+use crate::runtime::instance;
+
+/// Where udev rules are read from, highest precedence first.
+pub const RULES_DIRS: [&str; 4] = [
+    "/etc/udev/rules.d",
+    "/run/udev/rules.d",
+    "/usr/lib/udev/rules.d",
+    "/lib/udev/rules.d",
+];
+const SERVICE: &str = "three-finger-drag.service";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Works, but not as intended.
+    Warn,
+    Fail,
+    /// Couldn't be checked (e.g. no systemd).
+    Skip,
+}
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub check: &'static str,
+    pub status: Status,
+    pub detail: String,
+    /// What to do about it, when it isn't a pass.
+    pub fix: Option<String>,
+}
+
+impl Finding {
+    fn pass(check: &'static str, detail: impl Into<String>) -> Self {
+        Finding {
+            check,
+            status: Status::Pass,
+            detail: detail.into(),
+            fix: None,
+        }
+    }
+
+    fn fail(check: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Finding {
+            check,
+            status: Status::Fail,
+            detail: detail.into(),
+            fix: Some(fix.into()),
+        }
+    }
+
+    fn warn(check: &'static str, detail: impl Into<String>, fix: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Warn,
+            ..Finding::fail(check, detail, fix)
+        }
+    }
+
+    fn skip(check: &'static str, detail: impl Into<String>) -> Self {
+        Finding {
+            status: Status::Skip,
+            ..Finding::pass(check, detail)
+        }
+    }
+}
+
+pub struct Report {
+    pub findings: Vec<Finding>,
+}
+
+impl Report {
+    pub fn ok(&self) -> bool {
+        self.findings.iter().all(|f| f.status != Status::Fail)
+    }
+
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        for f in &self.findings {
+            let tag = match f.status {
+                Status::Pass => "pass",
+                Status::Warn => "WARN",
+                Status::Fail => "FAIL",
+                Status::Skip => "skip",
+            };
+            let _ = writeln!(s, "[{tag}] {}: {}", f.check, f.detail);
+            if let Some(fix) = &f.fix {
+                let _ = writeln!(s, "       fix: {fix}");
+            }
+        }
+        let count = |status| self.findings.iter().filter(|f| f.status == status).count();
+        let _ = writeln!(
+            s,
+            "\n{} passed, {} warning(s), {} failed",
+            count(Status::Pass),
+            count(Status::Warn),
+            count(Status::Fail)
+        );
+        s
+    }
+}
+
+/// Runs every check, against the touchpad `filter` would pick.
+pub fn diagnose(filter: &DeviceFilter) -> Report {
+    let mut findings = vec![
+        input_group(
+            &std::fs::read_to_string("/etc/group").unwrap_or_default(),
+            &user_name(),
+            &process_groups(),
+        ),
+        uinput_module(
+            Path::new("/dev/uinput").exists() || Path::new("/sys/class/misc/uinput").exists(),
+        ),
+    ];
+    let rules: Vec<PathBuf> = RULES_DIRS.iter().map(PathBuf::from).collect();
+    findings.extend(udev_rules(&rules));
+    findings.push(uinput_open(
+        OpenOptions::new()
+            .write(true)
+            .custom_flags(O_NONBLOCK)
+            .open("/dev/uinput")
+            .map(drop),
+    ));
+    findings.extend(service());
+    findings.extend(touchpad(filter));
+    Report { findings }
+}
+
+fn user_name() -> String {
+    let pw = unsafe { libc::getpwuid(libc::getuid()) };
+    if pw.is_null() {
+        return std::env::var("USER").unwrap_or_default();
+    }
+    unsafe { std::ffi::CStr::from_ptr((*pw).pw_name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// The groups this process runs with: what's actually in effect, as
+/// opposed to what `/etc/group` says for the next login.
+fn process_groups() -> Vec<libc::gid_t> {
+    let n = unsafe { libc::getgroups(0, std::ptr::null_mut()) };
+    let mut groups = vec![0; n.max(0) as usize];
+    let n = unsafe { libc::getgroups(groups.len() as libc::c_int, groups.as_mut_ptr()) };
+    groups.truncate(n.max(0) as usize);
+    groups.push(unsafe { libc::getegid() });
+    groups
+}
+
+/// Whether `user` is in the `input` group, per `group_file` (the text of
+/// `/etc/group`) and the gids actually in effect.
+pub fn input_group(group_file: &str, user: &str, in_effect: &[libc::gid_t]) -> Finding {
+    const CHECK: &str = "input group";
+    let add = format!(
+        "sudo gpasswd --add {} input, then log out and back in",
+        if user.is_empty() { "$USER" } else { user }
+    );
+    let Some((gid, members)) = group_file.lines().find_map(|line| {
+        let mut fields = line.split(':');
+        if fields.next()? != "input" {
+            return None;
+        }
+        let gid = fields.nth(1)?.parse::<libc::gid_t>().ok()?;
+        let members: Vec<&str> = fields.next().unwrap_or("").split(',').collect();
+        Some((gid, members))
+    }) else {
+        return Finding::fail(
+            CHECK,
+            "there is no 'input' group in /etc/group",
+            "check your distribution's documentation for which group owns /dev/input",
+        );
+    };
+    if in_effect.contains(&gid) {
+        Finding::pass(CHECK, "this session is in group 'input'")
+    } else if members.contains(&user) {
+        Finding::fail(
+            CHECK,
+            format!("{user} was added to 'input', but this session started before that"),
+            "log out and back in (or reboot): group changes only apply to new logins",
+        )
+    } else {
+        Finding::fail(
+            CHECK,
+            format!("{user} is not in group 'input', so /dev/input can't be read"),
+            add,
+        )
+    }
+}
+
+pub fn uinput_module(present: bool) -> Finding {
+    const CHECK: &str = "uinput module";
+    if present {
+        Finding::pass(CHECK, "loaded (/dev/uinput exists)")
+    } else {
+        Finding::fail(
+            CHECK,
+            "not loaded: /dev/uinput doesn't exist",
+            "sudo modprobe uinput, and load it at boot with: \
+            echo uinput | sudo tee /etc/modules-load.d/uinput.conf",
+        )
+    }
+}
+
+/// The udev side, from every rules file in `dirs`: a rule giving the
+/// `input` group `/dev/uinput`, and no stale hotplug rule.
+pub fn udev_rules(dirs: &[PathBuf]) -> Vec<Finding> {
+    let mut files: Vec<(PathBuf, String)> = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|ext| ext == "rules"))
+            .collect();
+        paths.sort();
+        for path in paths {
+            // an earlier directory's file of the same name overrides it
+            if files.iter().any(|(p, _)| p.file_name() == path.file_name()) {
+                continue;
+            }
+            if let Ok(text) = std::fs::read_to_string(&path) {
+                files.push((path, text));
+            }
+        }
+    }
+
+    let mut findings = Vec::new();
+    let uinput_rule = files.iter().find(|(_, text)| {
+        text.lines().any(|line| {
+            !line.trim_start().starts_with('#')
+                && line.contains("KERNEL==\"uinput\"")
+                && (line.contains("GROUP=\"input\"") || line.contains("uaccess"))
+        })
+    });
+    findings.push(match uinput_rule {
+        Some((path, _)) => Finding::pass("uinput udev rule", path.display().to_string()),
+        None => Finding::fail(
+            "uinput udev rule",
+            "no rule gives the 'input' group access to /dev/uinput",
+            "sudo cp 60-uinput.rules /etc/udev/rules.d/ (from the repo), then \
+            sudo udevadm control --reload && sudo udevadm trigger",
+        ),
+    });
+
+    let hotplug: Vec<&(PathBuf, String)> = files
+        .iter()
+        .filter(|(_, text)| text.contains(SERVICE) && text.contains("restart"))
+        .collect();
+    if hotplug.is_empty() {
+        findings.push(Finding::pass("hotplug rule", "none (not needed)"));
+    }
+    for (path, text) in hotplug {
+        let remove = format!(
+            "sudo rm {}; the program follows the touchpad across \
+            re-enumeration by itself, so the rule isn't needed",
+            path.display()
+        );
+        let placeholder = text.lines().any(|line| {
+            !line.trim_start().starts_with('#')
+                && (line.contains("<username here>") || line.contains("<username_here>"))
+        });
+        if placeholder {
+            findings.push(Finding::fail(
+                "hotplug rule",
+                format!(
+                    "{} still has the `<username here>` placeholder, so \
+                    every input hotplug runs a failing command",
+                    path.display()
+                ),
+                remove,
+            ));
+        } else {
+            findings.push(Finding::warn(
+                "hotplug rule",
+                format!(
+                    "{} restarts the service on every input hotplug",
+                    path.display()
+                ),
+                remove,
+            ));
+        }
+    }
+    findings
+}
+
+/// What opening `/dev/uinput` for writing came to.
+pub fn uinput_open(result: io::Result<()>) -> Finding {
+    const CHECK: &str = "open /dev/uinput";
+    match result {
+        Ok(()) => Finding::pass(CHECK, "writable: the virtual devices can be created"),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Finding::fail(
+            CHECK,
+            "permission denied",
+            "install the uinput udev rule and join the 'input' group (above), \
+            then log out and back in",
+        ),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Finding::fail(
+            CHECK,
+            "/dev/uinput doesn't exist",
+            "load the uinput module (above)",
+        ),
+        Err(e) => Finding::fail(
+            CHECK,
+            e.to_string(),
+            "see the uinput checks above; please open an issue if they pass",
+        ),
+    }
+}
+
+/// The systemd user unit: enabled, and running.
+fn service() -> Vec<Finding> {
+    let systemctl = |verb: &str| {
+        Command::new("systemctl")
+            .args(["--user", verb, SERVICE])
+            .output()
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_string())
+    };
+    let enabled = match systemctl("is-enabled") {
+        Ok(state) => state,
+        Err(e) => {
+            return vec![Finding::skip(
+                "service",
+                format!("can't run systemctl ({e}); start the program with your init system"),
+            )]
+        }
+    };
+    let mut findings = vec![match enabled.as_str() {
+        "enabled" | "enabled-runtime" | "linked" => Finding::pass("service enabled", enabled),
+        "" | "not-found" => {
+            return vec![Finding::fail(
+                "service enabled",
+                format!("{SERVICE} is not installed"),
+                format!(
+                    "cp {SERVICE} ~/.config/systemd/user/ (from the repo), then \
+                    systemctl --user enable --now {SERVICE}"
+                ),
+            )]
+        }
+        state => Finding::fail(
+            "service enabled",
+            format!("{SERVICE} is {state}"),
+            format!("systemctl --user enable --now {SERVICE}"),
+        ),
+    }];
+    let active = systemctl("is-active").unwrap_or_default();
+    findings.push(if active == "active" {
+        Finding::pass("service running", active)
+    } else {
+        Finding::warn(
+            "service running",
+            format!(
+                "{SERVICE} is {}",
+                if active.is_empty() {
+                    "unknown"
+                } else {
+                    &active
+                }
+            ),
+            format!(
+                "systemctl --user start {SERVICE}; if it won't stay up, see \
+                journalctl --user -u {SERVICE} -e"
+            ),
+        )
+    });
+    findings
+}
+
+/// A touchpad to proxy, and whether anything else has it grabbed.
+fn touchpad(filter: &DeviceFilter) -> Vec<Finding> {
+    let probes = match discovery::probe_all() {
+        Ok(probes) => probes,
+        Err(e) => return vec![Finding::fail("touchpad", e.to_string(), "check /dev/input")],
+    };
+    let Some(pad) = probes
+        .iter()
+        .filter(|p| p.is_touchpad())
+        .filter_map(|p| filter.rank(&p.info).map(|rank| (rank, p)))
+        .min_by_key(|(rank, _)| *rank)
+        .map(|(_, p)| p)
+    else {
+        return vec![Finding::fail(
+            "touchpad",
+            "no touchpad found (or selected)",
+            "run linux-3-finger-drag list-devices to see which check each device failed",
+        )];
+    };
+    let path = pad.info.path.display().to_string();
+    let mut findings = vec![Finding::pass(
+        "touchpad",
+        format!("\"{}\" at {path}", pad.info.name),
+    )];
+
+    const GRAB: &str = "exclusive grab";
+    let dev = match OpenOptions::new()
+        .read(true)
+        .custom_flags(O_NONBLOCK)
+        .open(&pad.info.path)
+    {
+        Ok(file) => EvdevHandle::new(file),
+        Err(e) => {
+            findings.push(Finding::skip(GRAB, format!("can't open {path}: {e}")));
+            return findings;
+        }
+    };
+    findings.push(match dev.grab(true) {
+        Ok(()) => {
+            let _ = dev.grab(false);
+            Finding::pass(GRAB, "nothing else holds the touchpad")
+        }
+        Err(e) if e.raw_os_error() == Some(libc::EBUSY) => {
+            match instance::device_key(&path).and_then(|key| instance::holder_of(&key)) {
+                Ok(Some(holder)) => Finding::pass(
+                    GRAB,
+                    format!("held by {} (this program, running)", holder.describe()),
+                ),
+                _ => Finding::fail(
+                    GRAB,
+                    "another program has the touchpad grabbed, so this one can't proxy it",
+                    format!(
+                        "find it with sudo fuser -v {path} (often another \
+                        remapper or gesture daemon, or an older instance of \
+                        this program) and stop it"
+                    ),
+                ),
+            }
+        }
+        Err(e) => Finding::skip(GRAB, format!("grab probe failed: {e}")),
+    });
+    findings
+}
+
+#[cfg(test)]
+mod tests;
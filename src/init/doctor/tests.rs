@@ -0,0 +1,80 @@
+//! `doctor`'s checks, fed made-up system state.
+
+use super::*;
+
+const GROUPS: &str = "root:x:0:\nwheel:x:10:alice\ninput:x:97:alice,bob\n";
+
+#[test]
+fn input_group_tells_not_added_from_not_yet_in_effect() {
+    let f = input_group(GROUPS, "alice", &[10, 97, 1000]);
+    assert_eq!(f.status, Status::Pass, "{f:?}");
+
+    let f = input_group(GROUPS, "bob", &[1000]);
+    assert_eq!(f.status, Status::Fail);
+    assert!(f.fix.unwrap().contains("log out and back in"));
+
+    let f = input_group(GROUPS, "carol", &[1000]);
+    assert_eq!(f.status, Status::Fail);
+    assert!(f.fix.unwrap().contains("gpasswd --add carol input"));
+
+    let f = input_group("root:x:0:\n", "carol", &[0]);
+    assert_eq!(f.status, Status::Fail);
+}
+
+#[test]
+fn udev_rules_need_uinput_and_flag_a_leftover_hotplug_rule() {
+    let root = std::env::temp_dir().join(format!("3fd-doctor-{}", std::process::id()));
+    let (etc, lib) = (root.join("etc"), root.join("lib"));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&etc).unwrap();
+    std::fs::create_dir_all(&lib).unwrap();
+    let dirs = [etc.clone(), lib.clone()];
+
+    let findings = udev_rules(&dirs);
+    assert_eq!(findings[0].status, Status::Fail, "{findings:?}");
+    assert_eq!(findings[1].status, Status::Pass, "{findings:?}");
+
+    let hotplug = include_str!("../../../61-hotplug.rules");
+    std::fs::write(
+        lib.join("60-uinput.rules"),
+        include_str!("../../../60-uinput.rules"),
+    )
+    .unwrap();
+    std::fs::write(etc.join("61-hotplug.rules"), hotplug).unwrap();
+    let findings = udev_rules(&dirs);
+    assert_eq!(findings[0].status, Status::Pass, "{findings:?}");
+    assert_eq!(findings[1].status, Status::Fail, "{findings:?}");
+    assert!(findings[1].detail.contains("<username here>"));
+
+    // substituted, it works -- but isn't needed any more
+    let substituted = hotplug.replace("<username here>", "alice");
+    std::fs::write(etc.join("61-hotplug.rules"), substituted).unwrap();
+    assert_eq!(udev_rules(&dirs)[1].status, Status::Warn);
+
+    // a file in an earlier directory overrides one of the same name
+    std::fs::write(etc.join("60-uinput.rules"), "# disabled\n").unwrap();
+    assert_eq!(udev_rules(&dirs)[0].status, Status::Fail);
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+#[test]
+fn uinput_failures_point_at_the_right_fix() {
+    assert_eq!(uinput_open(Ok(())).status, Status::Pass);
+    let denied = uinput_open(Err(io::ErrorKind::PermissionDenied.into()));
+    assert!(denied.fix.unwrap().contains("udev rule"));
+    let missing = uinput_open(Err(io::ErrorKind::NotFound.into()));
+    assert!(missing.fix.unwrap().contains("module"));
+    assert!(uinput_module(false)
+        .fix
+        .unwrap()
+        .contains("modprobe uinput"));
+
+    let report = Report {
+        findings: vec![uinput_module(true), uinput_module(false)],
+    };
+    assert!(!report.ok());
+    let text = report.render();
+    assert!(text.contains("[FAIL] uinput module"), "{text}");
+    assert!(text.contains("fix: sudo modprobe uinput"), "{text}");
+    assert!(text.contains("1 passed, 0 warning(s), 1 failed"), "{text}");
+}
@@ -0,0 +1,208 @@
+//! `logFile: "journal"`: log straight to journald, in its native protocol.
+//!
+//! Under the shipped systemd unit, stdout already ends up in the
+//! journal -- but as flat text: every line at the same priority, with a
+//! second timestamp of our own in front of journald's. Talking to the
+//! journal socket directly fixes both, and adds what text can't carry:
+//! each event goes out as a set of fields, with `PRIORITY` mapped from
+//! the tracing level and the context as fields of its own
+//! (`DEVICE_PATH`, `TOUCH_ID`, `DECISION`, ...), so e.g.
+//! `journalctl --user -u three-finger-drag -o verbose DECISION=late-4th-abort-after-press`
+//! finds every phantom click.
+//!
+//! The protocol is one datagram per entry: `NAME=value` lines, or, for
+//! values containing a newline, `NAME\n` + 64-bit little-endian length
+//! + the raw value + `\n`.
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// Where journald listens for native-protocol entries.
+pub const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+const IDENTIFIER: &str = "linux-3-finger-drag";
+
+/// Journal field names for the fields this program uses as context; any
+/// other field is just upper-cased.
+const FIELD_NAMES: &[(&str, &str)] = &[
+    ("path", "DEVICE_PATH"),
+    ("touch", "TOUCH_ID"),
+    ("transition", "DECISION"),
+];
+
+fn priority(level: &Level) -> u8 {
+    match *level {
+        Level::ERROR => 3,
+        Level::WARN => 4,
+        Level::INFO => 6,
+        Level::DEBUG | Level::TRACE => 7,
+    }
+}
+
+/// A valid journal field name for tracing field `name`: upper-case
+/// letters, digits and `_`, not starting with `_` (reserved for trusted
+/// fields) or a digit.
+fn field_name(name: &str) -> String {
+    if let Some((_, mapped)) = FIELD_NAMES.iter().find(|(from, _)| *from == name) {
+        return mapped.to_string();
+    }
+    let mut out: String = name
+        .chars()
+        .map(|c| match c.to_ascii_uppercase() {
+            c @ ('A'..='Z' | '0'..='9') => c,
+            _ => '_',
+        })
+        .collect::<String>()
+        .trim_start_matches('_')
+        .to_string();
+    if out.is_empty() || out.starts_with(|c: char| c.is_ascii_digit()) {
+        out.insert_str(0, "F_");
+    }
+    out
+}
+
+fn put(entry: &mut Vec<u8>, name: &str, value: &str) {
+    entry.extend_from_slice(name.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+/// Collects an event's or span's fields as `(journal name, value)`.
+#[derive(Default)]
+struct Fields {
+    message: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record(field, format!("{value:?}"));
+    }
+}
+
+impl Fields {
+    fn record(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+            return;
+        }
+        let name = field_name(field.name());
+        match self.fields.iter_mut().find(|(n, _)| *n == name) {
+            Some(existing) => existing.1 = value,
+            None => self.fields.push((name, value)),
+        }
+    }
+}
+
+pub struct JournalLayer {
+    socket: UnixDatagram,
+    path: PathBuf,
+    enabled: Arc<AtomicBool>,
+}
+
+impl JournalLayer {
+    /// A layer sending to the journal socket at `path`. It sends only
+    /// while `enabled` is set, so it can be switched on and off by a
+    /// config reload.
+    pub fn new(path: &Path, enabled: Arc<AtomicBool>) -> io::Result<Self> {
+        Ok(JournalLayer {
+            socket: UnixDatagram::unbound()?,
+            path: path.to_path_buf(),
+            enabled,
+        })
+    }
+
+    fn send(&self, entry: &[u8]) {
+        // nowhere to report a failure to but stderr (which systemd also
+        // puts in the journal)
+        if let Err(e) = self.socket.send_to(entry, &self.path) {
+            eprintln!(
+                "Could not log to the journal at {}: {e}",
+                self.path.display()
+            );
+        }
+    }
+}
+
+impl<S> Layer<S> for JournalLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+                values.record(fields);
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if !self.enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let meta = event.metadata();
+        let mut entry = Vec::with_capacity(256);
+        put(&mut entry, "PRIORITY", &priority(meta.level()).to_string());
+        put(&mut entry, "SYSLOG_IDENTIFIER", IDENTIFIER);
+        put(&mut entry, "TARGET", meta.target());
+        if let Some(file) = meta.file() {
+            put(&mut entry, "CODE_FILE", file);
+        }
+        if let Some(line) = meta.line() {
+            put(&mut entry, "CODE_LINE", &line.to_string());
+        }
+
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        // context from the enclosing spans, outermost first, so the
+        // event's own fields (sent last) win on a clash
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                if let Some(span_fields) = span.extensions().get::<Fields>() {
+                    for (name, value) in &span_fields.fields {
+                        if !fields.fields.iter().any(|(n, _)| n == name) {
+                            put(&mut entry, name, value);
+                        }
+                    }
+                }
+            }
+        }
+        for (name, value) in &fields.fields {
+            put(&mut entry, name, value);
+        }
+        let message = fields.message.unwrap_or_else(|| meta.name().to_string());
+        put(&mut entry, "MESSAGE", &message);
+        self.send(&entry);
+    }
+}
+
+#[cfg(test)]
+mod tests;
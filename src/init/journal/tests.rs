@@ -0,0 +1,100 @@
+//! The journal layer against a local datagram socket standing in for
+//! journald.
+
+use super::*;
+use tracing_subscriber::prelude::*;
+
+/// Parses one native-protocol datagram into `(name, value)` pairs.
+fn parse(mut entry: &[u8]) -> Vec<(String, String)> {
+    let mut fields = Vec::new();
+    while !entry.is_empty() {
+        let end = entry.iter().position(|&b| b == b'\n' || b == b'=').unwrap();
+        let name = String::from_utf8(entry[..end].to_vec()).unwrap();
+        if entry[end] == b'=' {
+            let rest = &entry[end + 1..];
+            let nl = rest.iter().position(|&b| b == b'\n').unwrap();
+            fields.push((name, String::from_utf8(rest[..nl].to_vec()).unwrap()));
+            entry = &rest[nl + 1..];
+        } else {
+            let len = u64::from_le_bytes(entry[end + 1..end + 9].try_into().unwrap()) as usize;
+            let value = &entry[end + 9..end + 9 + len];
+            fields.push((name, String::from_utf8(value.to_vec()).unwrap()));
+            entry = &entry[end + 9 + len + 1..];
+        }
+    }
+    fields
+}
+
+fn get<'a>(fields: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// Runs `f` with a journal layer pointed at a fresh local socket, and
+/// returns every entry it sent.
+fn capture(enabled: bool, f: impl FnOnce()) -> Vec<Vec<(String, String)>> {
+    let path =
+        std::env::temp_dir().join(format!("3fd-journal-{}-{enabled}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = UnixDatagram::bind(&path).unwrap();
+    server.set_nonblocking(true).unwrap();
+
+    let layer = JournalLayer::new(&path, Arc::new(AtomicBool::new(enabled))).unwrap();
+    tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), f);
+
+    let mut entries = Vec::new();
+    let mut buf = vec![0u8; 65536];
+    while let Ok(n) = server.recv(&mut buf) {
+        entries.push(parse(&buf[..n]));
+    }
+    let _ = std::fs::remove_file(&path);
+    entries
+}
+
+#[test]
+fn entries_carry_priority_message_and_context_fields() {
+    let entries = capture(true, || {
+        let device = tracing::info_span!("device", path = "/dev/input/event7");
+        let _in_device = device.enter();
+        tracing::info!("grabbed");
+        let touch = tracing::info_span!("touch", touch = 12u64);
+        tracing::warn!(parent: &touch, transition = "late-4th-abort-after-press", "phantom click");
+    });
+    assert_eq!(entries.len(), 2);
+
+    let grabbed = &entries[0];
+    assert_eq!(get(grabbed, "PRIORITY"), Some("6"));
+    assert_eq!(get(grabbed, "MESSAGE"), Some("grabbed"));
+    assert_eq!(
+        get(grabbed, "SYSLOG_IDENTIFIER"),
+        Some("linux-3-finger-drag")
+    );
+    assert_eq!(get(grabbed, "DEVICE_PATH"), Some("/dev/input/event7"));
+
+    let abort = &entries[1];
+    assert_eq!(get(abort, "PRIORITY"), Some("4"));
+    assert_eq!(get(abort, "TOUCH_ID"), Some("12"));
+    assert_eq!(get(abort, "DECISION"), Some("late-4th-abort-after-press"));
+    assert_eq!(get(abort, "DEVICE_PATH"), Some("/dev/input/event7"));
+}
+
+#[test]
+fn multi_line_messages_use_the_length_prefixed_form() {
+    let entries = capture(true, || tracing::debug!("Metrics:\ntouches: 3"));
+    assert_eq!(get(&entries[0], "MESSAGE"), Some("Metrics:\ntouches: 3"));
+    assert_eq!(get(&entries[0], "PRIORITY"), Some("7"));
+}
+
+#[test]
+fn nothing_is_sent_while_disabled() {
+    assert!(capture(false, || tracing::error!("not for the journal")).is_empty());
+}
+
+#[test]
+fn field_names_are_valid_for_the_journal() {
+    assert_eq!(field_name("relay.latency"), "RELAY_LATENCY");
+    assert_eq!(field_name("_private"), "PRIVATE");
+    assert_eq!(field_name("9lives"), "F_9LIVES");
+}
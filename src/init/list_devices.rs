@@ -0,0 +1,140 @@
+//! `list-devices`: what discovery makes of every input device.
+//!
+//! When discovery comes up empty (or picks the wrong touchpad), its log
+//! line says how many devices it looked at, not why each was turned
+//! down. This runs the same probe over every `/dev/input/event*` node
+//! and shows each check it passed or failed, which touchpads the device
+//! selection lets through, and which one the daemon would proxy -- as
+//! text, or as JSON to paste into a bug report.
+
+use std::fmt::Write;
+use std::io;
+
+use serde::Serialize;
+
+use super::discovery::{self, Probe};
+use super::selector::DeviceFilter;
+
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    #[serde(flatten)]
+    pub probe: Probe,
+    /// Passed every check.
+    pub touchpad: bool,
+    /// A touchpad the device selection lets through.
+    pub selected: bool,
+    /// The one the daemon would proxy.
+    pub proxied: bool,
+}
+
+/// Every input device, as discovery sees it under `filter`.
+pub fn list(filter: &DeviceFilter) -> io::Result<Vec<Entry>> {
+    Ok(classify(discovery::probe_all()?, filter))
+}
+
+/// Works out the verdicts for `probes` (in `/dev/input` order).
+pub fn classify(probes: Vec<Probe>, filter: &DeviceFilter) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = probes
+        .into_iter()
+        .map(|probe| {
+            let touchpad = probe.is_touchpad();
+            Entry {
+                selected: touchpad && filter.rank(&probe.info).is_some(),
+                probe,
+                touchpad,
+                proxied: false,
+            }
+        })
+        .collect();
+    // the same pick as discovery: best rank, then first in path order
+    if let Some(first) = entries
+        .iter_mut()
+        .filter(|e| e.selected)
+        .min_by_key(|e| filter.rank(&e.probe.info))
+    {
+        first.proxied = true;
+    }
+    entries
+}
+
+pub fn render_json(entries: &[Entry]) -> String {
+    serde_json::to_string_pretty(entries).unwrap_or_default() + "\n"
+}
+
+pub fn render_text(entries: &[Entry], filter: &DeviceFilter) -> String {
+    let mut s = String::new();
+    for e in entries {
+        let info = &e.probe.info;
+        let _ = writeln!(s, "{}", info.path.display());
+        if let Some(err) = &e.probe.error {
+            let _ = writeln!(s, "  cannot open: {err}\n");
+            continue;
+        }
+        let _ = writeln!(s, "  name:  \"{}\"", info.name);
+        let _ = writeln!(s, "  id:    {:04x}:{:04x}", info.vendor, info.product);
+        let _ = writeln!(
+            s,
+            "  phys:  {}",
+            if info.phys.is_empty() {
+                "-"
+            } else {
+                &info.phys
+            }
+        );
+        if let Some(fingers) = e.probe.fingers {
+            let _ = writeln!(
+                s,
+                "  fingers: {fingers}{}",
+                if fingers.counts_three() {
+                    ""
+                } else {
+                    " -- too few for a 3-finger drag"
+                }
+            );
+        }
+        for &(check, passed) in &e.probe.checks {
+            let _ = writeln!(
+                s,
+                "  {}  {}",
+                if passed { "pass" } else { "FAIL" },
+                check.label()
+            );
+        }
+        let verdict = match (e.touchpad, e.selected, e.proxied) {
+            (false, _, _) => "not a touchpad",
+            (true, false, _) => "touchpad, not selected",
+            (true, true, false) => "touchpad, selected (another is proxied first)",
+            (true, true, true) => "touchpad, proxied",
+        };
+        let _ = writeln!(s, "  => {verdict}\n");
+    }
+
+    let touchpads = entries.iter().filter(|e| e.touchpad).count();
+    let denied = entries.iter().filter(|e| e.probe.denied()).count();
+    let _ = writeln!(
+        s,
+        "{} device(s), {} unreadable, {} touchpad(s); device selection: {filter}",
+        entries.len(),
+        denied,
+        touchpads
+    );
+    match entries.iter().find(|e| e.proxied) {
+        Some(e) => {
+            let _ = writeln!(s, "Would proxy {}.", e.probe.info.path.display());
+        }
+        None => {
+            let _ = writeln!(s, "No touchpad would be proxied.");
+        }
+    }
+    if denied > 0 {
+        let _ = writeln!(
+            s,
+            "Unreadable devices can't be checked: is this user in the \
+            'input' group (and logged in again since being added)?"
+        );
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,163 @@
+//! `list-devices`: the verdict per device, and both renderings.
+
+use std::path::PathBuf;
+
+use super::*;
+use crate::init::discovery::{Check, Fingers};
+use crate::init::selector::DeviceInfo;
+
+fn device(node: u32, name: &str, failing: &[Check]) -> Probe {
+    Probe {
+        info: DeviceInfo {
+            path: PathBuf::from(format!("/dev/input/event{node}")),
+            name: name.to_string(),
+            phys: format!("i2c-{node}"),
+            vendor: 0x06cb,
+            product: 0xcd8b,
+        },
+        error: None,
+        checks: Check::ALL
+            .iter()
+            .map(|&c| (c, !failing.contains(&c)))
+            .collect(),
+        fingers: Some(Fingers {
+            slots: 5,
+            protocol_a: false,
+            semi_mt: false,
+            tool_fingers: 5,
+        }),
+    }
+}
+
+fn denied(node: u32) -> Probe {
+    Probe {
+        info: DeviceInfo {
+            path: PathBuf::from(format!("/dev/input/event{node}")),
+            ..Default::default()
+        },
+        error: Some("permission denied".to_string()),
+        checks: Vec::new(),
+        fingers: None,
+    }
+}
+
+fn probes() -> Vec<Probe> {
+    vec![
+        device(0, "Power Button", &[Check::PointerProp, Check::MtPositionX]),
+        denied(1),
+        device(2, "Touchpad A", &[]),
+        device(3, "Touchpad B", &[]),
+        device(4, "Touchpad A", &[Check::NotOurClone]),
+    ]
+}
+
+#[test]
+fn the_first_selected_touchpad_is_the_one_proxied() {
+    let entries = classify(probes(), &DeviceFilter::default());
+    let verdicts: Vec<_> = entries
+        .iter()
+        .map(|e| (e.touchpad, e.selected, e.proxied))
+        .collect();
+    assert_eq!(
+        verdicts,
+        [
+            (false, false, false),
+            (false, false, false),
+            (true, true, true),
+            (true, true, false),
+            (false, false, false), // our own clone
+        ]
+    );
+
+    let prefer_b = DeviceFilter {
+        include: vec!["*B".parse().unwrap(), "*A".parse().unwrap()],
+        exclude: Vec::new(),
+    };
+    let entries = classify(probes(), &prefer_b);
+    assert!(entries[3].proxied && !entries[2].proxied && entries[2].selected);
+}
+
+#[test]
+fn text_shows_every_check_and_json_carries_them_by_name() {
+    let filter = DeviceFilter {
+        include: Vec::new(),
+        exclude: vec!["*B".parse().unwrap()],
+    };
+    let entries = classify(probes(), &filter);
+    let text = render_text(&entries, &filter);
+    assert!(text.contains("FAIL  INPUT_PROP_POINTER"), "{text}");
+    assert!(text.contains("FAIL  not our own clone"), "{text}");
+    assert!(text.contains("cannot open: permission denied"), "{text}");
+    assert!(text.contains("touchpad, not selected"), "{text}");
+    assert!(text.contains("Would proxy /dev/input/event2."), "{text}");
+    assert!(text.contains("'input' group"), "{text}");
+
+    let json: serde_json::Value = serde_json::from_str(&render_json(&entries)).unwrap();
+    let first = &json[0];
+    assert_eq!(first["path"], "/dev/input/event0");
+    assert_eq!(first["vendor"], "06cb");
+    assert_eq!(first["checks"]["pointer-prop"], false);
+    assert_eq!(first["checks"]["btn-tool-finger"], true);
+    assert_eq!(json[1]["error"], "permission denied");
+    assert_eq!(json[2]["proxied"], true);
+}
+
+#[test]
+fn finger_counting_is_shown_and_flags_pads_that_cannot_drag() {
+    let mut probes = probes();
+    // an older Synaptics: 2 slots, but counts to 3 by BTN_TOOL_TRIPLETAP
+    probes[2].fingers = Some(Fingers {
+        slots: 2,
+        protocol_a: false,
+        semi_mt: true,
+        tool_fingers: 3,
+    });
+    // 2 slots and nothing past BTN_TOOL_DOUBLETAP
+    probes[3].fingers = Some(Fingers {
+        slots: 2,
+        protocol_a: false,
+        semi_mt: false,
+        tool_fingers: 2,
+    });
+    let entries = classify(probes, &DeviceFilter::default());
+    let text = render_text(&entries, &DeviceFilter::default());
+    assert!(
+        text.contains("fingers: 2 slot(s), semi-MT, counts up to 3 by BTN_TOOL_*\n"),
+        "{text}"
+    );
+    assert!(
+        text.contains("fingers: 2 slot(s) -- too few for a 3-finger drag"),
+        "{text}"
+    );
+    assert!(
+        entries[3].touchpad,
+        "still a touchpad, just not a useful one"
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&render_json(&entries)).unwrap();
+    assert_eq!(json[2]["fingers"]["slots"], 2);
+    assert_eq!(json[2]["fingers"]["semi-mt"], true);
+    assert_eq!(json[2]["fingers"]["tool-fingers"], 3);
+    assert!(json[1]["fingers"].is_null());
+}
+
+#[test]
+fn a_protocol_a_pad_is_a_touchpad_that_can_drag() {
+    let mut probes = probes();
+    probes[2].fingers = Some(Fingers {
+        slots: 0,
+        protocol_a: true,
+        semi_mt: false,
+        tool_fingers: 2,
+    });
+    let entries = classify(probes, &DeviceFilter::default());
+    assert!(entries[2].proxied);
+    let text = render_text(&entries, &DeviceFilter::default());
+    assert!(
+        text.contains("fingers: MT protocol A, contacts tracked into slots\n"),
+        "{text}"
+    );
+    let json: serde_json::Value = serde_json::from_str(&render_json(&entries)).unwrap();
+    assert_eq!(json[2]["fingers"]["protocol-a"], true);
+    assert!(json[2]["checks"].get("mt-slot").is_none());
+}
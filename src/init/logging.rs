@@ -0,0 +1,228 @@
+//! Logging setup: the main log (stdout or `logFile`, at `logLevel`) plus,
+//! optionally, the JSON-lines decision trace (`traceFile`). The main log
+//! can also go to journald natively (`logFile: "journal"`, see
+//! `journal.rs`).
+//!
+//! The two are independent layers on one subscriber. The trace layer
+//! only ever sees the gesture machine's structured transition events
+//! (target [`TRACE_TARGET`]), and sees them whatever `logLevel` says, so
+//! a trace can be captured without drowning the main log in debug
+//! output.
+//!
+//! Both are reconfigurable in place: the filters sit behind reload
+//! layers, and the files behind a shared [`Sink`] that can be swapped
+//! (or simply reopened) while the subscriber keeps running. That is what
+//! lets a config reload change `logLevel`, `logFile` and `traceFile`
+//! without a restart. Files rotate by themselves (see `rotate.rs`).
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tracing::{info, Level};
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::{self, time::ChronoLocal, MakeWriter},
+    prelude::*,
+    reload, Layer, Registry,
+};
+
+use super::config::Configuration;
+use super::journal::{JournalLayer, JOURNAL_SOCKET};
+use super::rotate::{Policy, RotatingFile};
+use crate::runtime::gesture::TRACE_TARGET;
+
+// create(true): a fresh install has no log file yet, and failing to
+// *create* one shouldn't silently demote logging to stdout
+fn open_append(path: &str, what: &str, cfg: &Configuration) -> Option<RotatingFile> {
+    match RotatingFile::open(Path::new(path), Policy::from_config(cfg)) {
+        Ok(file) => Some(file),
+        Err(open_err) => {
+            println!(
+                "[PRE-LOG: WARN]: Failed to open {what} '{}' \
+                due to the the following error: {}, {}.",
+                path,
+                open_err.kind(),
+                open_err
+            );
+            None
+        }
+    }
+}
+
+/// Where a layer's output currently goes.
+enum Sink {
+    Stdout,
+    File(RotatingFile),
+    /// Nowhere (the trace layer, with no `traceFile`).
+    Off,
+}
+
+/// A [`Sink`] shared between a layer (writing) and the [`LogHandle`]
+/// (swapping it out).
+#[derive(Clone)]
+struct SharedSink(Arc<Mutex<Sink>>);
+
+impl SharedSink {
+    fn new(sink: Sink) -> Self {
+        SharedSink(Arc::new(Mutex::new(sink)))
+    }
+
+    fn set(&self, sink: Sink) {
+        *self.0.lock().unwrap_or_else(|e| e.into_inner()) = sink;
+    }
+}
+
+impl Write for SharedSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut *self.0.lock().unwrap_or_else(|e| e.into_inner()) {
+            Sink::Stdout => io::stdout().write(buf),
+            Sink::File(file) => file.write(buf),
+            Sink::Off => Ok(buf.len()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut *self.0.lock().unwrap_or_else(|e| e.into_inner()) {
+            Sink::Stdout => io::stdout().flush(),
+            Sink::File(file) => file.flush(),
+            Sink::Off => Ok(()),
+        }
+    }
+}
+
+impl<'a> MakeWriter<'a> for SharedSink {
+    type Writer = SharedSink;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// The main log's sink for `cfg`, announced the way the program always
+/// has before the logger exists. `Off` when logging to the journal.
+fn main_sink(cfg: &Configuration) -> Sink {
+    let log_level: LevelFilter = cfg.log_level.into();
+    if cfg.log_file == "journal" {
+        if Path::new(JOURNAL_SOCKET).exists() {
+            println!("[PRE-LOG: INFO]: Logging to the journal at {log_level}-level verbosity.");
+            return Sink::Off;
+        }
+        println!("[PRE-LOG: WARN]: No journal socket at {JOURNAL_SOCKET}.");
+    }
+    // If the log file is either "stdout" or can't be opened, log to the
+    // console instead.
+    let log_file = if cfg.log_file == "stdout" || cfg.log_file == "journal" {
+        None
+    } else {
+        open_append(&cfg.log_file, "logfile", cfg)
+    };
+    match log_file {
+        Some(file) => {
+            println!(
+                "[PRE-LOG: INFO]: Logging to '{}' at {}-level verbosity.",
+                cfg.log_file, log_level
+            );
+            Sink::File(file)
+        }
+        None => {
+            if cfg.log_file != "stdout" {
+                println!("[PRE-LOG: WARN]: Logging to stdout at {log_level}-level verbosity.");
+            }
+            Sink::Stdout
+        }
+    }
+}
+
+fn trace_sink(cfg: &Configuration) -> Sink {
+    cfg.trace_file
+        .as_deref()
+        .and_then(|path| {
+            let file = open_append(path, "trace file", cfg)?;
+            println!("[PRE-LOG: INFO]: Writing the decision trace to '{path}' as JSON lines.");
+            Some(Sink::File(file))
+        })
+        .unwrap_or(Sink::Off)
+}
+
+fn trace_filter(sink: &Sink) -> Targets {
+    match sink {
+        // with no trace file, don't even build the trace events
+        Sink::Off => Targets::new(),
+        _ => Targets::new().with_target(TRACE_TARGET, Level::DEBUG),
+    }
+}
+
+/// Keeps the running logger reconfigurable.
+pub struct LogHandle {
+    level: reload::Handle<LevelFilter, Registry>,
+    trace_filter: reload::Handle<Targets, Registry>,
+    main: SharedSink,
+    journal: Arc<AtomicBool>,
+    trace: SharedSink,
+}
+
+impl LogHandle {
+    /// Applies `cfg`'s log settings to the running logger. The files are
+    /// reopened even if their paths didn't change, so a reload also
+    /// picks up a log file that was moved or deleted in the meantime.
+    pub fn reconfigure(&self, cfg: &Configuration) {
+        let log_level: LevelFilter = cfg.log_level.into();
+        let main = main_sink(cfg);
+        self.journal
+            .store(matches!(main, Sink::Off), Ordering::Relaxed);
+        self.main.set(main);
+        let _ = self.level.modify(|level| *level = log_level);
+
+        let trace = trace_sink(cfg);
+        let _ = self.trace_filter.modify(|f| *f = trace_filter(&trace));
+        self.trace.set(trace);
+        info!("Log settings applied: {log_level}-level verbosity.");
+    }
+}
+
+/// Installs the global subscriber. Call once, right after loading the
+/// configuration.
+pub fn init_logger(cfg: &Configuration) -> LogHandle {
+    let log_level: LevelFilter = cfg.log_level.into();
+    let main_initial = main_sink(cfg);
+    let journal = Arc::new(AtomicBool::new(matches!(main_initial, Sink::Off)));
+    let main = SharedSink::new(main_initial);
+    let (level_filter, level) = reload::Layer::new(log_level);
+    let mut main_layers = vec![fmt::layer()
+        .with_writer(main.clone())
+        .with_timer(ChronoLocal::rfc_3339())
+        .boxed()];
+    match JournalLayer::new(Path::new(JOURNAL_SOCKET), journal.clone()) {
+        Ok(layer) => main_layers.push(layer.boxed()),
+        Err(e) => println!("[PRE-LOG: WARN]: Cannot log to the journal: {e}"),
+    }
+    let main_layer = main_layers.with_filter(level_filter).boxed();
+
+    let trace_initial = trace_sink(cfg);
+    let (targets, trace_filter_handle) = reload::Layer::new(trace_filter(&trace_initial));
+    let trace = SharedSink::new(trace_initial);
+    let trace_layer = fmt::layer()
+        .json()
+        .with_writer(trace.clone())
+        .with_timer(ChronoLocal::rfc_3339())
+        .with_current_span(true)
+        .with_span_list(false)
+        .with_filter(targets)
+        .boxed();
+
+    // a Vec, so both layers sit directly on the Registry and their
+    // reload handles share one simple type
+    tracing_subscriber::registry()
+        .with(vec![main_layer, trace_layer])
+        .init();
+
+    LogHandle {
+        level,
+        trace_filter: trace_filter_handle,
+        main,
+        journal,
+        trace,
+    }
+}
@@ -1,2 +1,9 @@
+pub mod check;
 pub mod config;
 pub mod discovery;
+pub mod doctor;
+pub mod journal;
+pub mod list_devices;
+pub mod logging;
+pub mod rotate;
+pub mod selector;
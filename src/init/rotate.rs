@@ -0,0 +1,213 @@
+//! Size- and age-based rotation for `logFile` (and `traceFile`).
+//!
+//! A laptop that runs this for weeks at `debug` would otherwise grow its
+//! log without bound. [`RotatingFile`] is an append-only file that, just
+//! before a write would take it past `logMaxSize` (or once it's older
+//! than `logMaxAge`), renames itself to `<file>.1` -- shifting older
+//! generations up to `logKeep`, and deleting the one after -- and starts
+//! afresh. Rotated files are optionally gzipped, off the event loop.
+//!
+//! It also copes with an external logrotate: if the file at the path is
+//! no longer the one we have open (moved away, or deleted), the path is
+//! reopened, and a `copytruncate`-style truncation is noticed too.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::config::Configuration;
+
+/// How often the path is re-checked for having been moved away: often
+/// enough that nothing much is lost to a rotated-away file, rarely
+/// enough not to add a `stat` to every log line.
+const MOVE_CHECK_EVERY: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// In bytes; 0 means no size limit.
+    pub max_size: u64,
+    pub max_age: Option<Duration>,
+    /// Rotated generations kept (`<file>.1` .. `<file>.<keep>`).
+    pub keep: u32,
+    pub compress: bool,
+}
+
+impl Policy {
+    pub fn from_config(cfg: &Configuration) -> Self {
+        Policy {
+            max_size: cfg.log_max_size.saturating_mul(1024 * 1024),
+            max_age: (cfg.log_max_age > 0).then(|| Duration::from_secs(cfg.log_max_age * 3600)),
+            keep: cfg.log_keep,
+            compress: cfg.log_compress,
+        }
+    }
+}
+
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    born: SystemTime,
+    policy: Policy,
+    last_check: Instant,
+    /// The background gzip of the last `<file>.1`, if it may still be
+    /// running: the next rotation waits for it before shifting `.1`
+    /// out from under it.
+    compressing: Option<JoinHandle<()>>,
+}
+
+fn open_append(path: &Path) -> io::Result<(File, u64, SystemTime)> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let meta = file.metadata()?;
+    let born = if meta.len() == 0 {
+        SystemTime::now()
+    } else {
+        meta.created()
+            .or_else(|_| meta.modified())
+            .unwrap_or_else(|_| SystemTime::now())
+    };
+    Ok((file, meta.len(), born))
+}
+
+/// `<path>.<n>`, plus `.gz` if `gz`.
+fn generation(path: &Path, n: u32, gz: bool) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    if gz {
+        name.push(".gz");
+    }
+    PathBuf::from(name)
+}
+
+/// Gzips `path` to `<path>.gz`, removing the original once the
+/// compressed copy is complete.
+pub fn compress(path: &Path) -> io::Result<()> {
+    let mut gz_path = path.as_os_str().to_owned();
+    gz_path.push(".gz");
+    let mut partial = gz_path.clone();
+    partial.push(".part");
+
+    let mut input = File::open(path)?;
+    let mut encoder =
+        flate2::write::GzEncoder::new(File::create(&partial)?, flate2::Compression::default());
+    io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()?;
+    std::fs::rename(&partial, &gz_path)?;
+    std::fs::remove_file(path)
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, policy: Policy) -> io::Result<Self> {
+        let (file, size, born) = open_append(path)?;
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            born,
+            policy,
+            last_check: Instant::now(),
+            compressing: None,
+        })
+    }
+
+    fn reopen(&mut self) -> io::Result<()> {
+        (self.file, self.size, self.born) = open_append(&self.path)?;
+        Ok(())
+    }
+
+    /// Whether someone else moved, deleted or truncated the file.
+    fn tampered(&self) -> bool {
+        let ours = match self.file.metadata() {
+            Ok(meta) => meta,
+            Err(_) => return true,
+        };
+        match std::fs::metadata(&self.path) {
+            Ok(at_path) => {
+                at_path.ino() != ours.ino() || at_path.dev() != ours.dev() || ours.len() < self.size
+            }
+            Err(_) => true,
+        }
+    }
+
+    fn due(&self, incoming: usize) -> bool {
+        if self.size == 0 {
+            return false;
+        }
+        let too_big =
+            self.policy.max_size > 0 && self.size + incoming as u64 > self.policy.max_size;
+        let too_old = self.policy.max_age.is_some_and(|max| {
+            SystemTime::now()
+                .duration_since(self.born)
+                .is_ok_and(|age| age >= max)
+        });
+        too_big || too_old
+    }
+
+    /// Shifts the generations up by one, moves the current file to
+    /// `<file>.1`, and starts a new one.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        if let Some(compressing) = self.compressing.take() {
+            let _ = compressing.join();
+        }
+        let keep = self.policy.keep;
+        let ignore_missing = |r: io::Result<()>| match r {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+        if keep == 0 {
+            ignore_missing(std::fs::remove_file(&self.path))?;
+        } else {
+            for gz in [false, true] {
+                ignore_missing(std::fs::remove_file(generation(&self.path, keep, gz)))?;
+                for n in (1..keep).rev() {
+                    ignore_missing(std::fs::rename(
+                        generation(&self.path, n, gz),
+                        generation(&self.path, n + 1, gz),
+                    ))?;
+                }
+            }
+            let first = generation(&self.path, 1, false);
+            std::fs::rename(&self.path, &first)?;
+            if self.policy.compress {
+                // a large log takes a moment to compress; not on the
+                // thread that relays touchpad input
+                self.compressing = Some(std::thread::spawn(move || {
+                    if let Err(e) = compress(&first) {
+                        eprintln!("Could not compress {}: {e}", first.display());
+                    }
+                }));
+            }
+        }
+        self.reopen()
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.last_check.elapsed() >= MOVE_CHECK_EVERY {
+            self.last_check = Instant::now();
+            if self.tampered() {
+                self.reopen()?;
+            }
+        }
+        if self.due(buf.len()) {
+            // a failed rotation must not cost the log line itself
+            if let Err(e) = self.rotate() {
+                eprintln!("Could not rotate {}: {e}", self.path.display());
+            }
+        }
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,117 @@
+//! Rotation: generations shift and are capped, compression completes,
+//! and an externally rotated file is let go of.
+
+use super::*;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("3fd-rotate-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("3fd.log")
+}
+
+fn policy(max_size: u64, keep: u32, compress: bool) -> Policy {
+    Policy {
+        max_size,
+        max_age: None,
+        keep,
+        compress,
+    }
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_default()
+}
+
+#[test]
+fn rotates_by_size_keeping_capped_generations() {
+    let path = scratch("size");
+    let mut log = RotatingFile::open(&path, policy(10, 2, false)).unwrap();
+    for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+        log.write_all(line.as_bytes()).unwrap();
+    }
+    assert_eq!(read(&path), "dddddddd\n");
+    assert_eq!(read(&generation(&path, 1, false)), "cccccccc\n");
+    assert_eq!(read(&generation(&path, 2, false)), "bbbbbbbb\n");
+    assert!(!generation(&path, 3, false).exists(), "only 2 kept");
+}
+
+#[test]
+fn a_line_is_never_split_across_files() {
+    let path = scratch("whole");
+    let mut log = RotatingFile::open(&path, policy(10, 1, false)).unwrap();
+    // bigger than the limit on its own: still written, whole
+    log.write_all(b"0123456789abcdef\n").unwrap();
+    log.write_all(b"x\n").unwrap();
+    assert_eq!(read(&generation(&path, 1, false)), "0123456789abcdef\n");
+    assert_eq!(read(&path), "x\n");
+}
+
+#[test]
+fn rotates_by_age() {
+    let path = scratch("age");
+    let mut log = RotatingFile::open(&path, policy(0, 1, false)).unwrap();
+    log.write_all(b"old\n").unwrap();
+    log.policy.max_age = Some(Duration::ZERO);
+    log.write_all(b"new\n").unwrap();
+    assert_eq!(read(&generation(&path, 1, false)), "old\n");
+    assert_eq!(read(&path), "new\n");
+}
+
+#[test]
+fn compresses_rotated_generations() {
+    let path = scratch("gz");
+    let mut log = RotatingFile::open(&path, policy(4, 2, true)).unwrap();
+    log.write_all(b"one\n").unwrap();
+    log.write_all(b"two\n").unwrap();
+    let gz = generation(&path, 1, true);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !gz.exists() && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let mut text = String::new();
+    io::Read::read_to_string(
+        &mut flate2::read::GzDecoder::new(File::open(&gz).unwrap()),
+        &mut text,
+    )
+    .unwrap();
+    assert_eq!(text, "one\n");
+    assert!(!generation(&path, 1, false).exists());
+}
+
+/// Rotations faster than compression: each generation is still
+/// compressed, whole, and none is lost.
+#[test]
+fn back_to_back_rotations_lose_no_generation() {
+    let path = scratch("gz-burst");
+    let mut log = RotatingFile::open(&path, policy(4, 3, true)).unwrap();
+    for line in ["one\n", "two\n", "six\n"] {
+        log.write_all(line.as_bytes()).unwrap();
+    }
+    log.rotate().unwrap(); // waits for the compression of "two"
+    if let Some(compressing) = log.compressing.take() {
+        compressing.join().unwrap();
+    }
+    for (n, text) in [(1, "six\n"), (2, "two\n"), (3, "one\n")] {
+        let mut found = String::new();
+        io::Read::read_to_string(
+            &mut flate2::read::GzDecoder::new(File::open(generation(&path, n, true)).unwrap()),
+            &mut found,
+        )
+        .unwrap();
+        assert_eq!(found, text, "generation {n}");
+        assert!(!generation(&path, n, false).exists());
+    }
+}
+
+#[test]
+fn reopens_after_external_rotation() {
+    let path = scratch("external");
+    let mut log = RotatingFile::open(&path, policy(0, 1, false)).unwrap();
+    log.write_all(b"before\n").unwrap();
+    std::fs::rename(&path, path.with_extension("log.moved")).unwrap();
+    log.last_check -= MOVE_CHECK_EVERY;
+    log.write_all(b"after\n").unwrap();
+    assert_eq!(read(&path), "after\n");
+    assert_eq!(read(&path.with_extension("log.moved")), "before\n");
+}
@@ -0,0 +1,218 @@
+//! Device selectors: which touchpad(s) discovery may pick.
+//!
+//! A raw `/dev/input/eventN` path names whatever happened to enumerate
+//! Nth this boot, and after a re-enumeration (suspend, a reloaded
+//! driver) it may name nothing, or something else. A selector names the
+//! device by something stable instead:
+//!
+//! * `name:<glob>`, or a bare glob: the device name (as in
+//!   `libinput list-devices`), e.g. `*Touchpad*`
+//! * `<vendor>:<product>`, in hex: the USB/I2C ID, e.g. `06cb:cd8b`
+//! * an absolute path: an event node, or better one of its stable
+//!   `/dev/input/by-id/` or `/dev/input/by-path/` links (resolved each
+//!   time it's matched, so it follows the device to a new node)
+//! * `phys:<glob>`: the physical location, e.g. `phys:i2c-SYNA*`
+//!
+//! Globs take `*` and `?` and ignore case. The same selectors work on
+//! the command line (`--device`, `--exclude`) and in the config's
+//! `devices.include` / `devices.exclude` lists.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize, Serializer};
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Selector {
+    Name(String),
+    Id { vendor: u16, product: u16 },
+    Path(PathBuf),
+    Phys(String),
+}
+
+/// What a selector is matched against: one candidate device.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeviceInfo {
+    /// Its event node, `/dev/input/eventN`.
+    pub path: PathBuf,
+    pub name: String,
+    pub phys: String,
+    #[serde(serialize_with = "hex")]
+    pub vendor: u16,
+    #[serde(serialize_with = "hex")]
+    pub product: u16,
+}
+
+/// IDs as they're written in selectors (and by `lsusb`): `"06cb"`.
+fn hex<S: Serializer>(id: &u16, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{id:04x}"))
+}
+
+fn parse_id(s: &str) -> Option<(u16, u16)> {
+    let (vendor, product) = s.split_once(':')?;
+    let hex = |part: &str| {
+        (!part.is_empty() && part.len() <= 4 && part.bytes().all(|b| b.is_ascii_hexdigit()))
+            .then(|| u16::from_str_radix(part, 16).ok())
+            .flatten()
+    };
+    Some((hex(vendor)?, hex(product)?))
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let nonempty = |glob: &str, what: &str| {
+            if glob.is_empty() {
+                Err(format!("device selector `{s}` has an empty {what} pattern"))
+            } else {
+                Ok(glob.to_string())
+            }
+        };
+        if s.is_empty() {
+            Err("empty device selector".to_string())
+        } else if let Some(glob) = s.strip_prefix("name:") {
+            nonempty(glob, "name").map(Selector::Name)
+        } else if let Some(glob) = s.strip_prefix("phys:") {
+            nonempty(glob, "phys").map(Selector::Phys)
+        } else if s.starts_with('/') {
+            Ok(Selector::Path(PathBuf::from(s)))
+        } else if let Some((vendor, product)) = parse_id(s) {
+            Ok(Selector::Id { vendor, product })
+        } else if s.starts_with('.') || s.contains('/') {
+            Err(format!(
+                "device selector `{s}` looks like a relative path; give the full path"
+            ))
+        } else {
+            Ok(Selector::Name(s.to_string()))
+        }
+    }
+}
+
+impl TryFrom<String> for Selector {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selector::Name(glob) => write!(f, "name:{glob}"),
+            Selector::Id { vendor, product } => write!(f, "{vendor:04x}:{product:04x}"),
+            Selector::Path(path) => write!(f, "{}", path.display()),
+            Selector::Phys(glob) => write!(f, "phys:{glob}"),
+        }
+    }
+}
+
+/// Whether `text` matches the shell-style `pattern` (`*` any run, `?`
+/// any one character), ignoring case.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+    let (mut p, mut t) = (0, 0);
+    // where the last `*` was, and where in `text` it's currently matched up to
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // let the last `*` swallow one more character
+                Some((sp, st)) => {
+                    star = Some((sp, st + 1));
+                    p = sp + 1;
+                    t = st + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The node a path selector resolves to right now, if any.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    std::fs::canonicalize(path).ok()
+}
+
+impl Selector {
+    pub fn matches(&self, dev: &DeviceInfo) -> bool {
+        match self {
+            Selector::Name(glob) => glob_match(glob, &dev.name),
+            Selector::Id { vendor, product } => dev.vendor == *vendor && dev.product == *product,
+            Selector::Path(path) => {
+                path == &dev.path
+                    || resolve(path).is_some_and(|node| Some(node) == resolve(&dev.path))
+            }
+            Selector::Phys(glob) => glob_match(glob, &dev.phys),
+        }
+    }
+}
+
+/// The `devices` section of the config (and `--device` / `--exclude`).
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct DeviceFilter {
+    /// Only touchpads matching one of these are proxied, preferred in
+    /// this order; empty means any touchpad.
+    pub include: Vec<Selector>,
+    /// Touchpads matching any of these never are, included or not.
+    pub exclude: Vec<Selector>,
+}
+
+impl DeviceFilter {
+    /// `None` if `dev` is filtered out; otherwise its preference (lower
+    /// first): the index of the first include selector it matches.
+    pub fn rank(&self, dev: &DeviceInfo) -> Option<usize> {
+        if self.exclude.iter().any(|s| s.matches(dev)) {
+            return None;
+        }
+        if self.include.is_empty() {
+            return Some(0);
+        }
+        self.include.iter().position(|s| s.matches(dev))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+}
+
+impl fmt::Display for DeviceFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |selectors: &[Selector]| {
+            selectors
+                .iter()
+                .map(|s| format!("`{s}`"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match (self.include.is_empty(), self.exclude.is_empty()) {
+            (true, true) => write!(f, "any touchpad"),
+            (false, true) => write!(f, "include {}", list(&self.include)),
+            (true, false) => write!(f, "exclude {}", list(&self.exclude)),
+            (false, false) => write!(
+                f,
+                "include {}, exclude {}",
+                list(&self.include),
+                list(&self.exclude)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+//! Device selectors: parsing each kind, globbing, and the include /
+//! exclude rules discovery applies.
+
+use super::*;
+
+fn touchpad() -> DeviceInfo {
+    DeviceInfo {
+        path: PathBuf::from("/dev/input/event7"),
+        name: "SYNA8004:00 06CB:CD8B Touchpad".to_string(),
+        phys: "i2c-SYNA8004:00".to_string(),
+        vendor: 0x06cb,
+        product: 0xcd8b,
+    }
+}
+
+#[test]
+fn every_selector_kind_parses_and_round_trips() {
+    let cases = [
+        ("name:*Touchpad*", Selector::Name("*Touchpad*".into())),
+        ("*Touchpad*", Selector::Name("*Touchpad*".into())),
+        (
+            "06cb:CD8B",
+            Selector::Id {
+                vendor: 0x06cb,
+                product: 0xcd8b,
+            },
+        ),
+        (
+            "/dev/input/by-path/platform-i2c-event-mouse",
+            Selector::Path("/dev/input/by-path/platform-i2c-event-mouse".into()),
+        ),
+        ("phys:i2c-SYNA*", Selector::Phys("i2c-SYNA*".into())),
+    ];
+    for (text, expected) in cases {
+        let parsed: Selector = text.parse().unwrap();
+        assert_eq!(parsed, expected, "{text}");
+        assert_eq!(parsed.to_string().parse::<Selector>().unwrap(), expected);
+    }
+    for bad in ["", "  ", "name:", "phys:", "by-id/usb-touchpad", "./event7"] {
+        assert!(bad.parse::<Selector>().is_err(), "{bad:?} should not parse");
+    }
+}
+
+#[test]
+fn globs_match_whole_names_ignoring_case() {
+    assert!(glob_match("*touchpad*", "SYNA8004:00 06CB:CD8B Touchpad"));
+    assert!(glob_match("SYNA????:00*", "SYNA8004:00 06CB:CD8B Touchpad"));
+    assert!(glob_match("*a*b*c", "xxaxxbxxbxxc"));
+    assert!(glob_match("*", ""));
+    assert!(!glob_match("touchpad", "SYNA8004:00 06CB:CD8B Touchpad"));
+    assert!(!glob_match("*Touchpad?", "SYNA8004:00 06CB:CD8B Touchpad"));
+    assert!(!glob_match("a*b", "ab-"));
+}
+
+#[test]
+fn each_kind_matches_its_own_field() {
+    let dev = touchpad();
+    for (selector, matches) in [
+        ("*Touchpad", true),
+        ("*Trackpad", false),
+        ("06cb:cd8b", true),
+        ("06cb:cd8c", false),
+        ("phys:i2c-SYNA*", true),
+        ("phys:usb-*", false),
+        ("/dev/input/event7", true),
+        ("/dev/input/event8", false),
+    ] {
+        let selector: Selector = selector.parse().unwrap();
+        assert_eq!(selector.matches(&dev), matches, "{selector}");
+    }
+}
+
+#[test]
+fn path_selectors_follow_links_to_the_node() {
+    let dir = std::env::temp_dir().join(format!("3fd-selector-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let node = dir.join("event7");
+    let link = dir.join("usb-Vendor_Touchpad-event-mouse");
+    std::fs::write(&node, "").unwrap();
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(&node, &link).unwrap();
+
+    let dev = DeviceInfo {
+        path: node.clone(),
+        ..touchpad()
+    };
+    assert!(Selector::Path(link.clone()).matches(&dev));
+    let elsewhere = DeviceInfo {
+        path: dir.join("event8"),
+        ..touchpad()
+    };
+    assert!(!Selector::Path(link).matches(&elsewhere));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn exclude_wins_and_include_order_is_preference() {
+    let dev = touchpad();
+    let filter = |include: &[&str], exclude: &[&str]| DeviceFilter {
+        include: include.iter().map(|s| s.parse().unwrap()).collect(),
+        exclude: exclude.iter().map(|s| s.parse().unwrap()).collect(),
+    };
+    assert_eq!(DeviceFilter::default().rank(&dev), Some(0));
+    assert_eq!(filter(&["usb-*", "*Touchpad"], &[]).rank(&dev), Some(1));
+    assert_eq!(filter(&["*Trackpad"], &[]).rank(&dev), None);
+    assert_eq!(filter(&[], &["06cb:cd8b"]).rank(&dev), None);
+    assert_eq!(filter(&["*Touchpad"], &["phys:i2c-*"]).rank(&dev), None);
+}
@@ -1,56 +1,184 @@
 use std::io;
 use std::os::fd::{AsRawFd, RawFd};
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
-use tokio::io::unix::AsyncFd;
+use tokio::io::unix::{AsyncFd, AsyncFdReadyMutGuard};
 use tokio::io::Interest;
-use tokio::signal::unix::{signal, SignalKind};
-use tracing::{debug, info, warn};
-use tracing_subscriber::fmt::time::ChronoLocal;
+use tokio::signal::unix::{signal, Signal, SignalKind};
+use tracing::{debug, error, info, warn};
 
 use linux_3_finger_drag::{
-    init::{config, discovery},
-    runtime::{gesture::GestureMachine, mt_proxy::MtProxy, virtual_trackpad},
+    init::{
+        check, config, discovery, doctor, list_devices, logging,
+        selector::{DeviceFilter, Selector},
+    },
+    runtime::{
+        adaptive::{self, StaggerLearner},
+        config_watch::ConfigWatch,
+        control::{self, ControlSocket},
+        gesture::{GestureMachine, Timing},
+        instance::{self, DeviceLock},
+        logind::LogindSleep,
+        metrics::Metrics,
+        mt_proxy::{self, MtProxy},
+        notify::Notifier,
+        recorder::{self, FlightRecorder, SharedRecorder},
+        suspend::SuspendClock,
+        virtual_trackpad,
+    },
 };
 
-/// How often the config file's mtime is checked for hot reload.
+/// How often the config file's mtime is checked for hot reload, when
+/// inotify can't be used.
 const CFG_POLL: Duration = Duration::from_secs(2);
+/// How long after the last change to the config a reload happens.
+/// Editors tend to save in several steps (truncate, write, chmod,
+/// rename); one reload for the lot, and after it's complete.
+const CFG_SETTLE: Duration = Duration::from_millis(100);
 /// Hotplug: how long to keep retrying discovery after the touchpad
 /// disappears (device re-enumeration, e.g. around suspend), before
 /// giving up and letting the service manager restart us.
 const REDISCOVER_ATTEMPTS: u32 = 60;
 const REDISCOVER_BACKOFF: Duration = Duration::from_millis(500);
+/// How often the metrics are dumped to the log (at debug level).
+const METRICS_LOG_EVERY: Duration = Duration::from_secs(600);
+/// Fail-open: how long the touchpad is left to the compositor after an
+/// internal error before the proxy is set up again, doubling per repeat
+/// failure up to the max. A proxy that then runs for the max without
+/// failing starts over from the first delay.
+const FAIL_OPEN_RETRY: Duration = Duration::from_secs(1);
+const FAIL_OPEN_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// Why the event loop for a device ended.
+enum Exit {
+    /// Asked to (SIGTERM/SIGINT).
+    Shutdown,
+    /// The touchpad went away (ENODEV).
+    Unplugged,
+    /// Something broke: fail open, and retry.
+    Failed(io::Error),
+}
+
+/// `?` for the event loop: an error breaks out of it as [`Exit::Failed`]
+/// instead of out of `run` -- or as [`Exit::Unplugged`] if it says the
+/// touchpad is gone, whichever call happened to find that out.
+macro_rules! or_fail {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(e) if mt_proxy::is_unplug(&e) => break Exit::Unplugged,
+            Err(e) => break Exit::Failed(e),
+        }
+    };
+}
+
+enum Command {
+    /// The daemon itself.
+    Run,
+    /// Print a running daemon's metrics.
+    Metrics,
+    /// Validate a config file (the one in effect, if no path is given).
+    CheckConfig(Option<PathBuf>),
+    /// Show what discovery makes of every input device (as JSON if set).
+    ListDevices { json: bool },
+    /// Diagnose the installation.
+    Doctor,
+}
 
 struct Args {
-    /// Explicit touchpad device path (skips discovery). Mainly for the
-    /// integration test harness, but also useful on multi-touchpad
-    /// machines.
-    device: Option<String>,
+    command: Command,
+    /// `--device` selectors: replace the config's `devices.include`.
+    /// Mainly for the integration test harness, but also useful on
+    /// multi-touchpad machines.
+    include: Vec<Selector>,
+    /// `--exclude` selectors: added to the config's `devices.exclude`.
+    exclude: Vec<Selector>,
+    /// Take the touchpad over from an instance already proxying it.
+    replace: bool,
+    /// Dry run: read the touchpad without grabbing it, emit nothing, and
+    /// log the machine's decisions.
+    observe: bool,
 }
 
 fn parse_args() -> Result<Args, String> {
-    let mut args = Args { device: None };
+    let mut args = Args {
+        command: Command::Run,
+        include: Vec::new(),
+        exclude: Vec::new(),
+        replace: false,
+        observe: false,
+    };
     let mut iter = std::env::args().skip(1);
     while let Some(arg) = iter.next() {
         match arg.as_str() {
-            "--device" => {
-                args.device = Some(
-                    iter.next()
-                        .ok_or_else(|| "--device requires a path argument".to_string())?,
-                );
+            "metrics" => args.command = Command::Metrics,
+            "check-config" => args.command = Command::CheckConfig(None),
+            "doctor" => args.command = Command::Doctor,
+            "list-devices" => args.command = Command::ListDevices { json: false },
+            "--json" if matches!(args.command, Command::ListDevices { .. }) => {
+                args.command = Command::ListDevices { json: true };
+            }
+            path if matches!(args.command, Command::CheckConfig(None))
+                && !path.starts_with('-') =>
+            {
+                args.command = Command::CheckConfig(Some(PathBuf::from(path)));
+            }
+            flag @ ("--device" | "--exclude") => {
+                let selector = iter
+                    .next()
+                    .ok_or_else(|| format!("{flag} requires a device selector argument"))?
+                    .parse()?;
+                if flag == "--device" {
+                    args.include.push(selector);
+                } else {
+                    args.exclude.push(selector);
+                }
             }
+            "--replace" => args.replace = true,
+            "--observe" => args.observe = true,
             "--version" | "-V" => {
                 println!("linux-3-finger-drag {}", env!("CARGO_PKG_VERSION"));
                 std::process::exit(0);
             }
             "--help" | "-h" => {
                 println!(
-                    "linux-3-finger-drag [--device /dev/input/eventN]\n\n\
+                    "linux-3-finger-drag [--device SELECTOR]... [--exclude SELECTOR]... [--replace] \
+                    [--observe]\n\
+                    linux-3-finger-drag metrics\n\
+                    linux-3-finger-drag check-config [PATH]\n\
+                    linux-3-finger-drag list-devices [--json] [--device SELECTOR]... \
+                    [--exclude SELECTOR]...\n\
+                    linux-3-finger-drag doctor\n\n\
                     Turns a sustained 3-finger touchpad touch into a drag \
                     (mouse-button-held movement).\n\n\
-                      --device PATH   proxy this evdev device instead of \
-                    auto-discovering the touchpad\n\
-                      --version       print the version and exit"
+                      metrics         print the running daemon's gesture \
+                    counters and latencies\n\
+                      check-config    validate a config file (default: the \
+                    one in effect) and print the effective configuration; \
+                    exits non-zero on errors\n\
+                      list-devices    show every input device, the touchpad \
+                    checks it passed or failed, and which touchpad would be \
+                    proxied (--json for a bug report)\n\
+                      doctor          check the installation (input group, \
+                    uinput, udev rules, service, touchpad grab) and say how to \
+                    fix what's wrong; exits non-zero on failures\n\n\
+                      --device SELECTOR   only proxy a touchpad matching this \
+                    (repeatable, in order of preference; replaces the config's \
+                    devices.include)\n\
+                      --exclude SELECTOR  never proxy a touchpad matching this \
+                    (repeatable)\n\
+                      --replace       take over from an instance already \
+                    proxying the touchpad\n\
+                      --observe       dry run: watch the touchpad without \
+                    grabbing it or emitting anything, and log what would have \
+                    been done (drags committed, presses, aborts)\n\
+                      --version       print the version and exit\n\n\
+                    A SELECTOR is a name glob (`name:*Touchpad*`, or just \
+                    `*Touchpad*`), a hex vendor:product (`06cb:cd8b`), a device \
+                    path (`/dev/input/by-id/...`, `/dev/input/eventN`), or \
+                    `phys:GLOB`."
                 );
                 std::process::exit(0);
             }
@@ -78,8 +206,144 @@ async fn sleep_until_opt(deadline: Option<std::time::Instant>) {
     }
 }
 
-fn is_unplug(err: &io::Error) -> bool {
-    err.raw_os_error() == Some(libc::ENODEV)
+/// The configured timing, with the learned windows substituted in when
+/// adaptive timing is on.
+fn effective_timing(cfg: &config::Configuration, learner: &Option<StaggerLearner>) -> Timing {
+    match learner {
+        Some(l) => l.apply(cfg.timing()),
+        None => cfg.timing(),
+    }
+}
+
+/// Hands the touches that finished since the last call to the adaptive
+/// learner (if enabled), applying and persisting whatever it adjusts.
+fn learn(
+    machine: &mut GestureMachine,
+    learner: &mut Option<StaggerLearner>,
+    cfg: &config::Configuration,
+    state_path: Option<&Path>,
+) {
+    let landings = machine.take_landings();
+    let Some(learner) = learner else { return };
+    for landing in landings {
+        let Some(learned) = learner.observe(landing) else {
+            continue;
+        };
+        info!(
+            "Adaptive timing: entryDebounce now {:?}, pressGrace now {:?}.",
+            learned.entry_debounce, learned.press_grace
+        );
+        machine.set_timing(learner.apply(cfg.timing()));
+        if let Some(path) = state_path {
+            if let Err(e) = adaptive::save(path, &learned) {
+                warn!("Could not save learned timing to {}: {e}", path.display());
+            }
+        }
+    }
+}
+
+/// Waits for an optional fd (the config watch, the logind listener) to
+/// have events, or forever if there is none (for the config watch, the
+/// poll timer then stands in).
+async fn readable_opt<T: AsRawFd>(
+    fd: &mut Option<AsyncFd<T>>,
+) -> io::Result<AsyncFdReadyMutGuard<'_, T>> {
+    match fd {
+        Some(fd) => fd.readable_mut().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next watchdog ping to be due, or forever if systemd
+/// didn't ask for any.
+async fn tick_opt(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(t) => {
+            t.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next control-socket client, or forever if the socket
+/// couldn't be set up. The select! counterpart of `sleep_until_opt`.
+async fn accept_opt(control: &Option<ControlSocket>) -> io::Result<tokio::net::UnixStream> {
+    match control {
+        Some(c) => c.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// The device selection in effect: the config's, with `--device`
+/// replacing its include list and `--exclude` adding to its exclusions.
+fn device_filter(args: &Args, cfg: &config::Configuration) -> DeviceFilter {
+    let mut filter = cfg.devices.clone();
+    if !args.include.is_empty() {
+        filter.include = args.include.clone();
+    }
+    filter.exclude.extend(args.exclude.iter().cloned());
+    filter
+}
+
+/// `--observe`: logs each decision the machine made since the last call.
+fn report_decisions(machine: &mut GestureMachine) {
+    for d in machine.take_decisions() {
+        info!(
+            touch = d.touch,
+            transition = d.reason,
+            "Observed touch #{} ({} finger(s)): {}.",
+            d.touch,
+            d.fingers,
+            d.description
+        );
+    }
+}
+
+/// Runs `f`, turning a panic into an error: a bug in the gesture machine
+/// then fails open like any other error, rather than taking the process
+/// (and with it the clone) down. The panic hook has already dumped the
+/// flight recorder by the time this returns.
+fn catch_panic<T>(f: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+    std::panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(io::Error::other("panicked (see the flight recorder dump)")))
+}
+
+/// Fail-open: with the touchpad handed back to the compositor, waits
+/// `retry` before the next attempt at proxying (and doubles it for the
+/// one after), still feeding the watchdog. Returns false if asked to
+/// shut down in the meantime.
+async fn fail_open(
+    err: io::Error,
+    retry: &mut Duration,
+    watchdog_timer: &mut Option<tokio::time::Interval>,
+    notifier: Option<&Notifier>,
+    sigterm: &mut Signal,
+    sigint: &mut Signal,
+) -> bool {
+    error!(
+        "Three-finger drag FAILED: {err}. The touchpad has been handed back \
+        to the compositor (working normally, without three-finger drag); \
+        retrying in {retry:?}."
+    );
+    if let Some(n) = notifier {
+        n.status(&format!(
+            "Paused after an error ({err}); retrying in {retry:?}"
+        ));
+    }
+    let resume_at = tokio::time::Instant::now() + *retry;
+    *retry = (*retry * 2).min(FAIL_OPEN_RETRY_MAX);
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep_until(resume_at) => return true,
+            _ = tick_opt(watchdog_timer) => {
+                if let Some(n) = notifier {
+                    n.watchdog();
+                }
+            }
+            _ = sigterm.recv() => return false,
+            _ = sigint.recv() => return false,
+        }
+    }
 }
 
 #[tokio::main(flavor = "current_thread")]
@@ -89,28 +353,80 @@ async fn main() -> Result<(), io::Error> {
         io::Error::new(io::ErrorKind::InvalidInput, e)
     })?;
 
-    let configs = config::init_cfg();
-
-    match config::init_file_logger(configs.clone()) {
-        Some(logger) => logger.init(),
-        None => {
-            tracing_subscriber::fmt()
-                .with_writer(std::io::stdout)
-                .with_max_level(configs.log_level)
-                .with_timer(ChronoLocal::rfc_3339())
-                .init();
+    match &args.command {
+        Command::Run => {}
+        Command::Metrics => {
+            print!("{}", control::query()?);
+            return Ok(());
         }
-    };
+        Command::CheckConfig(path) => {
+            let (path, ignored) = match path {
+                Some(p) => (p.clone(), Vec::new()),
+                None => config::get_config_files()?,
+            };
+            let mut report = check::check(&path);
+            for ignored in ignored {
+                report
+                    .warnings
+                    .push(format!("{} also exists, and is ignored", ignored.display()));
+            }
+            print!("{}", report.render(&path));
+            std::process::exit(if report.ok() { 0 } else { 1 });
+        }
+        Command::ListDevices { json } => {
+            // the selection the daemon would use, minus the config chatter
+            let cfg = config::parse_config_file().unwrap_or_default();
+            let filter = device_filter(&args, &cfg);
+            let entries = list_devices::list(&filter)?;
+            if *json {
+                print!("{}", list_devices::render_json(&entries));
+            } else {
+                print!("{}", list_devices::render_text(&entries, &filter));
+            }
+            return Ok(());
+        }
+        Command::Doctor => {
+            let cfg = config::parse_config_file().unwrap_or_default();
+            let report = doctor::diagnose(&device_filter(&args, &cfg));
+            print!("{}", report.render());
+            std::process::exit(if report.ok() { 0 } else { 1 });
+        }
+    }
+
+    let configs = config::load_config();
+
+    let log = logging::init_logger(&configs);
+
+    let flight_recorder = FlightRecorder::shared();
+    recorder::install_panic_hook(flight_recorder.clone());
 
-    let mut vtrackpad = virtual_trackpad::start_handler()?;
+    let mut vtrackpad = if args.observe {
+        virtual_trackpad::observer()
+    } else {
+        virtual_trackpad::start_handler()?
+    };
+    let notifier = Notifier::from_env();
 
     // run() holds the real event loop; wrapping it like this guarantees
     // the virtual devices are destroyed on the way out no matter how it
     // returns (including the button being released if a drag was live).
-    let result = run(&args, configs, &mut vtrackpad).await;
+    let result = run(
+        &args,
+        configs,
+        &log,
+        &mut vtrackpad,
+        flight_recorder,
+        notifier.as_ref(),
+    )
+    .await;
 
     info!("Cleaning up and exiting...");
-    vtrackpad.mouse_up()?; // just in case a drag was in flight
+    if let Some(n) = &notifier {
+        n.stopping();
+    }
+    if vtrackpad.any_down() {
+        vtrackpad.release_all()?; // a drag was in flight
+    }
     vtrackpad.destruct()?;
     info!("Clean up successful.");
     result
@@ -119,108 +435,408 @@ async fn main() -> Result<(), io::Error> {
 async fn run(
     args: &Args,
     mut cfg: config::Configuration,
+    log: &logging::LogHandle,
     vtp: &mut virtual_trackpad::VirtualTrackpad,
+    flight_recorder: SharedRecorder,
+    notifier: Option<&Notifier>,
 ) -> Result<(), io::Error> {
     let mut sigterm = signal(SignalKind::terminate())?;
     let mut sigint = signal(SignalKind::interrupt())?;
+    let mut sigusr1 = signal(SignalKind::user_defined1())?;
+    let mut sighup = signal(SignalKind::hangup())?;
 
-    let cfg_path = config::get_config_file_path()?;
+    let mut cfg_path = config::get_config_file_path()?;
+    let mut cfg_watch = ConfigWatch::new(&config::get_config_dir()?, &cfg_path)
+        .and_then(|w| AsyncFd::with_interest(w, Interest::READABLE))
+        .inspect_err(|e| {
+            info!(
+                "Cannot watch the config directory ({e}); checking it every {CFG_POLL:?} instead."
+            )
+        })
+        .ok();
     let mut cfg_mtime = std::fs::metadata(&cfg_path).and_then(|m| m.modified()).ok();
     let mut cfg_timer = tokio::time::interval(CFG_POLL);
     cfg_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // when the pending config reload is due
+    let mut reload_at: Option<std::time::Instant> = None;
+    let mut metrics_timer = tokio::time::interval_at(
+        tokio::time::Instant::now() + METRICS_LOG_EVERY,
+        METRICS_LOG_EVERY,
+    );
+    metrics_timer.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    // only if the unit sets WatchdogSec=; pinged from the event loop
+    // itself, so a wedged loop is what stops the pings
+    let mut watchdog_timer = notifier
+        .and_then(Notifier::watchdog_interval)
+        .map(tokio::time::interval);
+    // whether a first device was up (READY=1 goes out once; later
+    // re-acquisitions just update the status)
+    let mut started = false;
+    let mut retry = FAIL_OPEN_RETRY;
+
+    // bound once this instance owns its touchpad: binding replaces the
+    // socket file, which would cut a running instance off from its CLI
+    let mut control: Option<ControlSocket> = None;
+    let mut suspend_clock = SuspendClock::default();
+    let mut logind = LogindSleep::connect()
+        .and_then(|l| AsyncFd::with_interest(l, Interest::READABLE))
+        .inspect_err(|e| {
+            debug!("No sleep notifications from logind ({e}); detecting suspend by the clock.")
+        })
+        .ok();
+    // survives device re-acquisition, so the totals cover the whole run
+    let mut metrics = Metrics::default();
+    // the clone of a touchpad that went away, for it to come back to
+    let mut kept_clone = None;
 
     // Outer loop: one iteration per (re)acquired touchpad. Re-entered
     // only if the device disappears (ENODEV) and rediscovery succeeds.
     'device: loop {
-        let path = match &args.device {
-            Some(p) => p.clone(),
-            None => {
-                info!("Searching for the trackpad on your device...");
-                let paths = discovery::find_real_trackpads()?;
-                if paths.len() > 1 {
-                    warn!(
-                        "Found {} touchpads; only proxying the first one ({}).",
-                        paths.len(),
-                        paths[0]
-                    );
-                }
-                paths[0].clone()
+        info!("Searching for the trackpad on your device...");
+        let paths = match discovery::find_real_trackpads(&device_filter(args, &cfg)) {
+            Ok(paths) => paths,
+            Err(e) if started => {
+                if !fail_open(
+                    e,
+                    &mut retry,
+                    &mut watchdog_timer,
+                    notifier,
+                    &mut sigterm,
+                    &mut sigint,
+                )
+                .await
+                {
+                    return Ok(());
+                }
+                continue 'device;
+            }
+            Err(e) => return Err(e),
+        };
+        if paths.len() > 1 {
+            warn!(
+                "Found {} touchpads; only proxying the first one ({}).",
+                paths.len(),
+                paths[0]
+            );
+        }
+        let path = paths[0].clone();
+
+        // Everything logged while this device is proxied carries its
+        // path (e.g. as DEVICE_PATH in the journal). Holding the guard
+        // across awaits is fine here: this runtime has a single task.
+        let device_span = tracing::info_span!("device", path = %path);
+        let _in_device = device_span.enter();
+
+        // Only the first acquisition may replace another instance; one
+        // that slipped in during rediscovery is left alone. An observer
+        // takes no lock (it shares the device), but had better say if a
+        // proxying instance will starve it.
+        let _lock = if args.observe {
+            if let Ok(Some(holder)) =
+                instance::device_key(&path).and_then(|key| instance::holder_of(&key))
+            {
+                warn!(
+                    "{} has this touchpad grabbed, so nothing reaches an \
+                    observer while it runs. Stop it first (e.g. systemctl \
+                    --user stop three-finger-drag.service).",
+                    holder.describe()
+                );
+            }
+            None
+        } else {
+            match DeviceLock::acquire(&path, args.replace && !started) {
+                Ok(lock) => Some(lock),
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::ResourceBusy | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    error!("{e}");
+                    return Err(e);
+                }
+                Err(e) => {
+                    warn!("Could not take the single-instance lock ({e}); carrying on without it.");
+                    None
+                }
             }
         };
+        if control.is_none() && !args.observe {
+            control = ControlSocket::bind()
+                .inspect_err(|e| warn!("Metrics query socket unavailable: {e}"))
+                .ok();
+        }
 
-        let mut proxy = MtProxy::new(&path)?;
+        // Once the touchpad has been proxied, failing to set it up again
+        // fails open; at startup, it's better to exit and say why.
+        let opened = if args.observe {
+            MtProxy::observe(&path, flight_recorder.clone())
+        } else {
+            MtProxy::new(&path, flight_recorder.clone(), kept_clone.take())
+        };
+        let mut proxy = match opened {
+            Ok(proxy) => proxy,
+            Err(e) if started => {
+                if !fail_open(
+                    e,
+                    &mut retry,
+                    &mut watchdog_timer,
+                    notifier,
+                    &mut sigterm,
+                    &mut sigint,
+                )
+                .await
+                {
+                    return Ok(());
+                }
+                continue 'device;
+            }
+            Err(e) => return Err(e),
+        };
+        // an observer learns, but doesn't overwrite what the real thing learned
+        let state_path: Option<PathBuf> = adaptive::state_path(proxy.device_key())
+            .ok()
+            .filter(|_| !args.observe);
+        let mut learner = cfg.adaptive_timing.then(|| {
+            let saved = state_path.as_deref().and_then(adaptive::load);
+            if let Some(l) = saved {
+                info!(
+                    "Adaptive timing: resuming from learned entryDebounce {:?}, pressGrace {:?}.",
+                    l.entry_debounce, l.press_grace
+                );
+            }
+            StaggerLearner::new(&cfg.timing(), saved)
+        });
         let mut machine = GestureMachine::new(
-            cfg.timing(),
+            effective_timing(&cfg, &learner),
             proxy.x_res(),
             proxy.y_res(),
             proxy.slot_count(),
         );
+        *machine.metrics_mut() = std::mem::take(&mut metrics);
+        if args.observe {
+            machine.collect_decisions();
+        }
+        machine.set_click_action(cfg.click_during_drag);
+        proxy.set_drag_lock(cfg.timing().drag_end_delay);
         let watch = AsyncFd::with_interest(FdWatch(proxy.as_raw_fd()), Interest::READABLE)?;
+        let healthy_since = std::time::Instant::now();
 
-        info!("linux-3-finger-drag started successfully!");
+        if args.observe {
+            info!(
+                "Observing {path}: nothing is grabbed or emitted, and each \
+                decision is logged as it's made."
+            );
+        } else {
+            info!("linux-3-finger-drag started successfully!");
+        }
+        if let Some(n) = notifier {
+            let status = format!("Active on {path}");
+            if started {
+                n.status(&status);
+            } else {
+                n.ready(&status);
+            }
+        }
+        started = true;
 
         // Inner loop: fully event-driven. We wake for exactly three
         // reasons: the touchpad has events, a gesture decision deadline
-        // arrived, or housekeeping (config reload / shutdown signal).
-        let lost_device = loop {
+        // arrived, or housekeeping (config change or SIGHUP, metrics
+        // query or dump, flight recorder dump, shutdown signal).
+        let exit = loop {
             tokio::select! {
                 ready = watch.readable() => {
-                    let mut guard = ready?;
-                    match proxy.drain(&mut machine, vtp) {
-                        Ok(()) => { guard.clear_ready(); }
-                        Err(e) if is_unplug(&e) => break true,
-                        Err(e) => return Err(e),
-                    }
+                    let mut guard = or_fail!(ready);
+                    or_fail!(catch_panic(|| proxy.drain(&mut machine, vtp)));
+                    guard.clear_ready();
                 }
 
                 _ = sleep_until_opt(machine.next_deadline()) => {
-                    let outs = machine.on_tick(std::time::Instant::now());
-                    proxy.apply(&outs, vtp)?;
+                    or_fail!(catch_panic(|| {
+                        let outs = machine.on_tick(std::time::Instant::now());
+                        proxy.apply(&outs, &machine, vtp)
+                    }));
+                }
+
+                _ = sleep_until_opt(proxy.stuck_deadline()) => {
+                    let now = std::time::Instant::now();
+                    or_fail!(proxy.release_if_stuck(&mut machine, vtp, now));
+                }
+
+                ready = readable_opt(&mut cfg_watch) => {
+                    let mut guard = or_fail!(ready);
+                    if or_fail!(guard.get_inner_mut().changed()) {
+                        reload_at.get_or_insert(std::time::Instant::now() + CFG_SETTLE);
+                    }
+                    guard.clear_ready();
                 }
 
-                _ = cfg_timer.tick() => {
+                _ = cfg_timer.tick(), if cfg_watch.is_none() => {
                     let new_mtime = std::fs::metadata(&cfg_path)
                         .and_then(|m| m.modified())
                         .ok();
                     if new_mtime.is_some() && new_mtime != cfg_mtime {
                         cfg_mtime = new_mtime;
-                        cfg = config::init_cfg();
-                        machine.set_timing(cfg.timing());
-                        info!("Configuration reloaded (log settings need a restart).");
+                        reload_at = Some(std::time::Instant::now());
                     }
                 }
 
-                _ = sigterm.recv() => break false,
-                _ = sigint.recv() => break false,
+                _ = sighup.recv() => reload_at = Some(std::time::Instant::now()),
+
+                ready = readable_opt(&mut logind) => {
+                    let mut guard = or_fail!(ready);
+                    match guard.get_inner_mut().events() {
+                        Ok(events) => {
+                            guard.clear_ready();
+                            or_fail!(events.into_iter().try_for_each(|sleeping| {
+                                let why = if sleeping {
+                                    "System going to sleep"
+                                } else {
+                                    // the clocks will tell the same story
+                                    suspend_clock.check();
+                                    "System resumed"
+                                };
+                                proxy.reset(&mut machine, vtp, why)
+                            }));
+                        }
+                        Err(e) => {
+                            drop(guard);
+                            warn!(
+                                "Lost logind's sleep notifications ({e}); \
+                                detecting suspend by the clock."
+                            );
+                            logind = None;
+                        }
+                    }
+                }
+
+                _ = sleep_until_opt(reload_at) => {
+                    reload_at = None;
+                    cfg = config::init_cfg();
+                    log.reconfigure(&cfg);
+                    if cfg.adaptive_timing {
+                        learner
+                            .get_or_insert_with(|| {
+                                let saved = state_path.as_deref().and_then(adaptive::load);
+                                StaggerLearner::new(&cfg.timing(), saved)
+                            })
+                            .rebase(&cfg.timing());
+                    } else {
+                        learner = None;
+                    }
+                    machine.set_timing(effective_timing(&cfg, &learner));
+                    machine.set_click_action(cfg.click_during_drag);
+                    proxy.set_drag_lock(cfg.timing().drag_end_delay);
+                    // a rename-over-save replaced the file the watch was on
+                    cfg_path = or_fail!(config::get_config_file_path());
+                    if let Some(watch) = &mut cfg_watch {
+                        watch.get_mut().rearm(&cfg_path);
+                    }
+                    info!("Configuration reloaded.");
+                }
+
+                conn = accept_opt(&control) => {
+                    if let Ok(stream) = conn {
+                        control::reply(stream, &machine.metrics().render());
+                    }
+                }
+
+                _ = metrics_timer.tick() => {
+                    debug!("Metrics:\n{}", machine.metrics().render());
+                }
+
+                _ = sigusr1.recv() => proxy.dump("signal"),
+
+                _ = tick_opt(&mut watchdog_timer) => {
+                    if let Some(n) = notifier {
+                        n.watchdog();
+                    }
+                }
+
+                _ = sigterm.recv() => break Exit::Shutdown,
+                _ = sigint.recv() => break Exit::Shutdown,
             }
+            // Whatever woke us, it may be the first thing to happen after
+            // a resume that logind didn't tell us about.
+            if let Some(slept) = suspend_clock.check() {
+                let why = format!("Resumed after {slept:?} asleep");
+                or_fail!(proxy.reset(&mut machine, vtp, &why));
+            }
+            learn(&mut machine, &mut learner, &cfg, state_path.as_deref());
+            report_decisions(&mut machine);
         };
 
-        if !lost_device {
-            proxy.destruct()?;
-            return Ok(());
+        match exit {
+            Exit::Shutdown => {
+                if args.observe {
+                    info!("Observed:\n{}", machine.metrics().render());
+                }
+                proxy.destruct()?;
+                return Ok(());
+            }
+            Exit::Unplugged => {}
+            Exit::Failed(e) => {
+                // Fail open: buttons up, clone gone, real touchpad
+                // ungrabbed -- each attempted whatever else fails.
+                if vtp.any_down() {
+                    if let Err(e) = vtp.release_all() {
+                        warn!("Could not release the virtual mouse buttons: {e}");
+                    }
+                }
+                if let Err(e) = proxy.destruct() {
+                    warn!("Could not cleanly release the touchpad: {e}");
+                }
+                metrics = std::mem::take(machine.metrics_mut());
+                if healthy_since.elapsed() >= FAIL_OPEN_RETRY_MAX {
+                    retry = FAIL_OPEN_RETRY;
+                }
+                if !fail_open(
+                    e,
+                    &mut retry,
+                    &mut watchdog_timer,
+                    notifier,
+                    &mut sigterm,
+                    &mut sigint,
+                )
+                .await
+                {
+                    return Ok(());
+                }
+                continue 'device;
+            }
         }
 
-        // The touchpad vanished (re-enumeration / suspend quirk). Drop
-        // the dead handles, release the button if a drag was mid-flight,
-        // and try to find it again -- the systemd unit's Restart is the
-        // backstop if it never comes back.
+        // The touchpad vanished (re-enumeration / suspend quirk). Lift
+        // every finger on the clone and release the button if a drag was
+        // mid-flight, drop the dead handle, and try to find it again --
+        // the systemd unit's Restart is the backstop if it never comes
+        // back. The clone stays up meanwhile: if the same hardware
+        // returns, the compositor never sees the touchpad go.
         warn!("Touchpad disappeared (ENODEV); attempting rediscovery...");
-        if machine.button_held() {
-            vtp.mouse_up()?;
+        if let Some(n) = notifier {
+            n.status(&format!("{path} disappeared; rediscovering the touchpad"));
         }
-        let _ = proxy.destruct();
-
-        if args.device.is_some() {
-            // an explicitly given device won't be re-discovered; bail
-            return Err(io::Error::new(
-                io::ErrorKind::NotFound,
-                "explicitly specified device disappeared",
-            ));
+        if let Err(e) = proxy.lift_all(&mut machine, vtp) {
+            warn!("Could not lift the clone's fingers ({e}); replacing it.");
+            if vtp.any_down() {
+                vtp.release_all()?;
+            }
+            let _ = proxy.destruct();
+        } else {
+            kept_clone = proxy.into_clone();
         }
+        metrics = std::mem::take(machine.metrics_mut());
 
         for attempt in 1..=REDISCOVER_ATTEMPTS {
             tokio::time::sleep(REDISCOVER_BACKOFF).await;
-            match discovery::find_real_trackpads() {
+            // still alive, just waiting: keep the watchdog fed
+            if let (Some(n), Some(_)) = (notifier, &watchdog_timer) {
+                n.watchdog();
+            }
+            // the same selection as the first time, so an explicitly
+            // chosen touchpad comes back as itself (at whatever new node)
+            match discovery::find_real_trackpads(&device_filter(args, &cfg)) {
                 Ok(paths) if !paths.is_empty() => {
                     debug!("Touchpad back after {attempt} attempt(s).");
                     continue 'device;
@@ -0,0 +1,208 @@
+//! Online tuning of `entryDebounce` and `pressGrace` to the user's own
+//! finger stagger (opt-in via `adaptiveTiming`).
+//!
+//! Both knobs exist because fingers don't land in unison, and how far
+//! apart they land is personal: it depends on the hand, the pad, and how
+//! fast the gesture is. The defaults are a compromise. The learner
+//! watches the landing stagger of touches that actually ended as drags
+//! (first finger to third) and as 4-finger gestures (first finger to
+//! fourth) -- the machine reports both as [`Landing`]s -- and nudges the
+//! two windows toward what this particular user needs:
+//!
+//! * `entry_debounce` should cover nearly every drag's 3-finger landing
+//!   (or the touch flashes through the compositor as a 2-finger touch
+//!   first) and most swipes' 4th finger (or they get committed as drags
+//!   and have to be aborted);
+//! * `press_grace` should cover the tail of 4th fingers that the debounce
+//!   window misses, so those aborts stay click-free.
+//!
+//! Like the gesture machine, the learner itself is pure: it takes
+//! observations and returns adjusted values, and the fuzz suite holds it
+//! to the same ranges `Configuration::sanitize` allows. Loading and
+//! saving the learned values (the only I/O here) are separate functions
+//! at the bottom, used by the shell.
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::gesture::{Landing, Timing};
+use crate::init::config::{get_state_dir, MAX_ENTRY_DEBOUNCE, MAX_PRESS_GRACE};
+
+/// The learner's own, deliberately narrow, limits. Intersected with the
+/// config ceilings (and `probe_delay` as the debounce floor) at use, so
+/// no amount of unusual input can learn its way to a broken touchpad.
+const ENTRY_DEBOUNCE_RANGE: (Duration, Duration) =
+    (Duration::from_millis(30), Duration::from_millis(150));
+const PRESS_GRACE_RANGE: (Duration, Duration) =
+    (Duration::from_millis(40), Duration::from_millis(250));
+
+/// Most recent landings kept per kind.
+const WINDOW: usize = 32;
+/// Landings of a kind needed before it may influence anything.
+const MIN_SAMPLES: usize = 8;
+/// A stagger longer than this isn't a stagger: it's a deliberate late
+/// finger (e.g. growing a settled touch into a drag), and must not drag
+/// the windows out with it.
+const STAGGER_CAP: Duration = Duration::from_millis(300);
+/// Headroom added on top of the observed stagger percentiles.
+const MARGIN: Duration = Duration::from_millis(10);
+/// Largest change applied per adjustment, so a burst of odd touches
+/// can't swing the feel of the pad all at once.
+const MAX_STEP: Duration = Duration::from_millis(10);
+/// Differences smaller than this are noise, not worth an adjustment.
+const DEADBAND: Duration = Duration::from_millis(5);
+
+/// The values the learner owns, as persisted between runs.
+#[serde_with::serde_as]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Learned {
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    pub entry_debounce: Duration,
+    #[serde_as(as = "serde_with::DurationMilliSeconds<u64>")]
+    pub press_grace: Duration,
+}
+
+pub struct StaggerLearner {
+    drags: VecDeque<Duration>,
+    swipes: VecDeque<Duration>,
+    probe_delay: Duration,
+    learned: Learned,
+}
+
+impl StaggerLearner {
+    /// Starts from previously `saved` values if there are any, else from
+    /// the configured `base` timing. Either way the starting point is
+    /// clamped into the learner's bounds.
+    pub fn new(base: &Timing, saved: Option<Learned>) -> Self {
+        let start = saved.unwrap_or(Learned {
+            entry_debounce: base.entry_debounce,
+            press_grace: base.press_grace,
+        });
+        let mut learner = StaggerLearner {
+            drags: VecDeque::with_capacity(WINDOW),
+            swipes: VecDeque::with_capacity(WINDOW),
+            probe_delay: base.probe_delay,
+            learned: start,
+        };
+        learner.learned = learner.clamp(start);
+        learner
+    }
+
+    /// Hot-reload hook: a new config may move `probe_delay`, which is the
+    /// floor for the debounce window. Learned values are kept otherwise.
+    pub fn rebase(&mut self, base: &Timing) {
+        self.probe_delay = base.probe_delay;
+        self.learned = self.clamp(self.learned);
+    }
+
+    /// `timing` with the learned windows substituted in.
+    pub fn apply(&self, timing: Timing) -> Timing {
+        Timing {
+            entry_debounce: self.learned.entry_debounce,
+            press_grace: self.learned.press_grace,
+            ..timing
+        }
+    }
+
+    /// Records one finished touch. Returns the new values if this
+    /// observation moved either window.
+    pub fn observe(&mut self, landing: Landing) -> Option<Learned> {
+        let (samples, stagger) = match landing {
+            Landing::Drag(d) => (&mut self.drags, d),
+            Landing::Swipe(d) => (&mut self.swipes, d),
+        };
+        if stagger > STAGGER_CAP {
+            return None;
+        }
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(stagger);
+
+        let current = self.learned;
+        let mut target = current;
+
+        // Debounce: late enough for the slow end of 3-finger landings,
+        // and for the typical 4-finger one.
+        let drag_need = percentile(&self.drags, 90);
+        let swipe_need = percentile(&self.swipes, 75);
+        if let Some(need) = drag_need.max(swipe_need) {
+            target.entry_debounce = need + MARGIN;
+        }
+        // Grace: whatever of the slow end of 4th fingers the debounce
+        // window doesn't already cover.
+        if let Some(late) = percentile(&self.swipes, 95) {
+            target.press_grace = late.saturating_sub(target.entry_debounce) + MARGIN;
+        }
+
+        let target = self.clamp(target);
+        let next = self.clamp(Learned {
+            entry_debounce: step_toward(current.entry_debounce, target.entry_debounce),
+            press_grace: step_toward(current.press_grace, target.press_grace),
+        });
+        if next == current {
+            return None;
+        }
+        self.learned = next;
+        Some(next)
+    }
+
+    fn clamp(&self, l: Learned) -> Learned {
+        let lo = ENTRY_DEBOUNCE_RANGE.0.max(self.probe_delay);
+        let hi = ENTRY_DEBOUNCE_RANGE.1.min(MAX_ENTRY_DEBOUNCE).max(lo);
+        Learned {
+            entry_debounce: l.entry_debounce.clamp(lo, hi),
+            press_grace: l.press_grace.clamp(
+                PRESS_GRACE_RANGE.0,
+                PRESS_GRACE_RANGE.1.min(MAX_PRESS_GRACE),
+            ),
+        }
+    }
+}
+
+/// The `pct`th percentile of `samples` (nearest-rank), or None until
+/// there are enough samples to mean anything.
+fn percentile(samples: &VecDeque<Duration>, pct: usize) -> Option<Duration> {
+    if samples.len() < MIN_SAMPLES {
+        return None;
+    }
+    let mut sorted: Vec<Duration> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * pct).div_ceil(100).clamp(1, sorted.len());
+    Some(sorted[rank - 1])
+}
+
+fn step_toward(from: Duration, to: Duration) -> Duration {
+    if from.abs_diff(to) < DEADBAND {
+        from
+    } else if to > from {
+        from + (to - from).min(MAX_STEP)
+    } else {
+        from - (from - to).min(MAX_STEP)
+    }
+}
+
+// ---- persistence (the shell's side) -----------------------------------
+
+/// Where the learned values for the device identified by `device_key`
+/// are kept.
+pub fn state_path(device_key: &str) -> Result<PathBuf, std::io::Error> {
+    Ok(get_state_dir()?.join(format!("adaptive-{device_key}.json")))
+}
+
+/// Previously learned values, if any were saved (and are still readable).
+pub fn load(path: &Path) -> Option<Learned> {
+    let json = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+pub fn save(path: &Path, learned: &Learned) -> Result<(), std::io::Error> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(learned)?)
+}
@@ -0,0 +1,128 @@
+//! Config hot reload, driven by inotify instead of polling.
+//!
+//! Two watches, because editors save in two different ways. Some write
+//! the file in place, which a watch on the file itself sees (and which
+//! also follows a symlinked config to wherever it really lives). Most
+//! write a temporary file and atomically rename it over the original:
+//! the watched inode is then simply unlinked, never modified, so only a
+//! watch on the *directory* sees the new file arrive. The directory
+//! watch also catches a config file being created, or one of the other
+//! formats appearing and taking precedence.
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+use crate::init::config::CONFIG_FILE_NAMES;
+
+const DIR_MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_TO
+    | libc::IN_MOVED_FROM
+    | libc::IN_CREATE
+    | libc::IN_DELETE
+    | libc::IN_ONLYDIR;
+const FILE_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MODIFY | libc::IN_ATTRIB;
+
+pub struct ConfigWatch {
+    fd: OwnedFd,
+    file_wd: Option<i32>,
+    buf: Vec<u8>,
+}
+
+impl AsRawFd for ConfigWatch {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+fn add_watch(fd: RawFd, path: &Path, mask: u32) -> io::Result<i32> {
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let wd = unsafe { libc::inotify_add_watch(fd, c_path.as_ptr(), mask) };
+    if wd < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(wd)
+    }
+}
+
+impl ConfigWatch {
+    /// Watches `dir` (which must exist) and, if it exists, the config
+    /// file `file` in it.
+    pub fn new(dir: &Path, file: &Path) -> io::Result<Self> {
+        let raw = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if raw < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+        add_watch(raw, dir, DIR_MASK)?;
+        let mut watch = ConfigWatch {
+            fd,
+            file_wd: None,
+            buf: vec![0; 4096],
+        };
+        watch.rearm(file);
+        Ok(watch)
+    }
+
+    /// Points the file watch at `file` -- after a rename-over-save, the
+    /// old watch is following a deleted inode. A missing file just means
+    /// no file watch until it shows up (which the directory watch sees).
+    pub fn rearm(&mut self, file: &Path) {
+        if let Some(wd) = self.file_wd.take() {
+            unsafe { libc::inotify_rm_watch(self.fd.as_raw_fd(), wd) };
+        }
+        if let Ok(wd) = add_watch(self.fd.as_raw_fd(), file, FILE_MASK) {
+            self.file_wd = Some(wd);
+        }
+    }
+
+    /// Consumes every pending event. Returns whether any of them could
+    /// have changed the configuration.
+    pub fn changed(&mut self) -> io::Result<bool> {
+        let mut changed = false;
+        loop {
+            let n = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    self.buf.as_mut_ptr().cast(),
+                    self.buf.len(),
+                )
+            };
+            if n < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(changed);
+                }
+                return Err(err);
+            }
+            let n = n as usize;
+            if n == 0 {
+                return Ok(changed);
+            }
+            let header = std::mem::size_of::<libc::inotify_event>();
+            let mut offset = 0;
+            while offset + header <= n {
+                let event: libc::inotify_event =
+                    unsafe { std::ptr::read_unaligned(self.buf[offset..].as_ptr().cast()) };
+                let name = &self.buf[offset + header..offset + header + event.len as usize];
+                let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+                offset += header + event.len as usize;
+
+                let on_file = self.file_wd == Some(event.wd);
+                let on_config_name = CONFIG_FILE_NAMES
+                    .iter()
+                    .any(|config| config.as_bytes() == name);
+                // IN_Q_OVERFLOW: events were lost, so assume the worst
+                if on_file || on_config_name || event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    changed = true;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
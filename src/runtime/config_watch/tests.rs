@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::PathBuf;
+
+use super::*;
+
+/// A fresh directory holding a JSON config, watched.
+fn watched(name: &str) -> (PathBuf, PathBuf, ConfigWatch) {
+    let dir = std::env::temp_dir().join(format!("3fd-config-watch-{}-{name}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("3fd-config.json");
+    fs::write(&file, "{}").unwrap();
+    let watch = ConfigWatch::new(&dir, &file).unwrap();
+    (dir, file, watch)
+}
+
+#[test]
+fn an_in_place_write_is_a_change() {
+    let (_, file, mut watch) = watched("in-place");
+    assert!(!watch.changed().unwrap());
+    fs::write(&file, r#"{"acceleration": 1.5}"#).unwrap();
+    assert!(watch.changed().unwrap());
+    // every event was consumed
+    assert!(!watch.changed().unwrap());
+}
+
+#[test]
+fn a_rename_over_the_file_is_a_change_and_the_watch_follows_it() {
+    let (dir, file, mut watch) = watched("rename");
+    let tmp = dir.join(".3fd-config.json.swp");
+    fs::write(&tmp, r#"{"acceleration": 1.5}"#).unwrap();
+    fs::rename(&tmp, &file).unwrap();
+    assert!(watch.changed().unwrap());
+
+    watch.rearm(&file);
+    fs::write(&file, "{}").unwrap();
+    assert!(watch.changed().unwrap());
+}
+
+#[test]
+fn another_format_appearing_is_a_change() {
+    let (dir, _, mut watch) = watched("other-format");
+    fs::write(dir.join("3fd-config.toml"), "acceleration = 1.5\n").unwrap();
+    assert!(watch.changed().unwrap());
+}
+
+#[test]
+fn an_unrelated_file_is_not_a_change() {
+    let (dir, _, mut watch) = watched("unrelated");
+    let notes = dir.join("notes.txt");
+    fs::write(&notes, "hello").unwrap();
+    fs::rename(&notes, dir.join("notes.old")).unwrap();
+    fs::remove_file(dir.join("notes.old")).unwrap();
+    assert!(!watch.changed().unwrap());
+}
+
+/// A symlinked config is written where it really lives, which only the
+/// file watch sees.
+#[test]
+fn a_write_through_a_symlink_is_a_change() {
+    let (dir, _, _) = watched("symlink");
+    let real = dir.join("real");
+    fs::create_dir(&real).unwrap();
+    let target = real.join("3fd-config.json");
+    fs::write(&target, "{}").unwrap();
+    let link = dir.join("linked");
+    fs::create_dir(&link).unwrap();
+    let file = link.join("3fd-config.json");
+    std::os::unix::fs::symlink(&target, &file).unwrap();
+
+    let mut watch = ConfigWatch::new(&link, &file).unwrap();
+    fs::write(&target, r#"{"acceleration": 1.5}"#).unwrap();
+    assert!(watch.changed().unwrap());
+}
@@ -0,0 +1,104 @@
+//! The control socket: how the CLI queries a running daemon.
+//!
+//! A Unix socket in `$XDG_RUNTIME_DIR/linux-3-finger-drag/`. The
+//! protocol is as small as it gets: connecting *is* the query. The
+//! daemon writes its current metrics report and closes the connection,
+//! so serving a query never makes the event loop wait on a client.
+//!
+//! There is one socket per user, but an instance per touchpad: the first
+//! instance to bind it keeps it. A later one finds it answering and
+//! goes without, rather than taking it over, so a query always reaches
+//! the instance that owns the socket.
+
+use std::io::{self, Read};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use tokio::net::{UnixListener, UnixStream};
+
+use crate::init::config::get_runtime_dir;
+
+pub fn socket_path() -> io::Result<PathBuf> {
+    Ok(get_runtime_dir()?.join("control.sock"))
+}
+
+/// The daemon's end. Removes its socket file when dropped, unless
+/// another instance has bound the path since.
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+    /// The socket file's inode, to tell ours from a successor's.
+    ino: u64,
+}
+
+impl ControlSocket {
+    pub fn bind() -> io::Result<Self> {
+        Self::bind_at(&socket_path()?)
+    }
+
+    /// [`bind`](Self::bind), at `path`.
+    fn bind_at(path: &Path) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!(
+                    "another instance answers at {}; it keeps the socket",
+                    path.display()
+                ),
+            ));
+        }
+        // a socket file left behind by a crashed instance would make the
+        // bind fail with EADDRINUSE forever
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        let ino = std::fs::metadata(path)?.ino();
+        Ok(ControlSocket {
+            listener,
+            path: path.to_path_buf(),
+            ino,
+        })
+    }
+
+    pub async fn accept(&self) -> io::Result<UnixStream> {
+        self.listener.accept().await.map(|(stream, _)| stream)
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        if std::fs::metadata(&self.path).is_ok_and(|meta| meta.ino() == self.ino) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Writes `reply` to a freshly accepted client without waiting on it. A
+/// report is a few hundred bytes, far below the socket buffer, so a
+/// single non-blocking write delivers it whole; a client that went away
+/// in the meantime is simply dropped.
+pub fn reply(stream: UnixStream, reply: &str) {
+    let _ = stream.try_write(reply.as_bytes());
+}
+
+/// The CLI's end: connects to the running daemon and returns its report.
+pub fn query() -> io::Result<String> {
+    let path = socket_path()?;
+    let mut stream = std::os::unix::net::UnixStream::connect(&path).map_err(|e| {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "could not reach a running linux-3-finger-drag at {}: {e}",
+                path.display()
+            ),
+        )
+    })?;
+    let mut report = String::new();
+    stream.read_to_string(&mut report)?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,40 @@
+use super::*;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("3fd-control-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir.join("control.sock")
+}
+
+#[tokio::test]
+async fn a_live_socket_is_not_taken_over() {
+    let path = scratch("live");
+    let first = ControlSocket::bind_at(&path).unwrap();
+    let err = ControlSocket::bind_at(&path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+
+    // the first instance's socket is untouched, and still answers
+    drop(first.accept().await.unwrap()); // the refused instance's probe
+    let _client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    drop(first.accept().await.unwrap());
+
+    drop(first);
+    assert!(!path.exists());
+}
+
+#[tokio::test]
+async fn a_stale_socket_is_replaced_and_a_successors_is_left_alone() {
+    let path = scratch("stale");
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    // left behind by a crash: the file, with nobody listening
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let old = ControlSocket::bind_at(&path).unwrap();
+
+    // the old instance hung; a new one found its socket dead and took over
+    std::fs::remove_file(&path).unwrap();
+    let new = ControlSocket::bind_at(&path).unwrap();
+    drop(old);
+    assert!(path.exists(), "the predecessor must not delete it");
+    drop(new);
+    assert!(!path.exists());
+}
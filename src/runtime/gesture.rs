@@ -30,10 +30,18 @@
 //!   (hysteresis: staggered liftoff must not leak trailing 1-2 finger
 //!   touches, which libinput would read as a right-click tap).
 //! * A settled non-drag touch is relayed live, frame by frame, verbatim.
+//!
+//! Every touch gets an ID and a tracing span, and every state transition
+//! above is emitted as a structured debug event under [`TRACE_TARGET`]
+//! (`touch`, `transition` reason code, `fingers`, `max_fingers`), which
+//! the optional `traceFile` captures as JSON lines.
 
 use std::time::{Duration, Instant};
 
-use tracing::{debug, warn};
+use serde::Deserialize;
+use tracing::{debug, warn, Span};
+
+use super::metrics::Metrics;
 
 pub const EV_SYN: u16 = 0x00;
 pub const EV_KEY: u16 = 0x01;
@@ -41,9 +49,29 @@ pub const EV_ABS: u16 = 0x03;
 pub const SYN_REPORT: u16 = 0x00;
 pub const SYN_DROPPED: u16 = 0x03;
 pub const ABS_MT_SLOT: u16 = 0x2f;
-pub const ABS_MT_TRACKING_ID: u16 = 0x39;
+pub const ABS_MT_TOUCH_MAJOR: u16 = 0x30;
+pub const ABS_MT_TOUCH_MINOR: u16 = 0x31;
+pub const ABS_MT_WIDTH_MAJOR: u16 = 0x32;
+pub const ABS_MT_WIDTH_MINOR: u16 = 0x33;
+pub const ABS_MT_ORIENTATION: u16 = 0x34;
 pub const ABS_MT_POSITION_X: u16 = 0x35;
 pub const ABS_MT_POSITION_Y: u16 = 0x36;
+pub const ABS_MT_TOOL_TYPE: u16 = 0x37;
+pub const ABS_MT_BLOB_ID: u16 = 0x38;
+pub const ABS_MT_TRACKING_ID: u16 = 0x39;
+pub const ABS_MT_PRESSURE: u16 = 0x3a;
+pub const ABS_MT_DISTANCE: u16 = 0x3b;
+pub const ABS_MT_TOOL_X: u16 = 0x3c;
+pub const ABS_MT_TOOL_Y: u16 = 0x3d;
+pub const BTN_LEFT: u16 = 0x110;
+pub const BTN_TOOL_FINGER: u16 = 0x145;
+pub const BTN_TOOL_QUINTTAP: u16 = 0x148;
+pub const BTN_TOOL_DOUBLETAP: u16 = 0x14d;
+pub const BTN_TOOL_TRIPLETAP: u16 = 0x14e;
+pub const BTN_TOOL_QUADTAP: u16 = 0x14f;
+
+/// The tracing target of the per-touch decision trace.
+pub const TRACE_TARGET: &str = "3fd::trace";
 
 /// Hard upper bound on tracked slots; the effective count comes from the
 /// device's ABS_MT_SLOT range at construction.
@@ -86,6 +114,11 @@ pub enum Output {
     MouseDown,
     /// Release the virtual mouse's left button.
     MouseUp,
+    /// Press the virtual mouse's right button (a drag switched to it, see
+    /// [`ClickAction::RightDrag`]).
+    RightDown,
+    /// Release the virtual mouse's right button.
+    RightUp,
     /// Move the cursor by whole pixels (fractional remainders are
     /// carried inside the machine so slow drags don't lose motion).
     MouseMove { dx: i32, dy: i32 },
@@ -117,26 +150,289 @@ pub struct Timing {
     pub px_per_mm: f64,
 }
 
-#[derive(Clone, Copy)]
-struct Slot {
-    tracking_id: i32,
-    x: i32,
-    y: i32,
+/// What a physical click (the clickpad's own BTN_LEFT) in the middle of
+/// a drag does to it (`clickDuringDrag`). Whichever it is, the click
+/// itself never reaches the clone: the compositor didn't see the drag,
+/// so it mustn't see a click the user made as part of it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClickAction {
+    /// Nothing: the drag carries on.
+    #[default]
+    Ignore,
+    /// Ends the drag: the button is released, and the rest of the touch
+    /// does nothing.
+    Drop,
+    /// Locks the drag: after the fingers lift, the button stays held --
+    /// for no set time -- and a new 3-finger touch continues the drag,
+    /// until another click during it unlocks it or any other touch
+    /// releases it.
+    ToggleLock,
+    /// Carries on dragging with the right button instead of the left.
+    RightDrag,
+}
+
+/// The ABS_MT_* axes a slot carries besides its tracking ID and
+/// position.
+pub const MT_EXTRA_AXES: [u16; 11] = [
+    ABS_MT_TOUCH_MAJOR,
+    ABS_MT_TOUCH_MINOR,
+    ABS_MT_WIDTH_MAJOR,
+    ABS_MT_WIDTH_MINOR,
+    ABS_MT_ORIENTATION,
+    ABS_MT_TOOL_TYPE,
+    ABS_MT_BLOB_ID,
+    ABS_MT_PRESSURE,
+    ABS_MT_DISTANCE,
+    ABS_MT_TOOL_X,
+    ABS_MT_TOOL_Y,
+];
+
+/// Everything known about one slot. The kernel keeps a slot's values
+/// across touches and only reports the ones that change, so these
+/// persist across touches too.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SlotState {
+    pub tracking_id: i32,
+    pub x: i32,
+    pub y: i32,
+    /// The [`MT_EXTRA_AXES`], in that order; `None` for one the device
+    /// hasn't reported (or doesn't have).
+    pub extra: [Option<i32>; MT_EXTRA_AXES.len()],
+}
+
+impl SlotState {
+    /// No finger, nothing else known.
+    pub const UP: SlotState = SlotState::new(-1, 0, 0);
+
+    pub const fn new(tracking_id: i32, x: i32, y: i32) -> Self {
+        SlotState {
+            tracking_id,
+            x,
+            y,
+            extra: [None; MT_EXTRA_AXES.len()],
+        }
+    }
+
+    /// Sets `code`'s value, if it's one of the slot's axes.
+    pub fn set(&mut self, code: u16, value: i32) {
+        match code {
+            ABS_MT_TRACKING_ID => self.tracking_id = value,
+            ABS_MT_POSITION_X => self.x = value,
+            ABS_MT_POSITION_Y => self.y = value,
+            _ => {
+                if let Some(i) = MT_EXTRA_AXES.iter().position(|&c| c == code) {
+                    self.extra[i] = Some(value);
+                }
+            }
+        }
+    }
+}
+
+/// How a finished touch's fingers came down, reported to the shell for
+/// the adaptive learner (see `adaptive.rs`). The duration is the landing
+/// stagger: from the first finger down to the finger that completed the
+/// gesture.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Landing {
+    /// A touch that ended as a 3-finger drag committed inside the entry
+    /// window: first finger to third. A settled touch that grew into a
+    /// drag later reports nothing -- its third finger was deliberate,
+    /// not stagger.
+    Drag(Duration),
+    /// A 4+ finger gesture (including aborted drags): first finger to
+    /// fourth.
+    Swipe(Duration),
+}
+
+/// Something the machine just did that is worth preserving the flight
+/// recorder for (see `recorder.rs`).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Incident {
+    /// A late 4th finger aborted a drag whose button was already
+    /// pressed: the phantom click.
+    AbortAfterPress,
+}
+
+impl Incident {
+    pub fn reason(self) -> &'static str {
+        match self {
+            Incident::AbortAfterPress => "abort-after-press",
+        }
+    }
+}
+
+/// What finally pressed a committed drag's button (see
+/// [`Timing::press_grace`]).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PressCause {
+    /// The first real drag motion.
+    Motion,
+    /// The press grace ran out on a stationary drag.
+    Grace,
+    /// A stationary drag lifted before the grace ran out.
+    Liftoff,
+    /// A click locked a drag that hadn't pressed yet.
+    Click,
+}
+
+/// A decision the machine made, for a shell that reports them as they
+/// happen (`--observe`; see
+/// [`collect_decisions`](GestureMachine::collect_decisions)).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Decision {
+    pub touch: u64,
+    /// The transition's stable reason code, as in the decision trace.
+    pub reason: &'static str,
+    pub description: &'static str,
+    pub fingers: usize,
 }
 
-impl Default for Slot {
-    fn default() -> Self {
-        Slot {
-            tracking_id: -1,
-            x: 0,
-            y: 0,
+/// One step in a touch's life, as reported in the decision trace (and
+/// counted in the metrics).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Transition {
+    /// A fresh touch is being withheld until classified.
+    Buffered,
+    /// A lone finger outlasted the probe delay.
+    SettledSingle,
+    /// The debounce window closed on a 2-3 finger non-drag touch.
+    SettledByDebounce,
+    /// The touch reached 4+ fingers inside the window.
+    SettledBy4Plus,
+    /// The touch ended before any decision (a tap), and was replayed.
+    SettledTap,
+    /// The debounce window closed on a stable 3-finger touch.
+    Committed,
+    /// A settled touch grew to exactly 3 fingers.
+    CommittedLate,
+    Pressed(PressCause),
+    /// A 4th finger arrived after commit; the touch was handed over.
+    Late4thAbort {
+        pressed: bool,
+    },
+    /// Every finger of a drag lifted.
+    DragEnded,
+    DragLockArmed,
+    DragLockResumed,
+    DragLockExpired,
+    /// A non-drag touch released a held drag-lock.
+    DragLockCancelled,
+    /// A physical click during the drag, and what it did.
+    Click(ClickAction),
+    /// Resync: the clone was sent an authoritative correction.
+    ResyncCorrection,
+    /// Resync during a drag: motion re-baselined.
+    ResyncRebaseline,
+    /// Resync revealed a still-buffered touch had fully ended.
+    ResyncSwallowed,
+    /// Everything dropped and restarted from a snapshot (resume).
+    Reset,
+    /// The stuck-button watch forced the button up.
+    ForcedRelease,
+}
+
+impl Transition {
+    /// Stable reason code, for grepping and diffing traces.
+    fn reason(self) -> &'static str {
+        match self {
+            Transition::Buffered => "buffered",
+            Transition::SettledSingle => "settled-single",
+            Transition::SettledByDebounce => "settled-by-debounce",
+            Transition::SettledBy4Plus => "settled-by-4+",
+            Transition::SettledTap => "settled-tap",
+            Transition::Committed => "committed",
+            Transition::CommittedLate => "committed-late-growth",
+            Transition::Pressed(PressCause::Motion) => "pressed-by-motion",
+            Transition::Pressed(PressCause::Grace) => "pressed-by-grace",
+            Transition::Pressed(PressCause::Liftoff) => "pressed-by-liftoff",
+            Transition::Pressed(PressCause::Click) => "pressed-by-click",
+            Transition::Late4thAbort { pressed: false } => "late-4th-abort",
+            Transition::Late4thAbort { pressed: true } => "late-4th-abort-after-press",
+            Transition::DragEnded => "drag-ended",
+            Transition::DragLockArmed => "drag-lock-armed",
+            Transition::DragLockResumed => "drag-lock-resumed",
+            Transition::DragLockExpired => "drag-lock-expired",
+            Transition::DragLockCancelled => "drag-lock-cancelled",
+            Transition::Click(ClickAction::Ignore) => "click-ignored",
+            Transition::Click(ClickAction::Drop) => "click-dropped-drag",
+            Transition::Click(ClickAction::ToggleLock) => "click-toggled-lock",
+            Transition::Click(ClickAction::RightDrag) => "click-right-drag",
+            Transition::ResyncCorrection => "resync-correction",
+            Transition::ResyncRebaseline => "resync-rebaseline",
+            Transition::ResyncSwallowed => "resync-swallowed",
+            Transition::Reset => "reset",
+            Transition::ForcedRelease => "forced-release",
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Transition::Buffered => "new touch buffered until classified",
+            Transition::SettledSingle => "lone finger settled after the probe; relaying live",
+            Transition::SettledByDebounce => "debounce closed on a non-drag touch; relaying live",
+            Transition::SettledBy4Plus => "4+ fingers; relaying live",
+            Transition::SettledTap => "touch ended undecided; replayed as a tap",
+            Transition::Committed => "3-finger touch committed as a drag",
+            Transition::CommittedLate => "settled touch grew to 3 fingers; committed as a drag",
+            Transition::Pressed(_) => "drag button pressed",
+            Transition::Late4thAbort { .. } => {
+                "late 4th finger: aborting committed drag, handing touch to compositor"
+            }
+            Transition::DragEnded => "all fingers lifted; drag ended",
+            Transition::DragLockArmed => "drag-lock armed; button stays held",
+            Transition::DragLockResumed => "new 3-finger touch resumed the locked drag",
+            Transition::DragLockExpired => "drag-lock expired; button released",
+            Transition::DragLockCancelled => "other touch released the drag-lock",
+            Transition::Click(ClickAction::Ignore) => "click during the drag; ignored",
+            Transition::Click(ClickAction::Drop) => {
+                "click during the drag; dropped it, rest of the touch ignored"
+            }
+            Transition::Click(ClickAction::ToggleLock) => "click during the drag; toggled its lock",
+            Transition::Click(ClickAction::RightDrag) => {
+                "click during the drag; switched to the right button"
+            }
+            Transition::ResyncCorrection => "resync: corrected the clone's slot state",
+            Transition::ResyncRebaseline => "resync during drag: re-baselined motion",
+            Transition::ResyncSwallowed => {
+                "resync: buffered touch ended inside the drop; swallowed"
+            }
+            Transition::Reset => "reset: clone lifted, button released, touch state dropped",
+            Transition::ForcedRelease => {
+                "button forced up by the stuck-button watch; drag forgotten"
+            }
         }
     }
 }
 
-/// Authoritative per-slot state, as re-read from the kernel after a
-/// SYN_DROPPED (EVIOCGMTSLOTS). `(tracking_id, x, y)`.
-pub type SlotSnapshot = [(i32, i32, i32)];
+/// The real device's authoritative state, as re-read from the kernel
+/// after a SYN_DROPPED (or a resume).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Per slot (EVIOCGMTSLOTS).
+    pub slots: Vec<SlotState>,
+    /// Every key the device has, pressed or not (EVIOCGKEY): BTN_TOUCH,
+    /// BTN_TOOL_*, a clickpad's BTN_LEFT. Empty when not known.
+    pub keys: Vec<(u16, i32)>,
+    /// Every absolute axis's current value (EVIOCGABS). Besides the
+    /// legacy single-touch axes, this holds ABS_MT_SLOT: the slot the
+    /// device's next frame continues without saying so.
+    pub abs: Vec<(u16, i32)>,
+}
+
+impl Snapshot {
+    /// Just the slots, with key and axis state unknown.
+    pub fn of_slots(slots: &[SlotState]) -> Self {
+        Snapshot {
+            slots: slots.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    fn abs_value(&self, code: u16) -> Option<i32> {
+        self.abs.iter().find(|a| a.0 == code).map(|a| a.1)
+    }
+}
 
 pub struct GestureMachine {
     timing: Timing,
@@ -144,7 +440,7 @@ pub struct GestureMachine {
     y_res: f64,
     slot_count: usize,
 
-    slots: [Slot; MAX_SLOTS],
+    slots: [SlotState; MAX_SLOTS],
     current_slot: usize,
     /// Which slots the *synthetic clone* currently believes are active.
     relayed_active: [bool; MAX_SLOTS],
@@ -158,6 +454,14 @@ pub struct GestureMachine {
     /// While a committed drag hasn't moved yet: when to press the button
     /// anyway (see [`Timing::press_grace`]). Cleared once pressed.
     press_deadline: Option<Instant>,
+    /// What a physical click during a drag does.
+    click_action: ClickAction,
+    /// A drag a click (or a forced release) dropped: the touch is still
+    /// ours, but drives nothing.
+    drag_dropped: bool,
+    /// The real BTN_LEFT went down during a drag and hasn't come up
+    /// yet: the clone is never told of it.
+    click_swallowed: bool,
 
     /// Last EV_KEY values seen from the REAL device (BTN_TOUCH,
     /// BTN_TOOL_*...). The truth about tool state on the pad.
@@ -176,12 +480,32 @@ pub struct GestureMachine {
     touch_start: Option<Instant>,
     touch_max: usize,
     settled: bool,
+    /// When the current touch first reached 3 and 4 fingers.
+    reached_3: Option<Instant>,
+    reached_4: Option<Instant>,
+    /// Finished touches' landing staggers, not yet collected by the
+    /// shell (see [`take_landings`](Self::take_landings)).
+    landings: Vec<Landing>,
+    incident: Option<Incident>,
+    /// Decisions not yet collected by the shell, if it asked for them.
+    decisions: Option<Vec<Decision>>,
 
-    /// Virtual left button state (survives across touches for drag-lock).
+    /// Virtual button state (survives across touches for drag-lock).
     held: bool,
+    /// The held button is the right one ([`ClickAction::RightDrag`]).
+    held_right: bool,
     /// When set, the button stays held until this instant unless a new
     /// touch resolves the lock first (see [`Timing::drag_end_delay`]).
     lock_deadline: Option<Instant>,
+    /// The drag was locked by a click ([`ClickAction::ToggleLock`]):
+    /// lifting holds the button until a new touch resolves the lock,
+    /// however long that takes.
+    lock_latched: bool,
+
+    metrics: Metrics,
+    /// ID of the current (or most recent) touch, and its tracing span.
+    touch_id: u64,
+    span: Span,
 }
 
 impl GestureMachine {
@@ -191,7 +515,7 @@ impl GestureMachine {
             x_res: x_res.max(1.0),
             y_res: y_res.max(1.0),
             slot_count: slot_count.clamp(1, MAX_SLOTS),
-            slots: [Slot::default(); MAX_SLOTS],
+            slots: [SlotState::UP; MAX_SLOTS],
             current_slot: 0,
             relayed_active: [false; MAX_SLOTS],
             suppressing: false,
@@ -199,14 +523,27 @@ impl GestureMachine {
             drag_last_pos: None,
             carry: (0.0, 0.0),
             press_deadline: None,
+            click_action: ClickAction::Ignore,
+            drag_dropped: false,
+            click_swallowed: false,
             real_keys: Vec::new(),
             clone_keys: Vec::new(),
             pending: Vec::new(),
             touch_start: None,
             touch_max: 0,
             settled: false,
+            reached_3: None,
+            reached_4: None,
+            landings: Vec::new(),
+            incident: None,
+            decisions: None,
             held: false,
+            held_right: false,
             lock_deadline: None,
+            lock_latched: false,
+            metrics: Metrics::default(),
+            touch_id: 0,
+            span: Span::none(),
         }
     }
 
@@ -216,12 +553,63 @@ impl GestureMachine {
         self.timing = timing;
     }
 
+    /// Hot-reload hook for `clickDuringDrag`; a drag in progress keeps
+    /// what earlier clicks did to it.
+    pub fn set_click_action(&mut self, action: ClickAction) {
+        self.click_action = action;
+    }
+
     /// Whether the virtual button is currently held (used by the shell
     /// for a defensive release on shutdown).
     pub fn button_held(&self) -> bool {
         self.held
     }
 
+    /// Whether the drag is locked by a click, holding the button until a
+    /// new touch resolves the lock (used by the stuck-button watch).
+    pub fn lock_latched(&self) -> bool {
+        self.lock_latched
+    }
+
+    /// Drains the landing staggers of every touch that finished since
+    /// the last call. Only meaningful to the adaptive learner; the
+    /// buffer is bounded by how often the shell calls this (after every
+    /// batch of outputs), so it never grows unattended.
+    pub fn take_landings(&mut self) -> Vec<Landing> {
+        std::mem::take(&mut self.landings)
+    }
+
+    /// Starts keeping every decision (all transitions but the buffering
+    /// of a fresh touch) for [`take_decisions`](Self::take_decisions).
+    pub fn collect_decisions(&mut self) {
+        self.decisions.get_or_insert_with(Vec::new);
+    }
+
+    /// Drains the decisions made since the last call (none unless
+    /// [`collect_decisions`](Self::collect_decisions) was called).
+    pub fn take_decisions(&mut self) -> Vec<Decision> {
+        self.decisions
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// The incident raised by the last frame, tick or resync, if any.
+    pub fn take_incident(&mut self) -> Option<Incident> {
+        self.incident.take()
+    }
+
+    /// Decision counters and latencies so far (see `metrics.rs`).
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// For the shell's own measurements, and for carrying the totals
+    /// over to a new machine when the device is re-acquired.
+    pub fn metrics_mut(&mut self) -> &mut Metrics {
+        &mut self.metrics
+    }
+
     /// The next instant at which [`on_tick`](Self::on_tick) has work to
     /// do, if any. The I/O loop sleeps exactly until this, so decisions
     /// land on time instead of on the next poll interval.
@@ -256,7 +644,7 @@ impl GestureMachine {
             if !self.held {
                 if let Some(deadline) = self.press_deadline {
                     if now >= deadline {
-                        self.press_button(&mut out);
+                        self.press_button(PressCause::Grace, &mut out);
                     }
                 }
             }
@@ -267,6 +655,7 @@ impl GestureMachine {
             if let Some(deadline) = self.lock_deadline {
                 if now >= deadline {
                     self.lock_deadline = None;
+                    self.transition(Transition::DragLockExpired);
                     self.release_button(&mut out);
                 }
             }
@@ -277,11 +666,13 @@ impl GestureMachine {
             return out;
         }
 
-        let count = self.active_count();
+        let count = self.finger_count();
         let start = self.touch_start.expect("guarded by is_none() above");
 
         if count == 1 && self.touch_max == 1 && now >= start + self.timing.probe_delay {
             self.settled = true;
+            self.note_decision(now);
+            self.transition(Transition::SettledSingle);
             self.flush_pending(&mut out);
             return out;
         }
@@ -309,12 +700,10 @@ impl GestureMachine {
                 ABS_MT_SLOT => {
                     self.current_slot = (ev.value.max(0) as usize).min(self.slot_count - 1);
                 }
-                ABS_MT_TRACKING_ID => self.slots[self.current_slot].tracking_id = ev.value,
-                ABS_MT_POSITION_X => self.slots[self.current_slot].x = ev.value,
-                ABS_MT_POSITION_Y => self.slots[self.current_slot].y = ev.value,
-                _ => {}
+                code => self.slots[self.current_slot].set(code, ev.value),
             }
         }
+        self.note_click_release();
         // 2. decide what to do about it
         let mut out = Vec::new();
         self.decide(frame, now, &mut out);
@@ -323,18 +712,11 @@ impl GestureMachine {
 
     /// Reconcile with an authoritative kernel snapshot after the kernel
     /// reported dropped events (SYN_DROPPED). Whatever the dropped
-    /// events said is gone; `snapshot` is the truth now.
-    pub fn on_resync(&mut self, snapshot: &SlotSnapshot, now: Instant) -> Vec<Output> {
-        for slot in 0..MAX_SLOTS {
-            self.slots[slot] = match snapshot.get(slot) {
-                Some(&(id, x, y)) => Slot {
-                    tracking_id: id,
-                    x,
-                    y,
-                },
-                None => Slot::default(),
-            };
-        }
+    /// events said is gone; `snapshot` is the truth now -- slots, keys
+    /// and axes alike.
+    pub fn on_resync(&mut self, snapshot: &Snapshot, now: Instant) -> Vec<Output> {
+        self.metrics.resyncs += 1;
+        self.load_snapshot(snapshot);
 
         let mut out = Vec::new();
 
@@ -343,20 +725,25 @@ impl GestureMachine {
             // may have moved the reference finger arbitrarily far, so
             // re-baseline rather than applying the gap as a cursor jump.
             self.drag_last_pos = None;
-        } else if self.touch_start.is_some() && !self.settled && self.active_count() == 0 {
+            self.transition(Transition::ResyncRebaseline);
+        } else if self.touch_start.is_some() && !self.settled && self.finger_count() == 0 {
             // Every finger lifted *inside* the dropped window while the
             // touch was still buffered. The buffer holds touchdowns whose
             // matching releases were dropped -- flushing it would leave
             // the synthetic device holding a touch forever. Swallow the
             // (rare) truncated tap instead; consistency wins.
             self.pending.clear();
+            self.transition(Transition::ResyncSwallowed);
             self.touch_start = None;
             self.touch_max = 0;
         } else {
             // The synthetic device (or the buffer of a still-undecided
             // touch) may hold stale state. Correct it explicitly: release
             // any slot the clone believes is active but no longer is,
-            // then assert the authoritative position of every live slot.
+            // assert the authoritative state of every live slot (with the
+            // legacy single-touch axes alongside), and set every key the
+            // clone will hold wrong -- a BTN_TOUCH, BTN_TOOL_* or clickpad
+            // button that changed inside the drop.
             let mut correction = Vec::new();
             for slot in 0..self.slot_count {
                 if self.relayed_active[slot] && self.slots[slot].tracking_id < 0 {
@@ -364,8 +751,25 @@ impl GestureMachine {
                     correction.push(Ev::abs(ABS_MT_TRACKING_ID, -1));
                 }
             }
-            correction.extend(self.active_slot_dump());
+            let dump = self.active_slot_dump();
+            if !dump.is_empty() {
+                correction.extend(dump);
+                correction.extend(
+                    snapshot
+                        .abs
+                        .iter()
+                        .filter(|&&(code, _)| code < ABS_MT_SLOT)
+                        .map(|&(code, value)| Ev::abs(code, value)),
+                );
+            }
+            let clone_keys = self.keys_after_pending();
+            for (code, value) in self.keys_for_clone() {
+                if Self::key_value(&clone_keys, code) != value {
+                    correction.push(Ev::new(EV_KEY, code, value));
+                }
+            }
             if !correction.is_empty() {
+                self.transition(Transition::ResyncCorrection);
                 if self.touch_start.is_some() && !self.settled {
                     self.pending.extend(correction);
                     self.pending.push(Ev::syn());
@@ -373,6 +777,7 @@ impl GestureMachine {
                     let mut frame = correction;
                     frame.push(Ev::syn());
                     self.mark_relayed();
+                    self.note_clone_keys(&frame);
                     out.push(Output::EmitSynth(frame));
                 }
             }
@@ -386,8 +791,140 @@ impl GestureMachine {
         out
     }
 
+    /// Lets go of the button from outside, for the stuck-button watch:
+    /// the drag it held, and any lock on it, are forgotten, so the next
+    /// touch starts afresh instead of resuming a drag nothing holds. A
+    /// drag still under way ends as if a click had dropped it.
+    pub fn force_release(&mut self) -> Vec<Output> {
+        let mut out = Vec::new();
+        self.transition(Transition::ForcedRelease);
+        self.release_button(&mut out);
+        self.lock_deadline = None;
+        self.press_deadline = None;
+        if self.suppressing {
+            self.drag_dropped = true;
+            self.drag_ref_slot = None;
+            self.drag_last_pos = None;
+        }
+        out
+    }
+
+    /// Drops everything in flight and starts over from `snapshot` -- for
+    /// when the machine's view can't be trusted at all any more, e.g.
+    /// after the system slept through who knows how many frames. Unlike
+    /// [`on_resync`](Self::on_resync), nothing is reconciled: the button
+    /// is released (drag-lock and a pending press forgotten), and the
+    /// clone is told every slot and tool key it holds is up. Fingers
+    /// still down in `snapshot` begin a fresh, undecided touch, seeded
+    /// with their full state and the pad's keys so the clone sees a
+    /// complete touchdown if it's relayed.
+    pub fn reset(&mut self, snapshot: &Snapshot, now: Instant) -> Vec<Output> {
+        let mut out = Vec::new();
+        self.release_button(&mut out);
+        self.lock_deadline = None;
+        self.press_deadline = None;
+        self.suppressing = false;
+        self.drag_ref_slot = None;
+        self.drag_last_pos = None;
+        self.carry = (0.0, 0.0);
+        self.pending.clear();
+        self.touch_start = None;
+        self.touch_max = 0;
+        self.settled = false;
+        self.reached_3 = None;
+        self.reached_4 = None;
+        self.drag_dropped = false;
+        self.real_keys.clear();
+
+        let mut lift = Vec::new();
+        for slot in 0..MAX_SLOTS {
+            if self.relayed_active[slot] {
+                lift.push(Ev::abs(ABS_MT_SLOT, slot as i32));
+                lift.push(Ev::abs(ABS_MT_TRACKING_ID, -1));
+                self.relayed_active[slot] = false;
+            }
+        }
+        for (code, value) in &mut self.clone_keys {
+            if *value != 0 {
+                lift.push(Ev::new(EV_KEY, *code, 0));
+                *value = 0;
+            }
+        }
+        if !lift.is_empty() {
+            lift.push(Ev::syn());
+            out.push(Output::EmitSynth(lift));
+        }
+        self.transition(Transition::Reset);
+
+        self.load_snapshot(snapshot);
+        if self.active_count() > 0 {
+            let mut seed = self.active_slot_dump();
+            // (a click swallowed by a drag stays swallowed)
+            seed.extend(
+                self.keys_for_clone()
+                    .into_iter()
+                    .filter(|&(_, value)| value != 0)
+                    .map(|(code, value)| Ev::new(EV_KEY, code, value)),
+            );
+            seed.push(Ev::syn());
+            self.decide(&seed, now, &mut out);
+        }
+        out
+    }
+
     // ---- internals ----------------------------------------------------
 
+    /// Takes `snapshot` as the truth about every slot, key and the
+    /// current slot. An axis the snapshot doesn't carry keeps the value
+    /// last reported for it, and so do the keys if it has none.
+    fn load_snapshot(&mut self, snapshot: &Snapshot) {
+        for (slot, state) in self.slots.iter_mut().enumerate() {
+            let known = state.extra;
+            *state = snapshot.slots.get(slot).copied().unwrap_or(SlotState::UP);
+            for (value, old) in state.extra.iter_mut().zip(known) {
+                *value = value.or(old);
+            }
+        }
+        for &(code, value) in &snapshot.keys {
+            Self::note_key(&mut self.real_keys, code, value);
+        }
+        if let Some(slot) = snapshot.abs_value(ABS_MT_SLOT) {
+            self.current_slot = (slot.max(0) as usize).min(self.slot_count - 1);
+        }
+        self.note_click_release();
+    }
+
+    /// Ends a swallowed click once the real button is back up.
+    fn note_click_release(&mut self) {
+        if Self::key_value(&self.real_keys, BTN_LEFT) == 0 {
+            self.click_swallowed = false;
+        }
+    }
+
+    /// The real pad's keys as the clone should hold them: all of them,
+    /// but for a swallowed click.
+    fn keys_for_clone(&self) -> Vec<(u16, i32)> {
+        self.real_keys
+            .iter()
+            .map(|&(code, value)| match code {
+                BTN_LEFT if self.click_swallowed => (code, 0),
+                _ => (code, value),
+            })
+            .collect()
+    }
+
+    /// The keys the clone will hold once the pending buffer (if any) is
+    /// flushed.
+    fn keys_after_pending(&self) -> Vec<(u16, i32)> {
+        let mut keys = self.clone_keys.clone();
+        for ev in &self.pending {
+            if ev.type_ == EV_KEY {
+                Self::note_key(&mut keys, ev.code, ev.value);
+            }
+        }
+        keys
+    }
+
     fn active_slots(&self) -> Vec<usize> {
         (0..self.slot_count)
             .filter(|&s| self.slots[s].tracking_id >= 0)
@@ -400,9 +937,39 @@ impl GestureMachine {
             .count()
     }
 
+    /// Fingers on the pad: normally its active slots. Many older pads
+    /// (Synaptics/ALPS with 2 slots, or semi-MT) track fewer fingers
+    /// than they sense and report the real count only through the
+    /// BTN_TOOL_* bits -- so on a pad with fewer slots than those bits
+    /// can count (5), whichever says more wins. That also covers the
+    /// untracked finger that's last to lift.
+    fn finger_count(&self) -> usize {
+        let active = self.active_count();
+        match Self::tool_count(&self.real_keys) {
+            Some(tools) if self.slot_count < 5 => tools.max(active),
+            _ => active,
+        }
+    }
+
+    /// The finger count the real pad's BTN_TOOL_* bits report, if any is
+    /// down.
+    fn tool_count(keys: &[(u16, i32)]) -> Option<usize> {
+        keys.iter()
+            .filter(|&&(_, value)| value != 0)
+            .filter_map(|&(code, _)| match code {
+                BTN_TOOL_FINGER => Some(1),
+                BTN_TOOL_DOUBLETAP => Some(2),
+                BTN_TOOL_TRIPLETAP => Some(3),
+                BTN_TOOL_QUADTAP => Some(4),
+                BTN_TOOL_QUINTTAP => Some(5),
+                _ => None,
+            })
+            .max()
+    }
+
     fn decide(&mut self, frame: &[Ev], now: Instant, out: &mut Vec<Output>) {
         let active = self.active_slots();
-        let count = active.len();
+        let count = self.finger_count();
 
         // Once a drag has started, stay suppressed until every finger is
         // off, not just until the count first drops below 3. Fingers
@@ -411,7 +978,17 @@ impl GestureMachine {
         // touch, which libinput reads as a 2-finger tap (right-click)
         // the moment they lift too.
         if self.suppressing {
+            if frame
+                .iter()
+                .any(|ev| ev.type_ == EV_KEY && ev.code == BTN_LEFT && ev.value == 1)
+            {
+                self.on_click(out);
+            }
             if count == 0 {
+                if let (Some(start), Some(at)) = (self.touch_start, self.reached_3) {
+                    self.landings.push(Landing::Drag(at - start));
+                }
+                self.transition(Transition::DragEnded);
                 self.suppressing = false;
                 self.drag_ref_slot = None;
                 self.drag_last_pos = None;
@@ -422,15 +999,24 @@ impl GestureMachine {
                 self.touch_start = None;
                 self.touch_max = 0;
                 self.settled = false;
+                if std::mem::take(&mut self.drag_dropped) {
+                    // a click already ended this drag, button and all
+                    return;
+                }
                 // A committed drag that never moved and lifted before
                 // the press grace still owes its click: press now so the
                 // release below (or the drag-lock) completes it.
-                self.press_button(out);
-                if self.timing.drag_end_delay > Duration::ZERO {
+                self.press_button(PressCause::Liftoff, out);
+                if self.lock_latched {
+                    // locked by a click: held until a new touch says
+                    // otherwise, with no deadline
+                    self.transition(Transition::DragLockArmed);
+                } else if self.timing.drag_end_delay > Duration::ZERO {
                     // Drag-lock: keep the button held; a new 3-finger
                     // touch inside the window resumes the drag, anything
                     // else releases it (see flush_pending / on_tick).
                     self.lock_deadline = Some(now + self.timing.drag_end_delay);
+                    self.transition(Transition::DragLockArmed);
                 } else {
                     self.release_button(out);
                 }
@@ -448,14 +1034,18 @@ impl GestureMachine {
                 // the deferred press, in the common case no button was
                 // ever pressed, so nothing to undo.
                 if self.held {
+                    self.incident = Some(Incident::AbortAfterPress);
                     warn!(
+                        touch = self.touch_id,
                         "4th finger after the drag already pressed the button; \
                         releasing (a brief phantom click was unavoidable)"
                     );
-                } else {
-                    debug!("late 4th finger: aborting committed drag, handing touch to compositor");
                 }
+                self.reached_4.get_or_insert(now);
+                self.touch_max = self.touch_max.max(count);
+                self.transition(Transition::Late4thAbort { pressed: self.held });
                 self.suppressing = false;
+                self.drag_dropped = false;
                 self.drag_ref_slot = None;
                 self.drag_last_pos = None;
                 self.press_deadline = None;
@@ -463,10 +1053,10 @@ impl GestureMachine {
                 self.release_button(out);
                 // Introduce the touch to the clone as a fresh, complete,
                 // consistent touchdown: all live slots plus the real
-                // pad's current tool state (BTN_TOUCH/BTN_TOOL_*).
+                // pad's current tool state (BTN_TOUCH/BTN_TOOL_*) -- but
+                // not a click made during the drag.
                 let mut intro = self.active_slot_dump();
-                for i in 0..self.real_keys.len() {
-                    let (code, value) = self.real_keys[i];
+                for (code, value) in self.keys_for_clone() {
                     if Self::key_value(&self.clone_keys, code) != value {
                         intro.push(Ev::new(EV_KEY, code, value));
                         Self::note_key(&mut self.clone_keys, code, value);
@@ -477,13 +1067,22 @@ impl GestureMachine {
                 out.push(Output::EmitSynth(intro));
                 return;
             }
-            self.drive_drag(&active, out);
+            if !self.drag_dropped {
+                self.drive_drag(&active, out);
+            }
             // frame intentionally not relayed
             return;
         }
 
         if count == 0 {
+            if let (Some(start), Some(at)) = (self.touch_start, self.reached_4) {
+                self.landings.push(Landing::Swipe(at - start));
+            }
             let had_pending = self.touch_start.is_some() && !self.settled;
+            if had_pending {
+                self.note_decision(now);
+                self.transition(Transition::SettledTap);
+            }
             self.touch_start = None;
             self.touch_max = 0;
             self.settled = false;
@@ -506,10 +1105,21 @@ impl GestureMachine {
             self.touch_start = Some(now);
             self.touch_max = count;
             self.settled = false;
+            self.reached_3 = None;
+            self.reached_4 = None;
             self.pending.clear();
+            self.touch_id += 1;
+            self.span = tracing::debug_span!(target: TRACE_TARGET, "touch", touch = self.touch_id);
+            self.transition(Transition::Buffered);
         } else {
             self.touch_max = self.touch_max.max(count);
         }
+        if count >= 3 {
+            self.reached_3.get_or_insert(now);
+        }
+        if count >= 4 {
+            self.reached_4.get_or_insert(now);
+        }
 
         if self.settled {
             // Already decided this touch is an ordinary gesture -- relay
@@ -523,6 +1133,9 @@ impl GestureMachine {
             // fingers never lift in unison -- from being hijacked into
             // a phantom drag + click.
             if count == 3 && self.touch_max == 3 {
+                // not a landing stagger for the adaptive learner
+                self.reached_3 = None;
+                self.transition(Transition::CommittedLate);
                 self.commit_drag(&active, now, out);
                 return;
             }
@@ -536,6 +1149,8 @@ impl GestureMachine {
             // Unambiguously bigger than a 3-finger drag could ever be --
             // no need to wait out the rest of the window.
             self.settled = true;
+            self.note_decision(now);
+            self.transition(Transition::SettledBy4Plus);
             self.flush_pending(out);
             return;
         }
@@ -549,6 +1164,8 @@ impl GestureMachine {
             // touch-lift-reposition cycle of normal cursor use would add
             // a felt hitch.
             self.settled = true;
+            self.note_decision(now);
+            self.transition(Transition::SettledSingle);
             self.flush_pending(out);
             return;
         }
@@ -562,22 +1179,80 @@ impl GestureMachine {
     /// touch held stably at exactly 3 fingers the whole time, otherwise
     /// release it to the compositor as an ordinary gesture.
     fn resolve_touch_decision(&mut self, count: usize, now: Instant, out: &mut Vec<Output>) {
+        self.note_decision(now);
         if count == 3 && self.touch_max == 3 {
+            self.transition(Transition::Committed);
             self.pending.clear();
             let active = self.active_slots();
             self.commit_drag(&active, now, out);
             return;
         }
+        self.transition(Transition::SettledByDebounce);
         self.settled = true;
         self.flush_pending(out);
     }
 
+    /// Records how long the current touch waited for its classification.
+    fn note_decision(&mut self, now: Instant) {
+        if let Some(start) = self.touch_start {
+            self.metrics.decision_latency.record(now - start);
+        }
+    }
+
+    /// Counts a transition in the metrics and emits it as a structured
+    /// trace event inside the current touch's span.
+    fn transition(&mut self, t: Transition) {
+        let m = &mut self.metrics;
+        match t {
+            Transition::SettledSingle => m.touches_single += 1,
+            Transition::SettledByDebounce => m.touches_gesture += 1,
+            Transition::SettledBy4Plus => m.touches_multi += 1,
+            Transition::SettledTap => m.touches_tap += 1,
+            Transition::Committed => m.touches_drag += 1,
+            Transition::Pressed(PressCause::Motion) => m.presses_motion += 1,
+            Transition::Pressed(PressCause::Grace) => m.presses_grace += 1,
+            Transition::Pressed(PressCause::Liftoff) => m.presses_liftoff += 1,
+            Transition::Pressed(PressCause::Click) => m.presses_click += 1,
+            Transition::Late4thAbort { pressed: false } => m.drags_aborted += 1,
+            Transition::Late4thAbort { pressed: true } => m.drags_aborted_pressed += 1,
+            _ => {}
+        }
+        if t != Transition::Buffered {
+            let fingers = self.finger_count();
+            if let Some(decisions) = &mut self.decisions {
+                decisions.push(Decision {
+                    touch: self.touch_id,
+                    reason: t.reason(),
+                    description: t.describe(),
+                    fingers,
+                });
+            }
+        }
+        debug!(
+            target: TRACE_TARGET,
+            parent: &self.span,
+            touch = self.touch_id,
+            transition = t.reason(),
+            fingers = self.finger_count(),
+            max_fingers = self.touch_max,
+            "{}",
+            t.describe()
+        );
+    }
+
     /// Commit the current touch as a 3-finger drag. The button press is
     /// DEFERRED: it fires at the first actual drag motion, or when
     /// press_grace expires -- so a late 4th finger (fast 4-finger swipe)
     /// can still abort without a phantom click having been sent.
     fn commit_drag(&mut self, active: &[usize], now: Instant, out: &mut Vec<Output>) {
-        debug!("3-finger touch committed as a drag");
+        self.metrics.drags_committed += 1;
+        if self.lock_deadline.is_some() || self.lock_latched {
+            self.transition(Transition::DragLockResumed);
+        }
+        // a click made before the touch was ours is the drag's now
+        if Self::key_value(&self.real_keys, BTN_LEFT) != 0 {
+            self.click_swallowed = true;
+        }
         self.settled = true;
         self.enter_suppress(out);
         if !self.held {
@@ -591,7 +1266,8 @@ impl GestureMachine {
     /// ours to intercept. If a drag-lock is pending, the button is
     /// released *first*, so the flushed motion can never drag anything.
     fn flush_pending(&mut self, out: &mut Vec<Output>) {
-        if self.lock_deadline.take().is_some() {
+        if self.lock_deadline.take().is_some() || self.lock_latched {
+            self.transition(Transition::DragLockCancelled);
             self.release_button(out);
         }
         if !self.pending.is_empty() {
@@ -671,8 +1347,9 @@ impl GestureMachine {
         }
     }
 
-    fn press_button(&mut self, out: &mut Vec<Output>) {
+    fn press_button(&mut self, cause: PressCause, out: &mut Vec<Output>) {
         if !self.held {
+            self.transition(Transition::Pressed(cause));
             self.held = true;
             self.press_deadline = None;
             out.push(Output::MouseDown);
@@ -684,7 +1361,51 @@ impl GestureMachine {
     fn release_button(&mut self, out: &mut Vec<Output>) {
         if self.held {
             self.held = false;
-            out.push(Output::MouseUp);
+            out.push(if std::mem::take(&mut self.held_right) {
+                Output::RightUp
+            } else {
+                Output::MouseUp
+            });
+        }
+        self.lock_latched = false;
+    }
+
+    /// A physical click during a drag: carries out the
+    /// [`ClickAction`]. The click itself is kept from the clone until
+    /// the button comes back up.
+    fn on_click(&mut self, out: &mut Vec<Output>) {
+        self.click_swallowed = Self::key_value(&self.real_keys, BTN_LEFT) != 0;
+        if self.drag_dropped {
+            return;
+        }
+        let action = self.click_action;
+        self.transition(Transition::Click(action));
+        match action {
+            ClickAction::Ignore => {}
+            ClickAction::Drop => {
+                self.drag_dropped = true;
+                self.press_deadline = None;
+                self.drag_ref_slot = None;
+                self.drag_last_pos = None;
+                self.release_button(out);
+            }
+            ClickAction::ToggleLock => {
+                // a lock holds the button, so it's pressed now rather
+                // than at the first motion
+                self.press_button(PressCause::Click, out);
+                self.lock_latched = !self.lock_latched;
+            }
+            ClickAction::RightDrag => {
+                if !self.held_right {
+                    if self.held {
+                        out.push(Output::MouseUp);
+                    }
+                    self.held = true;
+                    self.held_right = true;
+                    self.press_deadline = None;
+                    out.push(Output::RightDown);
+                }
+            }
         }
     }
 
@@ -694,8 +1415,14 @@ impl GestureMachine {
             _ => {
                 // first frame of the gesture, or the previous reference
                 // finger lifted and another took its place: re-baseline
-                // without applying a delta this frame.
-                let s = active[0];
+                // without applying a delta this frame. (On a pad counting
+                // fingers by BTN_TOOL_* there may briefly be no slot to
+                // follow at all.)
+                let Some(&s) = active.first() else {
+                    self.drag_ref_slot = None;
+                    self.drag_last_pos = None;
+                    return;
+                };
                 self.drag_ref_slot = Some(s);
                 self.drag_last_pos = Some((self.slots[s].x, self.slots[s].y));
                 return;
@@ -714,7 +1441,7 @@ impl GestureMachine {
             if dx != 0 || dy != 0 {
                 // real drag motion: the deferred press (if still pending)
                 // must land before the movement it accompanies
-                self.press_button(out);
+                self.press_button(PressCause::Motion, out);
                 out.push(Output::MouseMove { dx, dy });
             }
         } else {
@@ -724,8 +1451,11 @@ impl GestureMachine {
         self.drag_last_pos = Some((x, y));
     }
 
-    /// SLOT/TRACKING_ID/X/Y events asserting the current state of every
-    /// active slot (used to correct downstream state after a resync).
+    /// Events asserting the complete current state of every active slot
+    /// -- tracking ID, position and every other axis known -- for when
+    /// the clone must be corrected (resync) or introduced to a touch it
+    /// never saw land (late-4th abort, reset). A finger with no pressure
+    /// or touch size would read to libinput as hovering, or as a thumb.
     fn active_slot_dump(&self) -> Vec<Ev> {
         let mut dump = Vec::new();
         for slot in 0..self.slot_count {
@@ -735,8 +1465,18 @@ impl GestureMachine {
                 dump.push(Ev::abs(ABS_MT_TRACKING_ID, s.tracking_id));
                 dump.push(Ev::abs(ABS_MT_POSITION_X, s.x));
                 dump.push(Ev::abs(ABS_MT_POSITION_Y, s.y));
+                for (&code, value) in MT_EXTRA_AXES.iter().zip(s.extra) {
+                    if let Some(value) = value {
+                        dump.push(Ev::abs(code, value));
+                    }
+                }
             }
         }
+        if !dump.is_empty() {
+            // hand the clone back the slot the real device is on: its
+            // next frame won't repeat ABS_MT_SLOT if that didn't change
+            dump.push(Ev::abs(ABS_MT_SLOT, self.current_slot as i32));
+        }
         dump
     }
 }
@@ -4,12 +4,15 @@
 //! After every single step we assert the invariants that, if ever
 //! violated, produce exactly the class of bug this project has been
 //! bitten by before: a clone left holding phantom touches or stuck tool
-//! bits, a virtual button that never releases, or a panic.
+//! bits, a clone finger with stale pressure or touch size, a virtual
+//! button that never releases, or a panic.
 //!
 //! The PRNG is a fixed-seed LCG, so every failure is perfectly
 //! reproducible: re-run the test, get the same sequence.
 
 use super::*;
+use crate::init::config::{MAX_ENTRY_DEBOUNCE, MAX_PRESS_GRACE};
+use crate::runtime::adaptive::{Learned, StaggerLearner};
 use std::time::Duration;
 
 /// Deterministic PRNG (Knuth MMIX LCG).
@@ -44,14 +47,26 @@ const TOOL_BITS: [u16; 5] = [
 ];
 
 /// Shadow model of the synthetic clone: replays everything the machine
-/// emits, exactly as the kernel/libinput would accumulate it.
-#[derive(Default)]
+/// emits, exactly as the kernel/libinput would accumulate it -- slot
+/// values included, which outlive the touch that set them.
 struct ShadowClone {
     active: [bool; MAX_SLOTS],
+    slots: [SlotState; MAX_SLOTS],
     keys: Vec<(u16, i32)>,
     current_slot: usize,
 }
 
+impl Default for ShadowClone {
+    fn default() -> Self {
+        ShadowClone {
+            active: [false; MAX_SLOTS],
+            slots: [SlotState::UP; MAX_SLOTS],
+            keys: Vec::new(),
+            current_slot: 0,
+        }
+    }
+}
+
 impl ShadowClone {
     fn feed(&mut self, evs: &[Ev]) {
         for ev in evs {
@@ -59,8 +74,11 @@ impl ShadowClone {
                 (EV_ABS, ABS_MT_SLOT) => {
                     self.current_slot = (ev.value.max(0) as usize).min(MAX_SLOTS - 1);
                 }
-                (EV_ABS, ABS_MT_TRACKING_ID) => {
-                    self.active[self.current_slot] = ev.value >= 0;
+                (EV_ABS, code) => {
+                    if code == ABS_MT_TRACKING_ID {
+                        self.active[self.current_slot] = ev.value >= 0;
+                    }
+                    self.slots[self.current_slot].set(code, ev.value);
                 }
                 (EV_KEY, code) => {
                     if let Some(e) = self.keys.iter_mut().find(|e| e.0 == code) {
@@ -81,10 +99,31 @@ impl ShadowClone {
     }
 }
 
+/// One finger of the hand.
+#[derive(Clone, Copy)]
+struct Finger {
+    id: i32,
+    x: i32,
+    y: i32,
+    pressure: i32,
+    touch_major: i32,
+}
+
+impl Finger {
+    /// What the kernel holds for its slot (what EVIOCGMTSLOTS reports).
+    fn state(&self) -> SlotState {
+        let mut state = SlotState::new(self.id, self.x, self.y);
+        state.set(ABS_MT_PRESSURE, self.pressure);
+        state.set(ABS_MT_TOUCH_MAJOR, self.touch_major);
+        state
+    }
+}
+
 /// One simulated "hand": up to 5 fingers occupying random slots, with
-/// realistic tool-bit reporting (BTN_TOUCH + BTN_TOOL_<count>).
+/// pressure and touch size, and realistic tool-bit reporting (BTN_TOUCH
+/// + BTN_TOOL_<count>).
 struct Hand {
-    fingers: [Option<(i32, i32, i32)>; MAX_SLOTS], // (id, x, y) per slot
+    fingers: [Option<Finger>; MAX_SLOTS],
     next_id: i32,
     last_tool: Option<u16>,
     touching: bool,
@@ -104,6 +143,27 @@ impl Hand {
         self.fingers.iter().filter(|f| f.is_some()).count()
     }
 
+    /// What EVIOCGMTSLOTS and EVIOCGKEY would report. (A lifted slot's
+    /// old values aren't carried: the machine must keep what it knew of
+    /// them.)
+    fn snapshot(&self) -> Snapshot {
+        let mut keys = vec![(BTN_TOUCH, i32::from(self.touching))];
+        keys.extend(
+            TOOL_BITS
+                .iter()
+                .map(|&code| (code, i32::from(self.last_tool == Some(code)))),
+        );
+        Snapshot {
+            slots: self
+                .fingers
+                .iter()
+                .map(|f| f.map_or(SlotState::UP, |f| f.state()))
+                .collect(),
+            keys,
+            abs: Vec::new(),
+        }
+    }
+
     /// Events updating BTN_TOUCH / BTN_TOOL_* to match the new count,
     /// the way real touchpad firmware reports it.
     fn tool_events(&mut self) -> Vec<Ev> {
@@ -141,14 +201,21 @@ impl Hand {
         let slot = free[rng.below(free.len() as u64) as usize];
         let id = self.next_id;
         self.next_id += 1;
-        let x = rng.below(2000) as i32;
-        let y = rng.below(1400) as i32;
-        self.fingers[slot] = Some((id, x, y));
+        let finger = Finger {
+            id,
+            x: rng.below(2000) as i32,
+            y: rng.below(1400) as i32,
+            pressure: 10 + rng.below(90) as i32,
+            touch_major: 100 + rng.below(400) as i32,
+        };
+        self.fingers[slot] = Some(finger);
         let mut evs = vec![
             Ev::abs(ABS_MT_SLOT, slot as i32),
             Ev::abs(ABS_MT_TRACKING_ID, id),
-            Ev::abs(ABS_MT_POSITION_X, x),
-            Ev::abs(ABS_MT_POSITION_Y, y),
+            Ev::abs(ABS_MT_POSITION_X, finger.x),
+            Ev::abs(ABS_MT_POSITION_Y, finger.y),
+            Ev::abs(ABS_MT_PRESSURE, finger.pressure),
+            Ev::abs(ABS_MT_TOUCH_MAJOR, finger.touch_major),
         ];
         evs.extend(self.tool_events());
         evs
@@ -174,13 +241,17 @@ impl Hand {
     fn wiggle(&mut self, rng: &mut Lcg) -> Vec<Ev> {
         let mut evs = Vec::new();
         for slot in 0..MAX_SLOTS {
-            if let Some((_, x, y)) = self.fingers[slot].as_mut() {
+            if let Some(f) = self.fingers[slot].as_mut() {
                 if rng.below(2) == 0 {
-                    *x = (*x + rng.below(41) as i32 - 20).clamp(0, 2000);
-                    *y = (*y + rng.below(41) as i32 - 20).clamp(0, 1400);
+                    f.x = (f.x + rng.below(41) as i32 - 20).clamp(0, 2000);
+                    f.y = (f.y + rng.below(41) as i32 - 20).clamp(0, 1400);
                     evs.push(Ev::abs(ABS_MT_SLOT, slot as i32));
-                    evs.push(Ev::abs(ABS_MT_POSITION_X, *x));
-                    evs.push(Ev::abs(ABS_MT_POSITION_Y, *y));
+                    evs.push(Ev::abs(ABS_MT_POSITION_X, f.x));
+                    evs.push(Ev::abs(ABS_MT_POSITION_Y, f.y));
+                    if rng.below(3) == 0 {
+                        f.pressure = (f.pressure + rng.below(11) as i32 - 5).clamp(1, 255);
+                        evs.push(Ev::abs(ABS_MT_PRESSURE, f.pressure));
+                    }
                 }
             }
         }
@@ -188,20 +259,37 @@ impl Hand {
     }
 }
 
+/// The adaptive learner may only ever produce timing that
+/// `Configuration::sanitize` would have let through unchanged.
+fn assert_sanitize_range(t: &Timing, ctx: &str) {
+    assert!(
+        t.entry_debounce >= t.probe_delay && t.entry_debounce <= MAX_ENTRY_DEBOUNCE,
+        "learned entry_debounce {:?} outside [{:?}, {:?}] ({ctx})",
+        t.entry_debounce,
+        t.probe_delay,
+        MAX_ENTRY_DEBOUNCE
+    );
+    assert!(
+        t.press_grace <= MAX_PRESS_GRACE,
+        "learned press_grace {:?} above {:?} ({ctx})",
+        t.press_grace,
+        MAX_PRESS_GRACE
+    );
+}
+
 fn run_scenario(seed: u64, drag_end_delay_ms: u64) {
     let mut rng = Lcg(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1);
-    let mut m = GestureMachine::new(
-        Timing {
-            probe_delay: Duration::from_millis(15),
-            entry_debounce: Duration::from_millis(50),
-            drag_end_delay: Duration::from_millis(drag_end_delay_ms),
-            press_grace: Duration::from_millis(75),
-            px_per_mm: PX_PER_MM,
-        },
-        10.0,
-        10.0,
-        16,
-    );
+    let base = Timing {
+        probe_delay: Duration::from_millis(15),
+        entry_debounce: Duration::from_millis(50),
+        drag_end_delay: Duration::from_millis(drag_end_delay_ms),
+        press_grace: Duration::from_millis(75),
+        px_per_mm: PX_PER_MM,
+    };
+    let mut m = GestureMachine::new(base, 10.0, 10.0, 16);
+    // every scenario also runs with the adaptive learner live, so the
+    // machine sees its timing retuned mid-stream the way the shell does it
+    let mut learner = StaggerLearner::new(&base, None);
     let mut shadow = ShadowClone::default();
     let mut hand = Hand::new();
     let mut now = Instant::now();
@@ -211,11 +299,11 @@ fn run_scenario(seed: u64, drag_end_delay_ms: u64) {
         for o in outs {
             match o {
                 Output::EmitSynth(evs) => shadow.feed(evs),
-                Output::MouseDown => {
+                Output::MouseDown | Output::RightDown => {
                     assert!(!*expect_held, "double MouseDown ({ctx}, seed {seed})");
                     *expect_held = true;
                 }
-                Output::MouseUp => {
+                Output::MouseUp | Output::RightUp => {
                     assert!(*expect_held, "MouseUp while not held ({ctx}, seed {seed})");
                     *expect_held = false;
                 }
@@ -258,14 +346,9 @@ fn run_scenario(seed: u64, drag_end_delay_ms: u64) {
         }
 
         // occasionally simulate a SYN_DROPPED resync with the hand's
-        // true state (what EVIOCGMTSLOTS would report)
+        // true state (what EVIOCGMTSLOTS and EVIOCGKEY would report)
         if rng.below(97) == 0 {
-            let snapshot: Vec<(i32, i32, i32)> = (0..16)
-                .map(|s| match hand.fingers[s] {
-                    Some((id, x, y)) => (id, x, y),
-                    None => (-1, 0, 0),
-                })
-                .collect();
+            let snapshot = hand.snapshot();
             let outs = m.on_resync(&snapshot, now);
             apply(&outs, &mut shadow, &mut expect_held, "resync");
             // a resync may swallow tool state legitimately; re-assert
@@ -276,6 +359,28 @@ fn run_scenario(seed: u64, drag_end_delay_ms: u64) {
             apply(&outs, &mut shadow, &mut expect_held, "post-resync");
         }
 
+        // rarer still: a resume from suspend, resetting the machine onto
+        // whatever the hand is doing by then
+        if rng.below(293) == 0 {
+            now += Duration::from_secs(1 + rng.below(3600));
+            let snapshot = hand.snapshot();
+            let outs = m.reset(&snapshot, now);
+            apply(&outs, &mut shadow, &mut expect_held, "reset");
+            assert!(!m.held, "button still held after reset (seed {seed})");
+            let mut frame = hand.wiggle(&mut rng);
+            frame.push(Ev::syn());
+            let outs = m.on_frame(&frame, now);
+            apply(&outs, &mut shadow, &mut expect_held, "post-reset");
+        }
+
+        for landing in m.take_landings() {
+            if learner.observe(landing).is_some() {
+                let t = learner.apply(base);
+                assert_sanitize_range(&t, &format!("seed {seed}, step {step}"));
+                m.set_timing(t);
+            }
+        }
+
         // ---- INVARIANTS, every step --------------------------------
         // 1. the machine's belief about the clone matches the clone
         for slot in 0..MAX_SLOTS {
@@ -289,7 +394,20 @@ fn run_scenario(seed: u64, drag_end_delay_ms: u64) {
             m.held, expect_held,
             "button-state desync (seed {seed}, step {step})"
         );
-        // 3. the clone never has MORE active slots than the real pad
+        // 3. every finger the clone holds is the real one, in full:
+        //    position, pressure and touch size (stale pressure reads to
+        //    libinput as a hovering finger or a thumb)
+        for slot in 0..MAX_SLOTS {
+            if shadow.active[slot] {
+                let real = hand.fingers[slot].map(|f| f.state());
+                assert_eq!(
+                    Some(shadow.slots[slot]),
+                    real,
+                    "clone slot {slot} differs from the real finger (seed {seed}, step {step})"
+                );
+            }
+        }
+        // 4. the clone never has MORE active slots than the real pad
         //    (it may briefly have fewer: buffered/suppressed touches)
         assert!(
             shadow.active_count() <= hand.count(),
@@ -350,3 +468,47 @@ fn randomized_stress_invariants() {
         run_scenario(seed, 300);
     }
 }
+
+/// The learner fed garbage -- arbitrary staggers, arbitrary probe delays,
+/// corrupt saved state -- still never leaves the sanitize ranges.
+#[test]
+fn adaptive_learner_stays_within_sanitize_ranges() {
+    for seed in 0..64u64 {
+        let mut rng = Lcg(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1);
+        let base = Timing {
+            probe_delay: Duration::from_millis(rng.below(201)),
+            entry_debounce: Duration::from_millis(rng.below(501)),
+            drag_end_delay: Duration::ZERO,
+            press_grace: Duration::from_millis(rng.below(1001)),
+            px_per_mm: PX_PER_MM,
+        };
+        let saved = (rng.below(2) == 0).then(|| Learned {
+            entry_debounce: Duration::from_millis(rng.below(1 << 20)),
+            press_grace: Duration::from_millis(rng.below(1 << 20)),
+        });
+        let mut learner = StaggerLearner::new(&base, saved);
+        let mut cfg = base;
+        assert_sanitize_range(&learner.apply(cfg), &format!("seed {seed}, start"));
+
+        for step in 0..2000u32 {
+            let stagger = match rng.below(4) {
+                0 => Duration::from_millis(rng.below(40)),
+                1 => Duration::from_millis(rng.below(400)),
+                2 => Duration::from_micros(rng.below(1 << 30)),
+                _ => Duration::ZERO,
+            };
+            let landing = if rng.below(2) == 0 {
+                Landing::Drag(stagger)
+            } else {
+                Landing::Swipe(stagger)
+            };
+            learner.observe(landing);
+            if rng.below(500) == 0 {
+                // hot reload moving the debounce floor
+                cfg.probe_delay = Duration::from_millis(rng.below(201));
+                learner.rebase(&cfg);
+            }
+            assert_sanitize_range(&learner.apply(cfg), &format!("seed {seed}, step {step}"));
+        }
+    }
+}
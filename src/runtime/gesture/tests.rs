@@ -33,6 +33,13 @@ impl Sim {
             now: Instant::now(),
         }
     }
+    /// A pad with only `slots` slots (older Synaptics/ALPS: 2).
+    fn with_slots(slots: usize) -> Self {
+        Sim {
+            m: GestureMachine::new(timing(0), RES, RES, slots),
+            now: Instant::now(),
+        }
+    }
     /// Advance the clock and deliver the tick the I/O loop would.
     fn tick(&mut self, ms: u64) -> Vec<Output> {
         self.now += Duration::from_millis(ms);
@@ -74,6 +81,10 @@ fn mv(slot: i32, x: i32, y: i32) -> Vec<Ev> {
     ]
 }
 
+fn key(code: u16, value: i32) -> Ev {
+    Ev::new(EV_KEY, code, value)
+}
+
 fn cat(parts: &[&[Ev]]) -> Vec<Ev> {
     parts.iter().flat_map(|p| p.iter().copied()).collect()
 }
@@ -644,6 +655,34 @@ fn drag_lock_times_out() {
     assert_eq!(mouse_ups(&outs), 0, "and only once");
 }
 
+/// The stuck-button watch forcing the button up takes the drag with it:
+/// lifting then arms no lock and sends no second MouseUp, and the next
+/// drag presses the button anew rather than "resuming" a released one.
+#[test]
+fn a_forced_release_forgets_the_drag() {
+    let mut sim = Sim::with_delay(300);
+    start_drag(&mut sim);
+    assert_eq!(sim.m.force_release(), [Output::MouseUp]);
+    assert!(!sim.m.button_held());
+
+    let outs = sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2)]));
+    assert_eq!(mouse_ups(&outs), 0);
+    assert_eq!(sim.m.next_deadline(), None, "no lock to expire");
+
+    let outs = start_drag(&mut sim);
+    assert_eq!(mouse_downs(&outs), 1, "a fresh drag, pressed again");
+    sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2)]));
+    let outs = sim.tick(300);
+    assert_eq!(mouse_ups(&outs), 1);
+
+    // forced while locked, the lock goes too
+    start_drag(&mut sim);
+    sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2)]));
+    assert_eq!(sim.m.force_release(), [Output::MouseUp]);
+    assert!(sim.tick(300).is_empty());
+    assert!(sim.m.force_release().is_empty(), "nothing left to release");
+}
+
 // =========================================================================
 // SYN_DROPPED recovery
 // =========================================================================
@@ -656,9 +695,9 @@ fn resync_live_touch_emits_correction() {
     sim.frame(&down(0, 1, 100, 100));
     sim.tick(15); // settled live
 
-    let mut snapshot = vec![(-1, 0, 0); 16];
-    snapshot[0] = (1, 150, 150); // moved during the drop
-    let outs = sim.m.on_resync(&snapshot, sim.now);
+    let mut snapshot = vec![SlotState::UP; 16];
+    snapshot[0] = SlotState::new(1, 150, 150); // moved during the drop
+    let outs = sim.m.on_resync(&Snapshot::of_slots(&snapshot), sim.now);
     let evs = synth_events(&outs);
     assert!(evs.contains(&Ev::abs(ABS_MT_POSITION_X, 150)));
 }
@@ -671,9 +710,9 @@ fn resync_releases_slots_that_lifted_during_the_drop() {
     sim.frame(&cat(&[&down(0, 1, 100, 100), &down(1, 2, 200, 100)]));
     sim.tick(50); // settled live 2-finger touch, relayed
 
-    let mut snapshot = vec![(-1, 0, 0); 16];
-    snapshot[0] = (1, 100, 100); // slot 1 lifted during the drop
-    let outs = sim.m.on_resync(&snapshot, sim.now);
+    let mut snapshot = vec![SlotState::UP; 16];
+    snapshot[0] = SlotState::new(1, 100, 100); // slot 1 lifted during the drop
+    let outs = sim.m.on_resync(&Snapshot::of_slots(&snapshot), sim.now);
     let evs = synth_events(&outs);
     let has_release = evs
         .windows(2)
@@ -692,11 +731,11 @@ fn resync_during_drag_rebaselines_without_jump() {
     start_drag(&mut sim);
     sim.frame_at(10, &mv(0, 510, 500)); // establish motion
 
-    let mut snapshot = vec![(-1, 0, 0); 16];
-    snapshot[0] = (100, 900, 900); // reference finger "teleported" in the drop
-    snapshot[1] = (101, 600, 500);
-    snapshot[2] = (102, 700, 500);
-    let outs = sim.m.on_resync(&snapshot, sim.now);
+    let mut snapshot = vec![SlotState::UP; 16];
+    snapshot[0] = SlotState::new(100, 900, 900); // reference finger "teleported" in the drop
+    snapshot[1] = SlotState::new(101, 600, 500);
+    snapshot[2] = SlotState::new(102, 700, 500);
+    let outs = sim.m.on_resync(&Snapshot::of_slots(&snapshot), sim.now);
     assert_eq!(
         total_move(&outs),
         (0, 0),
@@ -715,8 +754,8 @@ fn resync_with_all_lifted_ends_drag() {
     let mut sim = Sim::new();
     start_drag(&mut sim);
 
-    let snapshot = vec![(-1, 0, 0); 16];
-    let outs = sim.m.on_resync(&snapshot, sim.now);
+    let snapshot = vec![SlotState::UP; 16];
+    let outs = sim.m.on_resync(&Snapshot::of_slots(&snapshot), sim.now);
     assert_eq!(
         mouse_ups(&outs),
         1,
@@ -732,8 +771,8 @@ fn resync_with_all_lifted_during_pending_swallows_cleanly() {
     let mut sim = Sim::new();
     sim.frame(&cat(&[&down(0, 1, 100, 100), &down(1, 2, 200, 100)]));
 
-    let snapshot = vec![(-1, 0, 0); 16];
-    let outs = sim.m.on_resync(&snapshot, sim.now);
+    let snapshot = vec![SlotState::UP; 16];
+    let outs = sim.m.on_resync(&Snapshot::of_slots(&snapshot), sim.now);
     assert!(
         synth_events(&outs).is_empty(),
         "a touchdown whose release was dropped must not be flushed"
@@ -746,6 +785,170 @@ fn resync_with_all_lifted_during_pending_swallows_cleanly() {
     assert!(!synth_events(&outs).is_empty()); // settles normally
 }
 
+const BTN_TOUCH: u16 = 0x14a;
+const ABS_X: u16 = 0x00;
+
+/// A resync snapshot: the given slots live, everything else up, and the
+/// given keys (every tool bit not listed reads as up).
+fn snapshot_of(live: &[(usize, SlotState)], keys: &[(u16, i32)]) -> Snapshot {
+    let mut snapshot = Snapshot::of_slots(&[SlotState::UP; 16]);
+    for &(slot, state) in live {
+        snapshot.slots[slot] = state;
+    }
+    for code in [BTN_LEFT, BTN_TOUCH, BTN_TOOL_FINGER, BTN_TOOL_DOUBLETAP] {
+        let value = keys.iter().find(|k| k.0 == code).map_or(0, |k| k.1);
+        snapshot.keys.push((code, value));
+    }
+    snapshot
+}
+
+fn cat_keys(a: &[(u16, i32)], b: &[(u16, i32)]) -> Vec<(u16, i32)> {
+    a.iter().chain(b).copied().collect()
+}
+
+/// A settled 1-finger touch, relayed live with its BTN_TOUCH and
+/// BTN_TOOL_FINGER.
+fn live_one_finger(sim: &mut Sim) {
+    sim.frame(&cat(&[
+        &down(0, 1, 100, 100),
+        &[key(BTN_TOUCH, 1), key(BTN_TOOL_FINGER, 1)],
+    ]));
+    sim.tick(15);
+}
+
+/// A second finger landed inside the drop: the clone's tool bits are
+/// corrected from the snapshot's keys, and only the ones that differ.
+#[test]
+fn resync_corrects_tool_bits_that_changed_in_the_drop() {
+    let mut sim = Sim::new();
+    live_one_finger(&mut sim);
+
+    let snapshot = snapshot_of(
+        &[
+            (0, SlotState::new(1, 100, 100)),
+            (1, SlotState::new(2, 300, 100)),
+        ],
+        &[(BTN_TOUCH, 1), (BTN_TOOL_DOUBLETAP, 1)],
+    );
+    let evs = synth_events(&sim.m.on_resync(&snapshot, sim.now));
+    assert!(evs.contains(&key(BTN_TOOL_FINGER, 0)), "{evs:?}");
+    assert!(evs.contains(&key(BTN_TOOL_DOUBLETAP, 1)), "{evs:?}");
+    assert!(!evs.iter().any(|e| e.code == BTN_TOUCH && e.type_ == EV_KEY));
+
+    // nothing changed since: nothing to correct
+    let evs = synth_events(&sim.m.on_resync(&snapshot, sim.now));
+    assert!(!evs.iter().any(|e| e.type_ == EV_KEY), "{evs:?}");
+}
+
+/// The whole touch ended inside the drop: the clone loses the slot and
+/// BTN_TOUCH with it, not just the slot.
+#[test]
+fn resync_releases_btn_touch_lifted_in_the_drop() {
+    let mut sim = Sim::new();
+    live_one_finger(&mut sim);
+
+    let evs = synth_events(&sim.m.on_resync(&snapshot_of(&[], &[]), sim.now));
+    assert!(evs.contains(&Ev::abs(ABS_MT_TRACKING_ID, -1)), "{evs:?}");
+    assert!(evs.contains(&key(BTN_TOUCH, 0)), "{evs:?}");
+    assert!(evs.contains(&key(BTN_TOOL_FINGER, 0)), "{evs:?}");
+}
+
+/// A clickpad click pressed, then released, inside drops.
+#[test]
+fn resync_corrects_a_clickpad_button_changed_in_the_drop() {
+    let mut sim = Sim::new();
+    live_one_finger(&mut sim);
+    let finger = [(0, SlotState::new(1, 100, 100))];
+    let touching = [(BTN_TOUCH, 1), (BTN_TOOL_FINGER, 1)];
+
+    let pressed = snapshot_of(&finger, &cat_keys(&touching, &[(BTN_LEFT, 1)]));
+    let evs = synth_events(&sim.m.on_resync(&pressed, sim.now));
+    assert_eq!(evs.iter().filter(|e| e.type_ == EV_KEY).count(), 1);
+    assert!(evs.contains(&key(BTN_LEFT, 1)), "{evs:?}");
+
+    let released = snapshot_of(&finger, &touching);
+    let evs = synth_events(&sim.m.on_resync(&released, sim.now));
+    assert!(evs.contains(&key(BTN_LEFT, 0)), "{evs:?}");
+}
+
+/// A still-buffered touch: the key correction joins the buffer, so the
+/// clone gets it in order when the touch is relayed, not before.
+#[test]
+fn resync_key_correction_during_a_buffered_touch_is_flushed_with_it() {
+    let mut sim = Sim::new();
+    sim.frame(&cat(&[
+        &down(0, 1, 100, 100),
+        &[key(BTN_TOUCH, 1), key(BTN_TOOL_FINGER, 1)],
+    ]));
+
+    let snapshot = snapshot_of(
+        &[
+            (0, SlotState::new(1, 100, 100)),
+            (1, SlotState::new(2, 300, 100)),
+        ],
+        &[(BTN_TOUCH, 1), (BTN_TOOL_DOUBLETAP, 1)],
+    );
+    let outs = sim.m.on_resync(&snapshot, sim.now);
+    assert!(synth_events(&outs).is_empty(), "still buffered: {outs:?}");
+
+    let evs = synth_events(&sim.tick(50));
+    let on = evs.iter().position(|&e| e == key(BTN_TOOL_FINGER, 1));
+    let off = evs.iter().position(|&e| e == key(BTN_TOOL_FINGER, 0));
+    assert!(on.is_some() && off > on, "{evs:?}");
+    assert!(evs.contains(&key(BTN_TOOL_DOUBLETAP, 1)), "{evs:?}");
+}
+
+/// During a drag the clone holds no touch at all, so there are no keys
+/// to correct: a tool bit changing in the drop must not leak to it.
+#[test]
+fn resync_during_drag_relays_no_keys() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+
+    let mut snapshot = snapshot_of(
+        &[
+            (0, SlotState::new(100, 500, 500)),
+            (1, SlotState::new(101, 600, 500)),
+            (2, SlotState::new(102, 700, 500)),
+        ],
+        &[(BTN_TOUCH, 1)],
+    );
+    snapshot.keys.push((BTN_TOOL_TRIPLETAP, 1));
+    let outs = sim.m.on_resync(&snapshot, sim.now);
+    assert!(synth_events(&outs).is_empty(), "{outs:?}");
+    assert!(sim.m.held);
+}
+
+/// The drop may have hidden a slot switch: the machine (and the clone)
+/// must continue on the slot the kernel says the device is on, since
+/// the device won't repeat ABS_MT_SLOT. The legacy single-touch axes
+/// come along in the correction.
+#[test]
+fn resync_restores_the_current_slot_and_legacy_axes() {
+    let mut sim = Sim::new();
+    sim.frame(&cat(&[&down(0, 1, 100, 100), &down(1, 2, 300, 100)]));
+    sim.tick(50); // settled 2-finger touch, relayed; the device is on slot 1
+
+    let mut snapshot = snapshot_of(
+        &[
+            (0, SlotState::new(1, 120, 100)),
+            (1, SlotState::new(2, 300, 100)),
+        ],
+        &[],
+    );
+    snapshot.abs = vec![(ABS_X, 120), (ABS_MT_SLOT, 0)];
+    let evs = synth_events(&sim.m.on_resync(&snapshot, sim.now));
+    assert!(evs.contains(&Ev::abs(ABS_X, 120)), "{evs:?}");
+    let last_slot = evs.iter().rev().find(|e| e.code == ABS_MT_SLOT);
+    assert_eq!(last_slot, Some(&Ev::abs(ABS_MT_SLOT, 0)), "{evs:?}");
+
+    // an update with no ABS_MT_SLOT belongs to slot 0
+    let evs = synth_events(&sim.frame_at(10, &[Ev::abs(ABS_MT_POSITION_X, 130)]));
+    assert_eq!(sim.m.slots[0].x, 130);
+    assert_eq!(sim.m.slots[1].x, 300);
+    assert!(evs.contains(&Ev::abs(ABS_MT_POSITION_X, 130)));
+}
+
 // =========================================================================
 // robustness details
 // =========================================================================
@@ -765,6 +968,38 @@ fn out_of_range_slot_clamps() {
     assert!(synth_events(&outs).is_empty());
 }
 
+/// Frames the machine makes up (the late-4th handoff, resync
+/// corrections) carry every axis the fingers have, not just position:
+/// libinput reads a finger with no pressure as hovering.
+#[test]
+fn synthetic_frames_carry_every_axis() {
+    let mut sim = Sim::new();
+    sim.frame(&cat(&[
+        &down(0, 100, 500, 500),
+        &[
+            Ev::abs(ABS_MT_PRESSURE, 40),
+            Ev::abs(ABS_MT_TOUCH_MAJOR, 300),
+        ],
+    ]));
+    sim.frame_at(5, &down(1, 101, 600, 500));
+    sim.frame_at(5, &down(2, 102, 700, 500));
+    sim.tick(45); // committed
+    let outs = sim.frame_at(10, &down(3, 103, 400, 100));
+    let evs = synth_events(&outs);
+    assert!(evs.contains(&Ev::abs(ABS_MT_PRESSURE, 40)), "{evs:?}");
+    assert!(evs.contains(&Ev::abs(ABS_MT_TOUCH_MAJOR, 300)));
+
+    // a snapshot without an axis keeps what the frames said of it; one
+    // with it wins
+    let mut snapshot = vec![SlotState::UP; 16];
+    snapshot[0] = SlotState::new(100, 510, 500);
+    snapshot[0].set(ABS_MT_PRESSURE, 55);
+    snapshot[3] = SlotState::new(103, 400, 100);
+    let evs = synth_events(&sim.m.on_resync(&Snapshot::of_slots(&snapshot), sim.now));
+    assert!(evs.contains(&Ev::abs(ABS_MT_PRESSURE, 55)), "{evs:?}");
+    assert!(evs.contains(&Ev::abs(ABS_MT_TOUCH_MAJOR, 300)));
+}
+
 /// A device with fewer slots than MAX_SLOTS must not grow phantom
 /// touches from snapshot entries beyond its real slot range.
 #[test]
@@ -773,10 +1008,10 @@ fn small_slot_count_has_no_phantom_slots() {
     let now = Instant::now();
     // kernel snapshot buffers are MAX_SLOTS long; entries past the
     // device's 5 real slots arrive zeroed (tracking_id 0 looks "active")
-    let snapshot = [(0, 0, 0); 16];
+    let snapshot = [SlotState::new(0, 0, 0); 16];
     // slots 0-4 zeroed = 5 "active" (a genuinely full pad); the machine
     // must at least never count slots 5-15
-    m.on_resync(&snapshot[..5.min(snapshot.len())], now);
+    m.on_resync(&Snapshot::of_slots(&snapshot[..5.min(snapshot.len())]), now);
     assert_eq!(m.active_count(), 5);
     let m2 = GestureMachine::new(timing(0), RES, RES, 5);
     assert_eq!(m2.slot_count, 5);
@@ -835,6 +1070,27 @@ fn non_mt_events_ride_along_with_their_frames() {
     );
 }
 
+/// MSC_TIMESTAMP (libinput's clock for jump detection) stays with its
+/// frame and in its place in it, through the buffer and live alike.
+#[test]
+fn msc_timestamps_are_relayed_in_order() {
+    const EV_MSC: u16 = 0x04;
+    const MSC_TIMESTAMP: u16 = 0x05;
+    let stamp = |us| Ev::new(EV_MSC, MSC_TIMESTAMP, us);
+    let mut sim = Sim::new();
+    let first = cat(&[&down(0, 1, 100, 100), &[stamp(7000)]]);
+    sim.frame(&first);
+    let second = cat(&[&mv(0, 110, 100), &[stamp(14000)]]);
+    sim.frame_at(7, &second);
+    let evs = synth_events(&sim.tick(8));
+    let expected = cat(&[&first, &[Ev::syn()], &second, &[Ev::syn()]]);
+    assert_eq!(evs, expected, "the buffer flushes both frames verbatim");
+
+    let live = cat(&[&[stamp(21000)], &mv(0, 120, 100)]);
+    let evs = synth_events(&sim.frame_at(7, &live));
+    assert_eq!(evs, cat(&[&live, &[Ev::syn()]]));
+}
+
 /// button_held reflects reality for the shutdown safety-release.
 #[test]
 fn button_held_tracks_drag_state() {
@@ -845,3 +1101,678 @@ fn button_held_tracks_drag_state() {
     sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2)]));
     assert!(!sim.m.button_held());
 }
+
+// =========================================================================
+// pads with fewer slots than fingers (BTN_TOOL_* counting)
+// =========================================================================
+
+/// A staggered 3-finger touchdown on a 2-slot pad: the third finger has
+/// no slot and shows up only as BTN_TOOL_TRIPLETAP.
+fn two_slot_touchdown(sim: &mut Sim) -> Vec<Output> {
+    let mut outs = sim.frame(&cat(&[&down(0, 100, 500, 500), &[key(BTN_TOOL_FINGER, 1)]]));
+    outs = collect(
+        outs,
+        sim.frame_at(
+            5,
+            &cat(&[
+                &down(1, 101, 600, 500),
+                &[key(BTN_TOOL_FINGER, 0), key(BTN_TOOL_DOUBLETAP, 1)],
+            ]),
+        ),
+    );
+    outs = collect(
+        outs,
+        sim.frame_at(5, &[key(BTN_TOOL_DOUBLETAP, 0), key(BTN_TOOL_TRIPLETAP, 1)]),
+    );
+    outs
+}
+
+/// The whole point: a 3-finger drag is recognized on a 2-slot pad, is
+/// driven by a tracked finger, and ends only when the tool bits say
+/// every finger is off.
+#[test]
+fn two_slot_pad_drags_with_three_fingers_by_tool_bits() {
+    let mut sim = Sim::with_slots(2);
+    let mut outs = two_slot_touchdown(&mut sim);
+    outs = collect(outs, sim.tick(45));
+    assert!(synth_events(&outs).is_empty(), "nothing may leak: {outs:?}");
+
+    let outs = sim.frame_at(10, &mv(0, 520, 500));
+    assert_eq!(mouse_downs(&outs), 1);
+    assert!(total_move(&outs).0 > 0);
+
+    // both tracked fingers lift first; the untracked one is still down
+    let outs = sim.frame_at(
+        10,
+        &cat(&[
+            &up(0),
+            &up(1),
+            &[key(BTN_TOOL_TRIPLETAP, 0), key(BTN_TOOL_FINGER, 1)],
+        ]),
+    );
+    assert_eq!(mouse_ups(&outs), 0, "a finger is still down");
+    assert!(synth_events(&outs).is_empty());
+    let outs = sim.frame_at(10, &[key(BTN_TOOL_FINGER, 0)]);
+    assert_eq!(mouse_ups(&outs), 1);
+}
+
+/// BTN_TOOL_QUADTAP on a 2-slot pad is a 4-finger gesture: relayed,
+/// never a drag.
+#[test]
+fn two_slot_pad_counts_four_fingers_by_tool_bits() {
+    let mut sim = Sim::with_slots(2);
+    two_slot_touchdown(&mut sim);
+    let outs = sim.frame_at(5, &[key(BTN_TOOL_TRIPLETAP, 0), key(BTN_TOOL_QUADTAP, 1)]);
+    let evs = synth_events(&outs);
+    assert!(evs.contains(&Ev::abs(ABS_MT_TRACKING_ID, 101)), "{evs:?}");
+    assert!(evs.contains(&key(BTN_TOOL_QUADTAP, 1)));
+    assert_eq!(mouse_downs(&collect(outs, sim.tick(100))), 0);
+}
+
+/// Two fingers on a 2-slot pad are just two fingers.
+#[test]
+fn two_slot_pad_two_finger_touch_is_not_a_drag() {
+    let mut sim = Sim::with_slots(2);
+    sim.frame(&cat(&[
+        &down(0, 1, 100, 100),
+        &down(1, 2, 200, 100),
+        &[key(BTN_TOOL_DOUBLETAP, 1)],
+    ]));
+    let outs = sim.tick(50);
+    assert!(!synth_events(&outs).is_empty());
+    assert_eq!(mouse_downs(&outs), 0);
+}
+
+/// Where the slots can hold every finger the tool bits report, the
+/// slots are the truth: a stale or early tool bit can't make a drag.
+#[test]
+fn tool_bits_never_override_slots_that_can_count() {
+    let mut sim = Sim::new();
+    sim.frame(&cat(&[
+        &down(0, 1, 100, 100),
+        &down(1, 2, 200, 100),
+        &[key(BTN_TOOL_TRIPLETAP, 1)],
+    ]));
+    let outs = sim.tick(50);
+    assert!(!synth_events(&outs).is_empty());
+    assert_eq!(mouse_downs(&collect(outs, sim.tick(100))), 0);
+}
+
+// =========================================================================
+// landing reports (adaptive timing)
+// =========================================================================
+
+/// A finished drag reports its 3-finger landing stagger, once, when the
+/// touch ends -- not at commit, where a late 4th could still turn it
+/// into a swipe.
+#[test]
+fn finished_drag_reports_its_landing_stagger() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+    assert!(sim.m.take_landings().is_empty(), "touch still in progress");
+
+    sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2)]));
+    assert_eq!(
+        sim.m.take_landings(),
+        vec![Landing::Drag(Duration::from_millis(10))],
+        "fingers landed at 0, 5 and 10ms"
+    );
+    assert!(sim.m.take_landings().is_empty(), "reported exactly once");
+}
+
+/// An aborted drag (late 4th finger) is a swipe, measured to the 4th
+/// finger -- the very sample the learner needs to widen the windows.
+#[test]
+fn late_4th_abort_reports_a_swipe_landing() {
+    let mut sim = Sim::new();
+    commit_drag_only(&mut sim);
+    sim.frame_at(15, &down(3, 4, 400, 100));
+    sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2), &up(3)]));
+    assert_eq!(
+        sim.m.take_landings(),
+        vec![Landing::Swipe(Duration::from_millis(70))]
+    );
+}
+
+/// Ordinary touches that never reach 3 fingers teach the learner
+/// nothing.
+#[test]
+fn small_touches_report_no_landing() {
+    let mut sim = Sim::new();
+    sim.frame(&cat(&[&down(0, 1, 100, 100), &down(1, 2, 200, 100)]));
+    sim.tick(50);
+    sim.frame_at(30, &cat(&[&up(0), &up(1)]));
+    assert!(sim.m.take_landings().is_empty());
+}
+
+/// A settled touch that grows into a drag reports no landing: its 3rd
+/// finger came late on purpose, and must not drag the learned windows
+/// out however often it happens.
+#[test]
+fn late_growth_drags_leave_the_learned_timing_alone() {
+    use crate::runtime::adaptive::StaggerLearner;
+    let mut sim = Sim::new();
+    let mut learner = StaggerLearner::new(&timing(0), None);
+    for _ in 0..40 {
+        sim.frame_at(500, &down(0, 1, 100, 100));
+        sim.tick(15); // settles as a lone finger
+        sim.frame_at(100, &down(1, 2, 200, 100));
+        sim.frame_at(100, &down(2, 3, 300, 100)); // committed late, 215ms in
+        sim.frame_at(10, &mv(0, 150, 100));
+        sim.frame_at(30, &cat(&[&up(0), &up(1), &up(2)]));
+        for landing in sim.m.take_landings() {
+            assert_eq!(learner.observe(landing), None, "{landing:?}");
+        }
+    }
+    let learned = learner.apply(timing(0));
+    assert_eq!(learned.entry_debounce, timing(0).entry_debounce);
+    assert_eq!(learned.press_grace, timing(0).press_grace);
+}
+
+/// `--observe` sees each decision once, in order, and only when it asked.
+#[test]
+fn decisions_are_collected_only_on_request() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+    assert!(sim.m.take_decisions().is_empty(), "not asked for");
+
+    sim.m.collect_decisions();
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2)]));
+    commit_drag_only(&mut sim);
+    sim.frame_at(15, &down(3, 4, 400, 100));
+    let reasons: Vec<&str> = sim.m.take_decisions().iter().map(|d| d.reason).collect();
+    assert_eq!(
+        reasons,
+        ["drag-ended", "committed", "late-4th-abort"],
+        "buffering isn't a decision"
+    );
+    assert!(sim.m.take_decisions().is_empty(), "reported exactly once");
+}
+
+// =========================================================================
+// metrics
+// =========================================================================
+
+/// Each touch is classified exactly once, and each way a drag presses
+/// is attributed to its cause.
+#[test]
+fn metrics_count_classifications_and_press_causes() {
+    let mut sim = Sim::new();
+    // drag pressed by motion
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &mv(0, 520, 500));
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2)]));
+    // drag pressed by the grace timer
+    start_drag(&mut sim);
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2)]));
+    // stationary drag lifting inside the grace
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2)]));
+    // a tap, and a lone finger
+    sim.frame_at(10, &down(0, 1, 100, 100));
+    sim.frame_at(5, &up(0));
+    sim.frame_at(10, &down(0, 2, 100, 100));
+    sim.tick(15);
+    sim.frame_at(5, &up(0));
+
+    let m = sim.m.metrics();
+    assert_eq!(m.touches_drag, 3);
+    assert_eq!(m.drags_committed, 3);
+    assert_eq!(m.touches_tap, 1);
+    assert_eq!(m.touches_single, 1);
+    assert_eq!(
+        (m.presses_motion, m.presses_grace, m.presses_liftoff),
+        (1, 1, 1)
+    );
+    assert_eq!(m.decision_latency.count(), 5, "one decision per touch");
+}
+
+/// Late-4th aborts are split by whether the phantom click happened.
+#[test]
+fn metrics_split_aborts_by_whether_the_button_was_pressed() {
+    let mut sim = Sim::new();
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &down(3, 9, 400, 100));
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2), &up(3)]));
+
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &mv(0, 520, 500));
+    sim.frame_at(10, &down(3, 9, 400, 100));
+
+    let m = sim.m.metrics();
+    assert_eq!((m.drags_aborted, m.drags_aborted_pressed), (1, 1));
+
+    sim.m
+        .on_resync(&Snapshot::of_slots(&[SlotState::UP; 16]), sim.now);
+    assert_eq!(sim.m.metrics().resyncs, 1);
+}
+
+// =========================================================================
+// decision trace
+// =========================================================================
+
+/// Collects `(touch, transition)` from the machine's trace events.
+#[derive(Clone, Default)]
+struct TraceLog(std::sync::Arc<std::sync::Mutex<Vec<(u64, String)>>>);
+
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for TraceLog {
+    fn on_event(&self, event: &tracing::Event<'_>, _: tracing_subscriber::layer::Context<'_, S>) {
+        struct Fields(u64, String);
+        impl tracing::field::Visit for Fields {
+            fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
+                if field.name() == "touch" {
+                    self.0 = value;
+                }
+            }
+            fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+                if field.name() == "transition" {
+                    self.1 = value.to_string();
+                }
+            }
+            fn record_debug(&mut self, _: &tracing::field::Field, _: &dyn std::fmt::Debug) {}
+        }
+        if event.metadata().target() != TRACE_TARGET {
+            return;
+        }
+        let mut fields = Fields(0, String::new());
+        event.record(&mut fields);
+        self.0.lock().unwrap().push((fields.0, fields.1));
+    }
+}
+
+/// Runs `f` with the trace captured; returns the reason codes in order.
+fn trace(f: impl FnOnce()) -> Vec<(u64, String)> {
+    use tracing_subscriber::prelude::*;
+    let log = TraceLog::default();
+    let subscriber = tracing_subscriber::registry().with(log.clone());
+    tracing::subscriber::with_default(subscriber, f);
+    let events = log.0.lock().unwrap().clone();
+    events
+}
+
+fn reasons(events: &[(u64, String)]) -> Vec<&str> {
+    events.iter().map(|(_, r)| r.as_str()).collect()
+}
+
+/// A late-4th abort after the press reads, in the trace, as the full
+/// story of what happened and why.
+#[test]
+fn trace_explains_a_late_4th_abort_after_press() {
+    let mut sim = Sim::new();
+    let events = trace(|| {
+        commit_drag_only(&mut sim);
+        sim.frame_at(10, &mv(0, 520, 500));
+        sim.frame_at(10, &down(3, 9, 400, 100));
+    });
+    assert_eq!(
+        reasons(&events),
+        [
+            "buffered",
+            "committed",
+            "pressed-by-motion",
+            "late-4th-abort-after-press"
+        ]
+    );
+    assert!(events.iter().all(|(touch, _)| *touch == 1));
+}
+
+/// Every touch gets its own ID, and drag-lock transitions are attributed
+/// to the touch that caused them.
+#[test]
+fn trace_ids_touches_and_follows_drag_lock() {
+    let mut sim = Sim::with_delay(500);
+    let events = trace(|| {
+        start_drag(&mut sim);
+        sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2)]));
+        // a lone finger inside the lock window cancels it
+        sim.frame_at(50, &down(0, 7, 100, 100));
+        sim.tick(15);
+        sim.frame_at(5, &up(0));
+        // a tap
+        sim.frame_at(10, &down(0, 8, 100, 100));
+        sim.frame_at(5, &up(0));
+    });
+    assert_eq!(
+        reasons(&events),
+        [
+            "buffered",
+            "committed",
+            "pressed-by-grace",
+            "drag-ended",
+            "drag-lock-armed",
+            "buffered",
+            "settled-single",
+            "drag-lock-cancelled",
+            "buffered",
+            "settled-tap",
+        ]
+    );
+    let ids: Vec<u64> = events.iter().map(|(touch, _)| *touch).collect();
+    assert_eq!(ids, [1, 1, 1, 1, 1, 2, 2, 2, 3, 3]);
+}
+
+// =========================================================================
+// flight recorder incidents
+// =========================================================================
+
+/// Only the abort that cost a phantom click is an incident; the
+/// click-free one is the machine working as designed.
+#[test]
+fn abort_after_press_raises_an_incident_once() {
+    let mut sim = Sim::new();
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &down(3, 9, 400, 100));
+    assert_eq!(sim.m.take_incident(), None);
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2), &up(3)]));
+
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &mv(0, 520, 500));
+    sim.frame_at(10, &down(3, 9, 400, 100));
+    assert_eq!(sim.m.take_incident(), Some(Incident::AbortAfterPress));
+    assert_eq!(sim.m.take_incident(), None);
+}
+
+// =========================================================================
+// reset (suspend/resume)
+// =========================================================================
+
+/// Suspended mid-drag: on resume the button must come up, and no press
+/// deadline or drag state may linger to act on a stale touch.
+#[test]
+fn reset_mid_drag_releases_the_button_and_forgets_the_drag() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+    assert!(sim.m.button_held());
+
+    sim.now += Duration::from_secs(3600);
+    let outs = sim
+        .m
+        .reset(&Snapshot::of_slots(&[SlotState::UP; 16]), sim.now);
+    assert_eq!(mouse_ups(&outs), 1);
+    assert!(!sim.m.button_held());
+    assert_eq!(sim.m.next_deadline(), None);
+
+    // a plain finger afterwards is an ordinary touch, not drag motion
+    sim.frame_at(10, &down(0, 9, 100, 100));
+    let outs = collect(sim.tick(15), sim.frame_at(5, &mv(0, 300, 300)));
+    assert_eq!(total_move(&outs), (0, 0));
+    assert!(!synth_events(&outs).is_empty());
+}
+
+/// A pending drag-lock must not outlive a reset (its deadline would fire
+/// on a monotonic clock that didn't count the sleep).
+#[test]
+fn reset_cancels_a_pending_drag_lock() {
+    let mut sim = Sim::with_delay(500);
+    start_drag(&mut sim);
+    sim.frame_at(5, &cat(&[&up(0), &up(1), &up(2)]));
+    assert!(sim.m.button_held());
+    assert!(sim.m.next_deadline().is_some());
+
+    let outs = sim
+        .m
+        .reset(&Snapshot::of_slots(&[SlotState::UP; 16]), sim.now);
+    assert_eq!(mouse_ups(&outs), 1);
+    assert_eq!(sim.m.next_deadline(), None);
+    assert!(sim.tick(1000).is_empty());
+}
+
+/// The clone is told every slot and tool key it holds is up.
+#[test]
+fn reset_lifts_everything_on_the_clone() {
+    const BTN_TOUCH: u16 = 0x14a;
+    let mut sim = Sim::new();
+    sim.frame(&cat(&[
+        &down(0, 1, 100, 100),
+        &down(1, 2, 200, 100),
+        &[Ev::new(EV_KEY, BTN_TOUCH, 1)][..],
+    ]));
+    sim.tick(50); // settled live 2-finger touch, relayed
+
+    let outs = sim
+        .m
+        .reset(&Snapshot::of_slots(&[SlotState::UP; 16]), sim.now);
+    let evs = synth_events(&outs);
+    for slot in [0, 1] {
+        assert!(
+            evs.windows(2)
+                .any(|w| w[0] == Ev::abs(ABS_MT_SLOT, slot)
+                    && w[1] == Ev::abs(ABS_MT_TRACKING_ID, -1)),
+            "slot {slot} must be lifted: {evs:?}"
+        );
+    }
+    assert!(evs.contains(&Ev::new(EV_KEY, BTN_TOUCH, 0)));
+    assert_eq!(mouse_ups(&outs), 0);
+
+    // nothing left to lift: a second reset is silent
+    assert!(sim
+        .m
+        .reset(&Snapshot::of_slots(&[SlotState::UP; 16]), sim.now)
+        .is_empty());
+}
+
+/// Fingers already down at resume start a fresh touch; when it settles,
+/// the clone gets their complete touchdown.
+#[test]
+fn reset_with_fingers_down_starts_a_fresh_touch() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+
+    let mut snapshot = vec![SlotState::UP; 16];
+    snapshot[0] = SlotState::new(40, 300, 300);
+    let outs = sim.m.reset(&Snapshot::of_slots(&snapshot), sim.now);
+    assert!(synth_events(&outs).is_empty()); // buffered, undecided
+
+    let outs = sim.tick(15); // settles as a lone finger
+    let evs = synth_events(&outs);
+    assert!(evs.contains(&Ev::abs(ABS_MT_TRACKING_ID, 40)), "{evs:?}");
+    assert!(evs.contains(&Ev::abs(ABS_MT_POSITION_X, 300)));
+}
+
+/// Every reset is in the trace.
+#[test]
+fn reset_is_traced() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+    let events = trace(|| {
+        sim.m
+            .reset(&Snapshot::of_slots(&[SlotState::UP; 16]), sim.now);
+    });
+    assert_eq!(reasons(&events), ["reset"]);
+}
+
+// =========================================================================
+// physical clickpad click during a drag
+// =========================================================================
+
+fn clicked_sim(action: ClickAction) -> Sim {
+    let mut sim = Sim::new();
+    sim.m.set_click_action(action);
+    sim
+}
+
+const CLICK: [Ev; 1] = [Ev::new(EV_KEY, BTN_LEFT, 1)];
+const UNCLICK: [Ev; 1] = [Ev::new(EV_KEY, BTN_LEFT, 0)];
+
+fn lift_three(sim: &mut Sim) -> Vec<Output> {
+    sim.frame_at(10, &cat(&[&up(0), &up(1), &up(2)]))
+}
+
+fn right_downs(outs: &[Output]) -> usize {
+    outs.iter()
+        .filter(|o| matches!(o, Output::RightDown))
+        .count()
+}
+
+fn right_ups(outs: &[Output]) -> usize {
+    outs.iter().filter(|o| matches!(o, Output::RightUp)).count()
+}
+
+/// By default a click changes nothing about the drag, and the clone
+/// never hears of it.
+#[test]
+fn click_during_drag_is_ignored_by_default() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+    let mut outs = sim.frame_at(10, &cat(&[&mv(0, 520, 500), &CLICK]));
+    outs = collect(outs, sim.frame_at(10, &cat(&[&mv(0, 540, 500), &UNCLICK])));
+    assert!(total_move(&outs).0 > 0, "the drag carries on");
+    assert_eq!((mouse_downs(&outs), mouse_ups(&outs)), (0, 0));
+    assert!(synth_events(&outs).is_empty(), "{outs:?}");
+
+    let outs = lift_three(&mut sim);
+    assert_eq!(mouse_ups(&outs), 1);
+}
+
+/// `drop`: the button comes up at the click, and the rest of the touch
+/// neither moves the pointer nor clicks at liftoff.
+#[test]
+fn click_drops_the_drag() {
+    let mut sim = clicked_sim(ClickAction::Drop);
+    start_drag(&mut sim);
+    let outs = sim.frame_at(10, &CLICK);
+    assert_eq!(mouse_ups(&outs), 1);
+    assert!(!sim.m.button_held());
+
+    let mut outs = sim.frame_at(10, &cat(&[&mv(0, 600, 500), &UNCLICK]));
+    outs = collect(outs, lift_three(&mut sim));
+    assert_eq!(total_move(&outs), (0, 0));
+    assert_eq!((mouse_downs(&outs), mouse_ups(&outs)), (0, 0));
+    assert!(synth_events(&outs).is_empty(), "{outs:?}");
+
+    // dropped before the press: the owed liftoff click is dropped too
+    commit_drag_only(&mut sim);
+    sim.frame_at(10, &CLICK);
+    let outs = collect(sim.tick(100), lift_three(&mut sim));
+    assert_eq!(mouse_downs(&outs), 0, "{outs:?}");
+    assert_eq!(sim.m.next_deadline(), None);
+}
+
+/// `toggle-lock`: the first click locks the drag (pressing it if it
+/// hadn't yet), so lifting holds the button with no deadline; a new
+/// drag continues it, and a second click unlocks it.
+#[test]
+fn click_toggles_a_drag_lock() {
+    let mut sim = clicked_sim(ClickAction::ToggleLock);
+    commit_drag_only(&mut sim);
+    let outs = sim.frame_at(10, &CLICK);
+    assert_eq!(
+        mouse_downs(&outs),
+        1,
+        "a lock holds the button: pressed now"
+    );
+    sim.frame_at(10, &UNCLICK);
+
+    let outs = lift_three(&mut sim);
+    assert_eq!(mouse_ups(&outs), 0);
+    assert!(sim.m.button_held());
+    assert!(sim.m.lock_latched());
+    assert_eq!(sim.m.next_deadline(), None);
+    assert!(sim.tick(60_000).is_empty());
+
+    let outs = start_drag(&mut sim);
+    assert_eq!((mouse_downs(&outs), mouse_ups(&outs)), (0, 0));
+    sim.frame_at(10, &CLICK); // unlocks
+    assert!(!sim.m.lock_latched());
+    sim.frame_at(10, &UNCLICK);
+    let outs = lift_three(&mut sim);
+    assert_eq!(mouse_ups(&outs), 1);
+}
+
+/// A locked drag is still released by any other touch, before the
+/// compositor sees it.
+#[test]
+fn a_click_locked_drag_breaks_for_another_touch() {
+    let mut sim = clicked_sim(ClickAction::ToggleLock);
+    start_drag(&mut sim);
+    sim.frame_at(10, &CLICK);
+    sim.frame_at(10, &UNCLICK);
+    lift_three(&mut sim);
+
+    let outs = collect(sim.frame_at(50, &down(0, 70, 100, 100)), sim.tick(15));
+    assert_eq!(mouse_ups(&outs), 1);
+    let up_idx = outs.iter().position(|o| matches!(o, Output::MouseUp));
+    let synth_idx = outs.iter().position(|o| matches!(o, Output::EmitSynth(_)));
+    assert!(up_idx < synth_idx, "{outs:?}");
+    assert!(!sim.m.button_held());
+}
+
+/// `right-drag`: the left button comes up, the right one goes down, and
+/// the drag carries on with it to the end.
+#[test]
+fn click_switches_the_drag_to_the_right_button() {
+    let mut sim = clicked_sim(ClickAction::RightDrag);
+    start_drag(&mut sim);
+    let outs = sim.frame_at(10, &CLICK);
+    assert_eq!(outs, [Output::MouseUp, Output::RightDown]);
+
+    // a second click changes nothing
+    sim.frame_at(10, &UNCLICK);
+    let mut outs = sim.frame_at(10, &CLICK);
+    outs = collect(outs, sim.frame_at(10, &cat(&[&mv(0, 540, 500), &UNCLICK])));
+    assert!(total_move(&outs).0 > 0);
+    assert_eq!((mouse_downs(&outs), right_downs(&outs)), (0, 0));
+
+    let outs = lift_three(&mut sim);
+    assert_eq!((mouse_ups(&outs), right_ups(&outs)), (0, 1));
+    assert!(!sim.m.button_held());
+
+    // before the press, the drag starts on the right button outright
+    commit_drag_only(&mut sim);
+    let outs = collect(sim.frame_at(10, &CLICK), sim.frame_at(10, &mv(0, 540, 500)));
+    assert_eq!((mouse_downs(&outs), right_downs(&outs)), (0, 1));
+}
+
+/// Handing the touch to the compositor with the clickpad still pressed
+/// (late 4th finger): the clone gets the touch, but not the click it
+/// never saw start -- and isn't corrected into it by a resync.
+#[test]
+fn a_click_during_the_drag_is_not_handed_off_with_the_touch() {
+    for action in [ClickAction::Ignore, ClickAction::RightDrag] {
+        let mut sim = clicked_sim(action);
+        start_drag(&mut sim);
+        sim.frame_at(10, &CLICK);
+
+        let outs = sim.frame_at(10, &down(3, 9, 400, 100));
+        assert_eq!(mouse_ups(&outs) + right_ups(&outs), 1, "{action:?}");
+        let evs = synth_events(&outs);
+        assert!(evs.contains(&Ev::abs(ABS_MT_TRACKING_ID, 9)), "{evs:?}");
+        assert!(!evs.contains(&key(BTN_LEFT, 1)), "{action:?}: {evs:?}");
+
+        let live = [
+            (0, SlotState::new(100, 500, 500)),
+            (1, SlotState::new(101, 600, 500)),
+            (2, SlotState::new(102, 700, 500)),
+            (3, SlotState::new(9, 400, 100)),
+        ];
+        let evs = synth_events(
+            &sim.m
+                .on_resync(&snapshot_of(&live, &[(BTN_LEFT, 1)]), sim.now),
+        );
+        assert!(!evs.contains(&key(BTN_LEFT, 1)), "{action:?}: {evs:?}");
+    }
+}
+
+/// Once the button is back up, the next click is the user's own again.
+#[test]
+fn a_click_after_the_swallowed_one_is_relayed() {
+    let mut sim = Sim::new();
+    start_drag(&mut sim);
+    sim.frame_at(10, &CLICK);
+    sim.frame_at(10, &down(3, 9, 400, 100)); // handed off, still pressed
+    sim.frame_at(10, &UNCLICK);
+
+    let evs = synth_events(&sim.frame_at(10, &CLICK));
+    assert!(evs.contains(&key(BTN_LEFT, 1)), "{evs:?}");
+}
+
+/// The trace says what each click did.
+#[test]
+fn clicks_during_a_drag_are_traced() {
+    let mut sim = clicked_sim(ClickAction::ToggleLock);
+    commit_drag_only(&mut sim);
+    let events = trace(|| {
+        sim.frame_at(10, &CLICK);
+    });
+    assert_eq!(reasons(&events), ["click-toggled-lock", "pressed-by-click"]);
+}
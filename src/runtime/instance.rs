@@ -0,0 +1,210 @@
+//! One instance per touchpad: a lock file per device in the runtime dir.
+//!
+//! Two instances (the systemd unit plus one started by hand, or the
+//! installed build plus a dev build) would fight over `EVIOCGRAB`: the
+//! loser gets a bare `EBUSY` -- or, if the clone's `phys` marker differs
+//! between their versions, doesn't recognize the winner's clone and
+//! proxies *that*. So before touching a device, each instance takes an
+//! `flock` on `<vendor>-<product>.lock`. Keyed by the device's identity
+//! rather than its path, the key survives re-enumeration, and the clone
+//! (which impersonates that identity) maps to the same lock.
+//!
+//! The holder writes its PID and version into the file, so a second
+//! instance can say who's in the way, and `--replace` can ask it to
+//! leave (SIGTERM, i.e. a clean shutdown) and take over once it has.
+//! The kernel drops the lock with the holder's last fd, so a crashed
+//! instance never leaves a stale one behind.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use input_linux::EvdevHandle;
+use libc::O_NONBLOCK;
+use tracing::info;
+
+use super::mt_proxy;
+use crate::init::config::get_runtime_dir;
+
+/// How long `--replace` waits for the old instance to shut down.
+const REPLACE_TIMEOUT: Duration = Duration::from_secs(5);
+const REPLACE_POLL: Duration = Duration::from_millis(50);
+
+/// Who holds a lock, as they wrote it into the lock file.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Holder {
+    pub pid: Option<i32>,
+    pub version: Option<String>,
+}
+
+impl Holder {
+    fn ours() -> String {
+        format!(
+            "pid={}\nversion={}\n",
+            std::process::id(),
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+
+    fn parse(text: &str) -> Holder {
+        let mut holder = Holder::default();
+        for line in text.lines() {
+            match line.split_once('=') {
+                Some(("pid", pid)) => holder.pid = pid.trim().parse().ok(),
+                Some(("version", version)) => holder.version = Some(version.trim().to_string()),
+                _ => {}
+            }
+        }
+        holder
+    }
+
+    pub fn describe(&self) -> String {
+        let version = self.version.as_deref().unwrap_or("unknown version");
+        match self.pid {
+            Some(pid) => format!("linux-3-finger-drag {version} (PID {pid})"),
+            None => format!("linux-3-finger-drag {version}"),
+        }
+    }
+}
+
+/// An exclusive claim on one device, held until dropped.
+#[derive(Debug)]
+pub struct DeviceLock {
+    _file: File,
+    path: PathBuf,
+}
+
+fn try_flock(file: &File) -> io::Result<bool> {
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(true);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        Ok(false)
+    } else {
+        Err(err)
+    }
+}
+
+fn read_holder(file: &mut File) -> Holder {
+    let mut text = String::new();
+    let _ = file.rewind().and_then(|_| file.read_to_string(&mut text));
+    Holder::parse(&text)
+}
+
+/// The lock key for the device at `path`: its `vendor-product`, the same
+/// key its per-device state is stored under.
+pub fn device_key(path: &str) -> io::Result<String> {
+    let file = OpenOptions::new()
+        .read(true)
+        .custom_flags(O_NONBLOCK)
+        .open(path)?;
+    let id = EvdevHandle::new(file).device_id()?;
+    Ok(mt_proxy::device_key_of(&id))
+}
+
+/// Who holds the lock on the device keyed `key`, if anyone does --
+/// looked up without taking it.
+pub fn holder_of(key: &str) -> io::Result<Option<Holder>> {
+    holder_in(&get_runtime_dir()?, key)
+}
+
+/// [`holder_of`], with the lock files in `dir`.
+pub fn holder_in(dir: &Path, key: &str) -> io::Result<Option<Holder>> {
+    let mut file = match File::open(dir.join(format!("{key}.lock"))) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    // a shared lock only fails against an exclusive one: a holder's
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } == 0 {
+        return Ok(None);
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() != io::ErrorKind::WouldBlock {
+        return Err(err);
+    }
+    Ok(Some(read_holder(&mut file)))
+}
+
+impl DeviceLock {
+    /// Claims the device at `path`. See [`acquire_in`](Self::acquire_in).
+    pub fn acquire(path: &str, replace: bool) -> io::Result<Self> {
+        let dir = get_runtime_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        Self::acquire_in(&dir, &device_key(path)?, replace)
+    }
+
+    /// Claims `<dir>/<key>.lock`. If another instance holds it, fails
+    /// with `ResourceBusy` and a message naming it -- unless `replace`,
+    /// in which case the holder is asked to shut down, and the lock
+    /// taken once it has.
+    pub fn acquire_in(dir: &Path, key: &str, replace: bool) -> io::Result<Self> {
+        let path = dir.join(format!("{key}.lock"));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .mode(0o644)
+            .open(&path)?;
+
+        if !try_flock(&file)? {
+            let holder = read_holder(&mut file);
+            let pid = holder
+                .pid
+                .filter(|&pid| pid > 1 && pid as u32 != std::process::id());
+            match pid {
+                Some(pid) if replace => {
+                    info!(
+                        "Replacing {} on this touchpad ({}).",
+                        holder.describe(),
+                        path.display()
+                    );
+                    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    let deadline = Instant::now() + REPLACE_TIMEOUT;
+                    while !try_flock(&file)? {
+                        if Instant::now() >= deadline {
+                            return Err(io::Error::new(
+                                io::ErrorKind::TimedOut,
+                                format!(
+                                    "{} did not shut down within {REPLACE_TIMEOUT:?}",
+                                    holder.describe()
+                                ),
+                            ));
+                        }
+                        std::thread::sleep(REPLACE_POLL);
+                    }
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::ResourceBusy,
+                        format!(
+                            "this touchpad is already being proxied by {} (lock: {}); \
+                            stop it first, or pass --replace to take over",
+                            holder.describe(),
+                            path.display()
+                        ),
+                    ))
+                }
+            }
+        }
+
+        file.set_len(0)?;
+        file.rewind()?;
+        file.write_all(Holder::ours().as_bytes())?;
+        Ok(DeviceLock { _file: file, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+mod tests;
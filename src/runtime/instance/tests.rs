@@ -0,0 +1,86 @@
+//! Lock files in a scratch directory. flock locks belong to the open
+//! file, so two acquisitions conflict even within one process.
+
+use super::*;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("3fd-instance-{}-{name}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn second_instance_is_told_who_holds_the_device() {
+    let dir = scratch("busy");
+    let first = DeviceLock::acquire_in(&dir, "04f3-3140", false).unwrap();
+    let holder = Holder::parse(&std::fs::read_to_string(first.path()).unwrap());
+    assert_eq!(holder.pid, Some(std::process::id() as i32));
+    assert_eq!(holder.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+
+    let err = DeviceLock::acquire_in(&dir, "04f3-3140", false).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::ResourceBusy);
+    let msg = err.to_string();
+    assert!(
+        msg.contains(&format!("PID {}", std::process::id())) && msg.contains("--replace"),
+        "{msg}"
+    );
+    // and so is anyone who only looks (doctor)
+    let seen = holder_in(&dir, "04f3-3140").unwrap().unwrap();
+    assert_eq!(seen.pid, Some(std::process::id() as i32));
+    assert_eq!(holder_in(&dir, "06cb-ce7e").unwrap(), None);
+
+    // other devices are unaffected, and the lock goes with its holder
+    DeviceLock::acquire_in(&dir, "06cb-ce7e", false).unwrap();
+    drop(first);
+    DeviceLock::acquire_in(&dir, "04f3-3140", false).unwrap();
+    assert_eq!(holder_in(&dir, "04f3-3140").unwrap(), None);
+}
+
+#[test]
+fn parses_what_a_holder_wrote() {
+    assert_eq!(
+        Holder::parse("pid=4242\nversion=1.9.0\n"),
+        Holder {
+            pid: Some(4242),
+            version: Some("1.9.0".into())
+        }
+    );
+    assert_eq!(Holder::parse("garbage"), Holder::default());
+    assert_eq!(
+        Holder::parse("").describe(),
+        "linux-3-finger-drag unknown version"
+    );
+}
+
+#[test]
+fn replace_stops_the_holder_and_takes_over() {
+    let dir = scratch("replace");
+    let path = dir.join("04f3-3140.lock");
+    let file = File::create(&path).unwrap();
+    assert!(try_flock(&file).unwrap());
+    // hand the locked file to a child (standing in for another
+    // instance), then let go of our copy: the child alone holds it
+    let fd = file.as_raw_fd();
+    let mut child = unsafe {
+        Command::new("sleep")
+            .arg("30")
+            .pre_exec(move || {
+                let flags = libc::fcntl(fd, libc::F_GETFD);
+                libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC);
+                Ok(())
+            })
+            .spawn()
+            .unwrap()
+    };
+    drop(file);
+    std::fs::write(&path, format!("pid={}\nversion=0.0.1\n", child.id())).unwrap();
+
+    let err = DeviceLock::acquire_in(&dir, "04f3-3140", false).unwrap_err();
+    assert!(err.to_string().contains("0.0.1"), "{err}");
+
+    DeviceLock::acquire_in(&dir, "04f3-3140", true).unwrap();
+    assert!(!child.wait().unwrap().success());
+}
@@ -0,0 +1,354 @@
+//! logind's `PrepareForSleep` signal, from the system bus.
+//!
+//! logind broadcasts `PrepareForSleep(true)` just before the system
+//! suspends and `PrepareForSleep(false)` once it's back -- earlier and
+//! more precise than the clock comparison in `suspend.rs`, which only
+//! notices on the first wakeup after resume. Optional: with no system
+//! bus (or no logind), the clocks are enough.
+//!
+//! All that takes is a small corner of the D-Bus wire protocol, written
+//! out here instead of pulling in a D-Bus stack: `EXTERNAL` auth, then
+//! two method calls (`Hello`, and `AddMatch` for the one signal), then
+//! reading messages and picking out that signal. Ours are little-endian;
+//! both byte orders are read.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixStream};
+use std::time::Duration;
+
+const SYSTEM_BUS: &str = "/run/dbus/system_bus_socket";
+const INTERFACE: &str = "org.freedesktop.login1.Manager";
+const MEMBER: &str = "PrepareForSleep";
+const MATCH_RULE: &str = "type='signal',sender='org.freedesktop.login1',\
+    interface='org.freedesktop.login1.Manager',member='PrepareForSleep',\
+    path='/org/freedesktop/login1'";
+/// For the blocking handshake; reading signals afterwards never blocks.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const METHOD_CALL: u8 = 1;
+const SIGNAL: u8 = 4;
+
+// header field codes
+const PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const DESTINATION: u8 = 6;
+const SIGNATURE: u8 = 8;
+
+/// Marshals a message, in either byte order.
+struct Writer {
+    buf: Vec<u8>,
+    big_endian: bool,
+}
+
+impl Writer {
+    fn new(big_endian: bool) -> Self {
+        Writer {
+            buf: Vec::with_capacity(256),
+            big_endian,
+        }
+    }
+
+    fn pad(&mut self, align: usize) {
+        while !self.buf.len().is_multiple_of(align) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.pad(4);
+        let bytes = if self.big_endian {
+            value.to_be_bytes()
+        } else {
+            value.to_le_bytes()
+        };
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    fn str(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, s: &str) {
+        self.buf.push(s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+}
+
+/// A string-typed header field value: `(code, type, value)`.
+type Field<'a> = (u8, char, &'a str);
+
+/// A complete message of `kind`, with `fields` in its header, and a body
+/// of `signature` marshalled by `body` (in the same byte order).
+fn encode(
+    big_endian: bool,
+    kind: u8,
+    serial: u32,
+    fields: &[Field],
+    signature: &str,
+    body: impl FnOnce(&mut Writer),
+) -> Vec<u8> {
+    let mut b = Writer::new(big_endian);
+    body(&mut b);
+    let body = b.buf;
+
+    let mut w = Writer::new(big_endian);
+    w.buf
+        .extend_from_slice(&[if big_endian { b'B' } else { b'l' }, kind, 0, 1]);
+    w.u32(body.len() as u32);
+    w.u32(serial);
+    w.u32(0); // header fields length, patched below
+    let sig_field = (SIGNATURE, 'g', signature);
+    for &(code, ty, value) in fields
+        .iter()
+        .chain((!signature.is_empty()).then_some(&sig_field))
+    {
+        w.pad(8);
+        w.buf.push(code);
+        w.signature(&ty.to_string());
+        match ty {
+            'g' => w.signature(value),
+            _ => w.str(value),
+        }
+    }
+    let len = (w.buf.len() - 16) as u32;
+    let len = if big_endian {
+        len.to_be_bytes()
+    } else {
+        len.to_le_bytes()
+    };
+    w.buf[12..16].copy_from_slice(&len);
+    w.pad(8);
+    w.buf.extend_from_slice(&body);
+    w.buf
+}
+
+fn call_bus(serial: u32, member: &str, arg: Option<&str>) -> Vec<u8> {
+    encode(
+        false,
+        METHOD_CALL,
+        serial,
+        &[
+            (PATH, 'o', "/org/freedesktop/DBus"),
+            (FIELD_INTERFACE, 's', "org.freedesktop.DBus"),
+            (FIELD_MEMBER, 's', member),
+            (DESTINATION, 's', "org.freedesktop.DBus"),
+        ],
+        if arg.is_some() { "s" } else { "" },
+        |w| {
+            if let Some(arg) = arg {
+                w.str(arg);
+            }
+        },
+    )
+}
+
+/// Reads a message in either byte order.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl Reader<'_> {
+    fn align(&mut self, align: usize) {
+        self.pos = self.pos.div_ceil(align) * align;
+    }
+
+    fn bytes(&mut self, n: usize) -> Option<&[u8]> {
+        let bytes = self.buf.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.align(4);
+        let b: [u8; 4] = self.bytes(4)?.try_into().ok()?;
+        Some(if self.big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    fn str(&mut self) -> Option<String> {
+        let len = self.u32()? as usize;
+        let s = String::from_utf8_lossy(self.bytes(len)?).into_owned();
+        self.pos += 1; // NUL
+        Some(s)
+    }
+
+    fn signature(&mut self) -> Option<String> {
+        let len = self.u8()? as usize;
+        let s = String::from_utf8_lossy(self.bytes(len)?).into_owned();
+        self.pos += 1;
+        Some(s)
+    }
+}
+
+/// The length of the complete message at the start of `buf`, if it's
+/// all there yet.
+fn message_len(buf: &[u8]) -> Option<usize> {
+    let mut r = Reader {
+        buf: buf.get(..16)?,
+        pos: 4,
+        big_endian: buf[0] == b'B',
+    };
+    let body = r.u32()? as usize;
+    r.u32()?;
+    let fields = r.u32()? as usize;
+    let len = (16 + fields).div_ceil(8) * 8 + body;
+    (buf.len() >= len).then_some(len)
+}
+
+/// `Some(sleeping)` if `msg` is a `PrepareForSleep` signal.
+fn prepare_for_sleep(msg: &[u8]) -> Option<bool> {
+    let mut r = Reader {
+        buf: msg,
+        pos: 12,
+        big_endian: msg[0] == b'B',
+    };
+    if msg[1] != SIGNAL {
+        return None;
+    }
+    let fields_end = 16 + r.u32()? as usize;
+    let (mut interface, mut member, mut signature) = (None, None, String::new());
+    while r.pos < fields_end {
+        r.align(8);
+        let code = r.u8()?;
+        let value = match r.signature()?.as_str() {
+            "s" | "o" => r.str()?,
+            "g" => r.signature()?,
+            "u" => r.u32()?.to_string(),
+            _ => return None, // not a header field type we know of
+        };
+        match code {
+            FIELD_INTERFACE => interface = Some(value),
+            FIELD_MEMBER => member = Some(value),
+            SIGNATURE => signature = value,
+            _ => {}
+        }
+    }
+    if interface.as_deref() != Some(INTERFACE) || member.as_deref() != Some(MEMBER) {
+        return None;
+    }
+    if signature != "b" {
+        return None;
+    }
+    r.pos = fields_end;
+    r.align(8);
+    Some(r.u32()? != 0)
+}
+
+/// Where the system bus is: `$DBUS_SYSTEM_BUS_ADDRESS` (its first
+/// `unix:path=` or `unix:abstract=` address), or the standard socket.
+fn system_bus() -> io::Result<SocketAddr> {
+    let Ok(addresses) = std::env::var("DBUS_SYSTEM_BUS_ADDRESS") else {
+        return SocketAddr::from_pathname(SYSTEM_BUS);
+    };
+    for address in addresses.split(';') {
+        let Some(params) = address.strip_prefix("unix:") else {
+            continue;
+        };
+        for param in params.split(',') {
+            match param.split_once('=') {
+                Some(("path", path)) => return SocketAddr::from_pathname(path),
+                Some(("abstract", name)) => return SocketAddr::from_abstract_name(name.as_bytes()),
+                _ => {}
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("no unix socket in DBUS_SYSTEM_BUS_ADDRESS={addresses}"),
+    ))
+}
+
+pub struct LogindSleep {
+    stream: UnixStream,
+    buf: Vec<u8>,
+}
+
+impl AsRawFd for LogindSleep {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+impl LogindSleep {
+    /// Subscribes to `PrepareForSleep` on the system bus.
+    pub fn connect() -> io::Result<Self> {
+        Self::connect_to(&system_bus()?)
+    }
+
+    /// Subscribes to `PrepareForSleep` on the bus at `addr`.
+    pub fn connect_to(addr: &SocketAddr) -> io::Result<Self> {
+        let mut stream = UnixStream::connect_addr(addr)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+
+        let uid = unsafe { libc::getuid() };
+        let hex_uid: String = uid
+            .to_string()
+            .bytes()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        stream.write_all(format!("\0AUTH EXTERNAL {hex_uid}\r\n").as_bytes())?;
+        // one line, and nothing after it until BEGIN: a buffered reader
+        // can't swallow any of the binary protocol
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("bus refused authentication: {}", line.trim_end()),
+            ));
+        }
+        stream.write_all(b"BEGIN\r\n")?;
+        stream.write_all(&call_bus(1, "Hello", None))?;
+        stream.write_all(&call_bus(2, "AddMatch", Some(MATCH_RULE)))?;
+        stream.set_nonblocking(true)?;
+        Ok(LogindSleep {
+            stream,
+            buf: Vec::with_capacity(1024),
+        })
+    }
+
+    /// Consumes everything pending. Returns each `PrepareForSleep`
+    /// received, in order: `true` when about to sleep, `false` on resume.
+    pub fn events(&mut self) -> io::Result<Vec<bool>> {
+        let mut chunk = [0u8; 1024];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the system bus closed the connection",
+                    ))
+                }
+                Ok(n) => self.buf.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        let mut events = Vec::new();
+        while let Some(len) = message_len(&self.buf) {
+            if let Some(sleeping) = prepare_for_sleep(&self.buf[..len]) {
+                events.push(sleeping);
+            }
+            self.buf.drain(..len);
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests;
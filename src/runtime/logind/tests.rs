@@ -0,0 +1,153 @@
+//! Against a local stand-in for the system bus: it does the auth dance,
+//! checks the subscription, and sends what logind would.
+
+use super::*;
+use std::os::unix::net::UnixListener;
+use std::thread;
+use std::time::Instant;
+
+const LOGIND_FIELDS: &[Field] = &[
+    (PATH, 'o', "/org/freedesktop/login1"),
+    (FIELD_INTERFACE, 's', INTERFACE),
+    (FIELD_MEMBER, 's', MEMBER),
+];
+
+fn prepare_for_sleep_signal(big_endian: bool, serial: u32, sleeping: bool) -> Vec<u8> {
+    encode(big_endian, SIGNAL, serial, LOGIND_FIELDS, "b", |w| {
+        w.u32(sleeping as u32)
+    })
+}
+
+/// Reads until the stream holds `n` complete messages, returning them.
+fn read_messages(stream: &mut UnixStream, n: usize) -> Vec<Vec<u8>> {
+    let mut buf = Vec::new();
+    let mut messages = Vec::new();
+    let mut chunk = [0u8; 512];
+    while messages.len() < n {
+        let read = stream.read(&mut chunk).unwrap();
+        assert!(read > 0, "client hung up");
+        buf.extend_from_slice(&chunk[..read]);
+        while let Some(len) = message_len(&buf) {
+            messages.push(buf.drain(..len).collect());
+        }
+    }
+    messages
+}
+
+/// One auth line, read a byte at a time: the binary messages the client
+/// sends right after `BEGIN` must stay in the stream.
+fn read_line(stream: &mut UnixStream) -> Vec<u8> {
+    let mut line = Vec::new();
+    let mut byte = [0u8; 1];
+    while !line.ends_with(b"\n") {
+        assert_eq!(stream.read(&mut byte).unwrap(), 1, "client hung up");
+        line.push(byte[0]);
+    }
+    line
+}
+
+/// The bus side: accepts one client and runs `script` on it once it has
+/// authenticated and subscribed.
+fn stand_in(name: &str, script: impl FnOnce(&mut UnixStream) + Send + 'static) -> SocketAddr {
+    let path = std::env::temp_dir().join(format!("3fd-bus-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let line = read_line(&mut stream);
+        assert!(line.starts_with(b"\0AUTH EXTERNAL "), "{line:?}");
+        stream
+            .write_all(b"OK 0123456789abcdef0123456789abcdef\r\n")
+            .unwrap();
+        assert_eq!(read_line(&mut stream), b"BEGIN\r\n");
+
+        let calls = read_messages(&mut stream, 2);
+        let text = |msg: &[u8]| String::from_utf8_lossy(msg).into_owned();
+        assert!(text(&calls[0]).contains("Hello"));
+        assert!(text(&calls[1]).contains("AddMatch"));
+        assert!(text(&calls[1]).contains(MATCH_RULE));
+        script(&mut stream);
+    });
+    SocketAddr::from_pathname(path).unwrap()
+}
+
+fn wait_for_events(sleep: &mut LogindSleep, n: usize) -> Vec<bool> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut events = Vec::new();
+    while events.len() < n {
+        assert!(Instant::now() < deadline, "only got {events:?}");
+        events.extend(sleep.events().unwrap());
+        thread::sleep(Duration::from_millis(5));
+    }
+    events
+}
+
+#[test]
+fn hears_sleep_and_resume_and_nothing_else() {
+    let addr = stand_in("signals", |stream| {
+        // replies and unrelated signals first, as a real bus sends them
+        let reply = encode(false, 2, 1, &[], "s", |w| w.str(":1.42"));
+        let unrelated = encode(
+            false,
+            SIGNAL,
+            2,
+            &[
+                (PATH, 'o', "/org/freedesktop/DBus"),
+                (FIELD_INTERFACE, 's', "org.freedesktop.DBus"),
+                (FIELD_MEMBER, 's', "NameAcquired"),
+            ],
+            "s",
+            |w| w.str(":1.42"),
+        );
+        stream.write_all(&reply).unwrap();
+        stream.write_all(&unrelated).unwrap();
+        // a message split across reads is reassembled
+        let going = prepare_for_sleep_signal(false, 3, true);
+        stream.write_all(&going[..10]).unwrap();
+        thread::sleep(Duration::from_millis(20));
+        stream.write_all(&going[10..]).unwrap();
+        stream
+            .write_all(&prepare_for_sleep_signal(true, 4, false))
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+    });
+    let mut sleep = LogindSleep::connect_to(&addr).unwrap();
+    assert_eq!(wait_for_events(&mut sleep, 2), [true, false]);
+}
+
+#[test]
+fn reports_the_bus_going_away() {
+    let addr = stand_in("hangup", |_| {});
+    let mut sleep = LogindSleep::connect_to(&addr).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(2);
+    loop {
+        match sleep.events() {
+            Ok(events) => assert!(events.is_empty()),
+            Err(e) => {
+                assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+                break;
+            }
+        }
+        assert!(Instant::now() < deadline, "hangup not noticed");
+        thread::sleep(Duration::from_millis(5));
+    }
+}
+
+#[test]
+fn refused_authentication_is_an_error() {
+    let path = std::env::temp_dir().join(format!("3fd-bus-{}-refuse.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut line = Vec::new();
+        BufReader::new(stream.try_clone().unwrap())
+            .read_until(b'\n', &mut line)
+            .unwrap();
+        stream.write_all(b"REJECTED EXTERNAL\r\n").unwrap();
+    });
+    let err = LogindSleep::connect_to(&SocketAddr::from_pathname(path).unwrap())
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,157 @@
+//! Runtime counters and latency histograms.
+//!
+//! How often do late-4th aborts actually happen? How many drags press on
+//! the grace timer rather than on motion? How long does a touch wait for
+//! its classification? None of that was visible before; these numbers
+//! make it visible. The gesture machine counts its own decisions (so the
+//! counting is as deterministic and testable as the decisions
+//! themselves); the I/O shell adds what only it can see, the kernel-to-
+//! uinput relay latency. The whole struct is plain data, rendered as
+//! text for the `metrics` CLI query and the periodic debug log.
+
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Power-of-two buckets in microseconds: bucket `i` holds samples in
+/// `[2^(i-1), 2^i)` us (bucket 0: under 1us), the last one everything
+/// from ~8.4s up.
+const BUCKETS: usize = 24;
+
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: [u64; BUCKETS],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: [0; BUCKETS],
+            count: 0,
+            sum: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+}
+
+impl Histogram {
+    pub fn record(&mut self, sample: Duration) {
+        let us = sample.as_micros().min(u64::MAX as u128) as u64;
+        let bucket = ((u64::BITS - us.leading_zeros()) as usize).min(BUCKETS - 1);
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(sample);
+        self.max = self.max.max(sample);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Upper bound of the bucket holding the `pct`th percentile -- as
+    /// precise as power-of-two buckets allow (within 2x).
+    pub fn percentile(&self, pct: u64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count * pct).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                let upper = Duration::from_micros(1u64 << i);
+                return Some(upper.min(self.max));
+            }
+        }
+        Some(self.max)
+    }
+
+    fn summary(&self) -> String {
+        if self.count == 0 {
+            return "no samples".to_string();
+        }
+        format!(
+            "n={} mean={:?} p50<={:?} p99<={:?} max={:?}",
+            self.count,
+            self.sum / self.count.min(u32::MAX as u64) as u32,
+            self.percentile(50).unwrap_or_default(),
+            self.percentile(99).unwrap_or_default(),
+            self.max
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    // how touches were classified (exactly one per touch)
+    /// Settled as a lone finger after the probe delay.
+    pub touches_single: u64,
+    /// Ended before any decision was reached (taps).
+    pub touches_tap: u64,
+    /// 2-3 fingers, settled as an ordinary gesture when the debounce
+    /// window closed.
+    pub touches_gesture: u64,
+    /// Reached 4+ fingers inside the window.
+    pub touches_multi: u64,
+    /// Committed as a drag inside the window.
+    pub touches_drag: u64,
+
+    /// Every drag commit, including settled touches growing to 3.
+    pub drags_committed: u64,
+    /// Late 4th finger aborted the drag before it pressed (click-free).
+    pub drags_aborted: u64,
+    /// Late 4th finger aborted the drag after it pressed (the
+    /// phantom-click warning).
+    pub drags_aborted_pressed: u64,
+
+    /// What pressed the drag button: first drag motion, press grace
+    /// expiring on a stationary drag, a stationary drag lifting, or a
+    /// click locking a drag that hadn't pressed yet.
+    pub presses_motion: u64,
+    pub presses_grace: u64,
+    pub presses_liftoff: u64,
+    pub presses_click: u64,
+
+    /// SYN_DROPPED resyncs.
+    pub resyncs: u64,
+    /// Virtual button releases forced by the stuck-button watch.
+    pub stuck_releases: u64,
+
+    /// First finger down to classification (settle or drag commit).
+    pub decision_latency: Histogram,
+    /// Kernel event timestamp to the resulting uinput write.
+    pub relay_latency: Histogram,
+}
+
+impl Metrics {
+    /// Multi-line, human-readable report (the `metrics` CLI query).
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "touches: single={} tap={} gesture={} 4+={} drag={}",
+            self.touches_single,
+            self.touches_tap,
+            self.touches_gesture,
+            self.touches_multi,
+            self.touches_drag
+        );
+        let _ = writeln!(
+            s,
+            "drags: committed={} aborted={} aborted-after-press={}",
+            self.drags_committed, self.drags_aborted, self.drags_aborted_pressed
+        );
+        let _ = writeln!(
+            s,
+            "presses: motion={} grace={} liftoff={} click={}",
+            self.presses_motion, self.presses_grace, self.presses_liftoff, self.presses_click
+        );
+        let _ = writeln!(s, "resyncs: {}", self.resyncs);
+        let _ = writeln!(s, "stuck-button releases: {}", self.stuck_releases);
+        let _ = writeln!(s, "decision latency: {}", self.decision_latency.summary());
+        let _ = writeln!(s, "relay latency: {}", self.relay_latency.summary());
+        s
+    }
+}
@@ -1,3 +1,14 @@
+pub mod adaptive;
+pub mod config_watch;
+pub mod control;
 pub mod gesture;
+pub mod instance;
+pub mod logind;
+pub mod metrics;
 pub mod mt_proxy;
+pub mod notify;
+pub mod protocol_a;
+pub mod recorder;
+pub mod stuck_button;
+pub mod suspend;
 pub mod virtual_trackpad;
@@ -14,22 +14,43 @@
 //! outright and re-emitting a clean copy, we control every frame the
 //! compositor ever sees: either an accurate mirror of the real pad, or
 //! an explicit "nothing is touching" state -- never a silent gap.
+//!
+//! A device speaking the legacy multitouch protocol A (anonymous
+//! contacts, no slots) goes through [`ProtocolA`] both ways: its frames
+//! are tracked into slots for the machine, and what the machine sends
+//! the clone -- which has the real device's capabilities, so no slots
+//! either -- is turned back into protocol A.
+//!
+//! With `--observe` ([`MtProxy::observe`]) none of that happens: the
+//! device is read alongside the compositor, ungrabbed and with no clone,
+//! so the machine's decisions can be watched without acting on them.
 
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::os::fd::{AsRawFd, RawFd};
 use std::os::unix::fs::OpenOptionsExt;
-use std::time::Instant;
+use std::sync::MutexGuard;
+use std::time::{Duration, Instant};
 
 use libc::O_NONBLOCK;
-use tracing::{debug, info, warn};
-
-use input_linux::{sys, AbsoluteAxis, AbsoluteInfoSetup, EvdevHandle, EventKind, UInputHandle};
-
-use super::gesture::{Ev, GestureMachine, Output, EV_SYN, MAX_SLOTS, SYN_DROPPED, SYN_REPORT};
+use tracing::{debug, error, info, warn};
+
+use input_linux::{
+    sys, AbsoluteAxis, AbsoluteInfo, AbsoluteInfoSetup, EvdevHandle, EventKind, InputId,
+    UInputHandle,
+};
+
+use super::gesture::{Ev, GestureMachine, Output, SlotState, Snapshot, MT_EXTRA_AXES};
+use super::gesture::{EV_SYN, MAX_SLOTS, SYN_DROPPED, SYN_REPORT};
+use super::protocol_a::ProtocolA;
+use super::recorder::{FlightRecorder, SharedRecorder};
+use super::stuck_button::StuckButtonWatch;
 use super::virtual_trackpad::VirtualTrackpad;
 
 const READ_BATCH: usize = 64;
+const EV_REP: u16 = 0x14;
+const REP_DELAY: u16 = 0x00;
+const REP_PERIOD: u16 = 0x01;
 
 /// The `phys` marker stamped on our synthetic clone so device discovery
 /// can never mistake our own clone for a real touchpad (it impersonates
@@ -60,6 +81,25 @@ fn ui_set_phys(fd: RawFd, phys: &std::ffi::CStr) -> io::Result<()> {
     }
 }
 
+/// CLOCK_MONOTONIC now, in the same terms as the real device's event
+/// timestamps (which [`MtProxy::new`] switches to that clock).
+fn monotonic_now() -> Duration {
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+/// Whether `err` says the real device is gone (ENODEV): from a read, or
+/// from the ioctls of a resync -- on resume, the touchpad has often been
+/// re-enumerated before its first event could tell.
+pub fn is_unplug(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::ENODEV)
+}
+
+fn event_time(raw: &sys::input_event) -> Duration {
+    Duration::new(raw.time.tv_sec as u64, raw.time.tv_usec as u32 * 1000)
+}
+
 fn to_raw(ev: &Ev) -> sys::input_event {
     let mut raw = zero_event();
     raw.type_ = ev.type_;
@@ -68,13 +108,122 @@ fn to_raw(ev: &Ev) -> sys::input_event {
     raw
 }
 
+/// What a clone copies from its device: the identity and every
+/// capability. Two devices with the same fingerprint can share a clone
+/// without the compositor telling them apart.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Fingerprint {
+    pub id: InputId,
+    pub name: Vec<u8>,
+    /// The code bitmask of each event type the device has (EVIOCGBIT),
+    /// the list of types itself first.
+    pub codes: Vec<(u16, Vec<u8>)>,
+    /// Each absolute axis's range, fuzz, flat and resolution: everything
+    /// EVIOCGABS says but the current value.
+    pub axes: Vec<(u16, [i32; 5])>,
+    pub props: Vec<u16>,
+}
+
+impl Fingerprint {
+    fn read(real: &EvdevHandle<File>) -> io::Result<Self> {
+        let mut name = real.device_name()?;
+        while name.last() == Some(&0) {
+            name.pop();
+        }
+        let mut codes = Vec::new();
+        let mut bits = [0u8; sys::KEY_MAX as usize / 8 + 1];
+        for kind in std::iter::once(EventKind::Synchronize).chain(real.event_bits()?.iter()) {
+            if !is_mirrored(kind) && kind != EventKind::Synchronize {
+                continue;
+            }
+            let len = real.event_bits_raw(kind, &mut bits)?;
+            codes.push((kind as u16, bits[..len].to_vec()));
+        }
+        let mut axes = Vec::new();
+        for axis in real.absolute_bits()?.iter() {
+            let i = real.absolute_info(axis)?;
+            axes.push((
+                axis as u16,
+                [i.minimum, i.maximum, i.fuzz, i.flat, i.resolution],
+            ));
+        }
+        Ok(Fingerprint {
+            id: real.device_id()?,
+            name,
+            codes,
+            axes,
+            props: real.device_properties()?.iter().map(|p| p as u16).collect(),
+        })
+    }
+
+    /// What sets `other` apart from this, for the log; empty when it
+    /// can take over this one's clone.
+    pub fn differences(&self, other: &Fingerprint) -> Vec<&'static str> {
+        let mut differ = Vec::new();
+        if self.id != other.id {
+            differ.push("id");
+        }
+        if self.name != other.name {
+            differ.push("name");
+        }
+        if self.codes != other.codes {
+            differ.push("event codes");
+        }
+        if self.axes != other.axes {
+            differ.push("axis ranges");
+        }
+        if self.props != other.props {
+            differ.push("properties");
+        }
+        differ
+    }
+}
+
+/// Whether the clone copies event type `kind`. Everything but the
+/// synthetic types and force feedback: the clone would have to serve
+/// effect uploads itself, and touchpads don't have any.
+fn is_mirrored(kind: EventKind) -> bool {
+    !matches!(
+        kind,
+        EventKind::Synchronize
+            | EventKind::ForceFeedback
+            | EventKind::ForceFeedbackStatus
+            | EventKind::UInput
+    )
+}
+
+/// The uinput clone, with the fingerprint of the device it copies. It
+/// outlives its [`MtProxy`] when the touchpad is re-enumerated, so the
+/// same hardware coming back gets the same clone.
+pub struct SyntheticClone {
+    device: UInputHandle<File>,
+    fingerprint: Fingerprint,
+}
+
+impl SyntheticClone {
+    /// Destroys the uinput device.
+    pub fn destroy(self) -> io::Result<()> {
+        self.device.dev_destroy()
+    }
+}
+
 pub struct MtProxy {
     real: EvdevHandle<File>,
-    synth: UInputHandle<File>,
+    /// None when only observing: nothing grabbed, nothing cloned.
+    synth: Option<SyntheticClone>,
     raw_fd: RawFd,
     x_res: f64,
     y_res: f64,
     slot_count: usize,
+    /// The ABS_MT_* axes the device has besides tracking ID and
+    /// position: read back on every resync, along with those.
+    mt_extra: Vec<AbsoluteAxis>,
+    /// Set for a protocol A device (no ABS_MT_SLOT): its contact
+    /// tracking.
+    protocol_a: Option<ProtocolA>,
+    device_key: String,
+    recorder: SharedRecorder,
+    stuck: StuckButtonWatch,
     frame: Vec<Ev>,
     /// True between a SYN_DROPPED and the SYN_REPORT that closes it:
     /// per the evdev protocol, everything in that window is garbage and
@@ -89,11 +238,48 @@ impl AsRawFd for MtProxy {
     }
 }
 
+/// How many slots the machine tracks, given a device's ABS_MT_SLOT.
+fn slot_count_of(has_slots: bool, slot_info: Option<AbsoluteInfo>) -> usize {
+    match slot_info {
+        Some(info) if has_slots => (info.maximum.max(0) as usize + 1).clamp(1, MAX_SLOTS),
+        _ => MAX_SLOTS,
+    }
+}
+
+/// `vendor-product`, in hex: a stable, file-name safe key for a device,
+/// and for its synthetic clone (which carries the same identity).
+pub fn device_key_of(id: &input_linux::InputId) -> String {
+    format!("{:04x}-{:04x}", id.vendor, id.product)
+}
+
 impl MtProxy {
     /// Opens the real touchpad at `path`, grabs it exclusively for the
-    /// rest of the program's life, and creates a synthetic clone with
-    /// identical capabilities for the compositor to read instead.
-    pub fn new(path: &str) -> io::Result<Self> {
+    /// rest of the program's life, and gives the compositor a synthetic
+    /// clone with identical capabilities to read instead: `kept` (the
+    /// clone of a touchpad that was re-enumerated), if this is the same
+    /// hardware, or else a new one. Every frame and output passing
+    /// through is kept in `recorder`.
+    pub fn new(
+        path: &str,
+        recorder: SharedRecorder,
+        kept: Option<SyntheticClone>,
+    ) -> io::Result<Self> {
+        Self::open(path, recorder, false, kept)
+    }
+
+    /// Opens the real touchpad at `path` for reading only: no grab, so
+    /// the compositor keeps using it, and no clone. Outputs then go
+    /// nowhere (see [`virtual_trackpad::observer`](super::virtual_trackpad::observer)).
+    pub fn observe(path: &str, recorder: SharedRecorder) -> io::Result<Self> {
+        Self::open(path, recorder, true, None)
+    }
+
+    fn open(
+        path: &str,
+        recorder: SharedRecorder,
+        observe: bool,
+        kept: Option<SyntheticClone>,
+    ) -> io::Result<Self> {
         let real_file = OpenOptions::new()
             .read(true)
             .custom_flags(O_NONBLOCK)
@@ -101,12 +287,25 @@ impl MtProxy {
         let raw_fd = real_file.as_raw_fd();
         let real = EvdevHandle::new(real_file);
 
-        // held for the entire program lifetime -- see module doc for why
-        // this must never be released mid-gesture
-        real.grab(true)?;
-        info!("Exclusively grabbed the real trackpad at {}.", path);
+        if !observe {
+            // held for the entire program lifetime -- see module doc for
+            // why this must never be released mid-gesture
+            real.grab(true)?;
+            info!("Exclusively grabbed the real trackpad at {}.", path);
+        }
+        // Stamp events with CLOCK_MONOTONIC rather than wall time, so the
+        // relay latency metric can't be thrown off by clock adjustments.
+        if let Err(e) = real.set_clock_id(libc::CLOCK_MONOTONIC) {
+            debug!("Could not switch event timestamps to CLOCK_MONOTONIC: {e}");
+        }
 
-        let synth = Self::clone_device(&real)?;
+        let synth = if observe {
+            None
+        } else {
+            Some(Self::clone_for(&real, kept)?)
+        };
+        let id = real.device_id()?;
+        let device_key = device_key_of(&id);
 
         // Units-per-mm, from the device's reported resolution. Some
         // touchpads (various Synaptics/Elan units) report resolution 0;
@@ -131,13 +330,26 @@ impl MtProxy {
         };
         let x_res = axis_res(AbsoluteAxis::MultitouchPositionX, 100.0);
         let y_res = axis_res(AbsoluteAxis::MultitouchPositionY, 70.0);
+        let abs = real.absolute_bits()?;
         // The device's real slot range: snapshot ioctls sized past it
         // return zeroed entries whose tracking_id 0 reads as "finger
         // down" -- the phantom-touch bug. Ask the device, don't assume.
-        let slot_count = real
-            .absolute_info(AbsoluteAxis::MultitouchSlot)
-            .map(|i| (i.maximum as usize + 1).clamp(1, MAX_SLOTS))
-            .unwrap_or(MAX_SLOTS);
+        // A protocol A device has no ABS_MT_SLOT (EVIOCGABS reads it as
+        // all zeroes, a range of one), and gets as many as `ProtocolA`
+        // hands out.
+        let slot_count = slot_count_of(
+            abs.get(AbsoluteAxis::MultitouchSlot),
+            real.absolute_info(AbsoluteAxis::MultitouchSlot).ok(),
+        );
+        let mt_extra = MT_EXTRA_AXES
+            .iter()
+            .filter_map(|&code| AbsoluteAxis::from_code(code).ok())
+            .filter(|&axis| abs.get(axis))
+            .collect();
+        let protocol_a = (!abs.get(AbsoluteAxis::MultitouchSlot)).then(|| {
+            info!("The touchpad speaks multitouch protocol A; tracking its contacts into slots.");
+            ProtocolA::new()
+        });
 
         Ok(MtProxy {
             real,
@@ -146,6 +358,11 @@ impl MtProxy {
             x_res,
             y_res,
             slot_count,
+            mt_extra,
+            protocol_a,
+            device_key,
+            recorder,
+            stuck: StuckButtonWatch::new(Duration::ZERO),
             frame: Vec::with_capacity(READ_BATCH),
             dropping: false,
             read_buf: [zero_event(); READ_BATCH],
@@ -161,10 +378,140 @@ impl MtProxy {
     pub fn slot_count(&self) -> usize {
         self.slot_count
     }
+    /// Tells the stuck-button watch how long drag-lock may legitimately
+    /// hold the button with no finger down (`dragEndDelay`).
+    pub fn set_drag_lock(&mut self, drag_lock: Duration) {
+        self.stuck.set_drag_lock(drag_lock);
+    }
+
+    /// When the virtual button will be overdue for a forced release.
+    pub fn stuck_deadline(&self) -> Option<Instant> {
+        self.stuck.deadline()
+    }
+
+    /// Forces the virtual button up if it has been held with no finger
+    /// on the touchpad for longer than drag-lock explains -- regardless
+    /// of the machine, which may well still think a drag is live, and is
+    /// made to let go of it too. Loud, and with the flight recorder
+    /// saved: this is always a bug.
+    pub fn release_if_stuck(
+        &mut self,
+        machine: &mut GestureMachine,
+        vtp: &mut VirtualTrackpad,
+        now: Instant,
+    ) -> io::Result<()> {
+        let Some(held) = self.stuck.overdue(now) else {
+            return Ok(());
+        };
+        error!(
+            "Virtual mouse button held for {held:?} with no finger on the touchpad; \
+            forcing its release (the gesture machine {} a drag held).",
+            if machine.button_held() {
+                "still had"
+            } else {
+                "did not have"
+            }
+        );
+        machine.metrics_mut().stuck_releases += 1;
+        let mut release = machine.force_release();
+        // the buttons are released whatever the machine thought of them
+        if vtp.right_is_down && !release.iter().any(|o| matches!(o, Output::RightUp)) {
+            release.push(Output::RightUp);
+        }
+        if vtp.mouse_is_down && !release.iter().any(|o| matches!(o, Output::MouseUp)) {
+            release.push(Output::MouseUp);
+        }
+        self.apply(&release, machine, vtp)?;
+        self.dump_incident("stuck-button");
+        Ok(())
+    }
+
+    /// Starts over from the device's current state, after the system
+    /// slept (or right before it does): the button released, the clone
+    /// emptied, and the machine reset onto a fresh snapshot.
+    pub fn reset(
+        &mut self,
+        machine: &mut GestureMachine,
+        vtp: &mut VirtualTrackpad,
+        why: &str,
+    ) -> io::Result<()> {
+        info!("{why}: releasing the button and resetting the gesture state.");
+        let snapshot = self.snapshot()?;
+        self.reset_to(machine, vtp, &snapshot)
+    }
 
-    /// Builds a synthetic uinput device with the same EV_KEY/EV_ABS/
-    /// INPUT_PROP capabilities as the real device, so the compositor's
-    /// libinput sees something functionally identical to the hardware.
+    /// Tells the clone every finger is up and the button is released,
+    /// with the machine reset to match -- for when the real device has
+    /// gone away, so there's no state of it to read.
+    pub fn lift_all(
+        &mut self,
+        machine: &mut GestureMachine,
+        vtp: &mut VirtualTrackpad,
+    ) -> io::Result<()> {
+        self.reset_to(machine, vtp, &Snapshot::default())
+    }
+
+    fn reset_to(
+        &mut self,
+        machine: &mut GestureMachine,
+        vtp: &mut VirtualTrackpad,
+        snapshot: &Snapshot,
+    ) -> io::Result<()> {
+        self.frame.clear();
+        let now = Instant::now();
+        self.recorder().resync(now, snapshot);
+        self.stuck.on_resync(&snapshot.slots);
+        let mut outs = machine.reset(snapshot, now);
+        // the buttons are released whatever the machine thought of them
+        if vtp.right_is_down && !outs.iter().any(|o| matches!(o, Output::RightUp)) {
+            outs.insert(0, Output::RightUp);
+        }
+        if vtp.mouse_is_down && !outs.iter().any(|o| matches!(o, Output::MouseUp)) {
+            outs.insert(0, Output::MouseUp);
+        }
+        self.apply(&outs, machine, vtp)
+    }
+
+    /// `vendor-product` of the real device, in hex: a stable, file-name
+    /// safe key for per-device state.
+    pub fn device_key(&self) -> &str {
+        &self.device_key
+    }
+
+    /// `kept` if it's a clone of this very hardware, or else a new clone
+    /// (with `kept` destroyed first, so the compositor never sees two).
+    fn clone_for(
+        real: &EvdevHandle<File>,
+        kept: Option<SyntheticClone>,
+    ) -> io::Result<SyntheticClone> {
+        let fingerprint = Fingerprint::read(real)?;
+        if let Some(kept) = kept {
+            let differ = kept.fingerprint.differences(&fingerprint);
+            if differ.is_empty() {
+                info!("The touchpad is the same hardware as before; keeping its clone.");
+                return Ok(kept);
+            }
+            info!(
+                "The touchpad differs from before ({}); replacing its clone.",
+                differ.join(", ")
+            );
+            if let Err(e) = kept.destroy() {
+                warn!("Could not destroy the old clone: {e}");
+            }
+        }
+        Ok(SyntheticClone {
+            device: Self::clone_device(real)?,
+            fingerprint,
+        })
+    }
+
+    /// Builds a synthetic uinput device with every capability of the
+    /// real device -- each event type it has and each code of each type,
+    /// axis ranges, INPUT_PROP bits and autorepeat settings -- so the
+    /// compositor's libinput sees something identical to the hardware,
+    /// MSC_TIMESTAMP included. Force feedback is the one exception: the
+    /// clone would have to serve effect uploads itself, and touchpads
+    /// don't have any.
     fn clone_device(real: &EvdevHandle<File>) -> io::Result<UInputHandle<File>> {
         let uinput_file = OpenOptions::new()
             .read(true)
@@ -174,18 +521,51 @@ impl MtProxy {
         let uinput_fd = uinput_file.as_raw_fd();
         let synth = UInputHandle::new(uinput_file);
 
-        synth.set_evbit(EventKind::Key)?;
-        synth.set_evbit(EventKind::Absolute)?;
-
-        for key in real.key_bits()?.iter() {
-            synth.set_keybit(key)?;
-        }
-
         let mut abs_setups = Vec::new();
-        for axis in real.absolute_bits()?.iter() {
-            synth.set_absbit(axis)?;
-            let info = real.absolute_info(axis)?;
-            abs_setups.push(AbsoluteInfoSetup { axis, info });
+        let mut repeat = None;
+        for kind in real.event_bits()?.iter().filter(|&kind| is_mirrored(kind)) {
+            synth.set_evbit(kind)?;
+            match kind {
+                EventKind::Key => {
+                    for key in real.key_bits()?.iter() {
+                        synth.set_keybit(key)?;
+                    }
+                }
+                EventKind::Relative => {
+                    for axis in real.relative_bits()?.iter() {
+                        synth.set_relbit(axis)?;
+                    }
+                }
+                EventKind::Absolute => {
+                    for axis in real.absolute_bits()?.iter() {
+                        synth.set_absbit(axis)?;
+                        let info = real.absolute_info(axis)?;
+                        abs_setups.push(AbsoluteInfoSetup { axis, info });
+                    }
+                }
+                EventKind::Misc => {
+                    for misc in real.misc_bits()?.iter() {
+                        synth.set_mscbit(misc)?;
+                    }
+                }
+                EventKind::Switch => {
+                    for switch in real.switch_bits()?.iter() {
+                        synth.set_swbit(switch)?;
+                    }
+                }
+                EventKind::Led => {
+                    for led in real.led_bits()?.iter() {
+                        synth.set_ledbit(led)?;
+                    }
+                }
+                EventKind::Sound => {
+                    for sound in real.sound_bits()?.iter() {
+                        synth.set_sndbit(sound)?;
+                    }
+                }
+                EventKind::Autorepeat => repeat = Some(real.repeat_settings()?),
+                _ => {}
+            }
         }
 
         for prop in real.device_properties()?.iter() {
@@ -214,6 +594,14 @@ impl MtProxy {
         synth
             .create(&real_id, &real_name, 0, &abs_setups)
             .map_err(|e| io::Error::new(e.kind(), format!("uinput create: {e}")))?;
+        if let Some(repeat) = repeat {
+            // uinput takes the autorepeat timing as EV_REP events once
+            // the device exists (it starts at the kernel's defaults)
+            let rep = |code: u16, value: u32| {
+                to_raw(&Ev::new(EV_REP, code, value.min(i32::MAX as u32) as i32))
+            };
+            synth.write(&[rep(REP_DELAY, repeat.delay), rep(REP_PERIOD, repeat.period)])?;
+        }
         debug!(
             "Synthetic touchpad clone created, impersonating \"{}\".",
             String::from_utf8_lossy(&real_name)
@@ -253,9 +641,13 @@ impl MtProxy {
                     // discard it, then re-read authoritative state.
                     if raw.type_ == EV_SYN && raw.code == SYN_REPORT {
                         self.dropping = false;
-                        let snapshot = self.slot_snapshot()?;
-                        let outs = machine.on_resync(&snapshot, Instant::now());
-                        self.apply(&outs, vtp)?;
+                        let snapshot = self.snapshot()?;
+                        let now = Instant::now();
+                        self.recorder().resync(now, &snapshot);
+                        self.stuck.on_resync(&snapshot.slots);
+                        let outs = machine.on_resync(&snapshot, now);
+                        self.apply(&outs, machine, vtp)?;
+                        self.dump_incident("resync");
                     }
                     continue;
                 }
@@ -270,33 +662,125 @@ impl MtProxy {
                 self.frame.push(Ev::new(raw.type_, raw.code, raw.value));
 
                 if raw.type_ == EV_SYN && raw.code == SYN_REPORT {
-                    let outs = machine.on_frame(&self.frame, Instant::now());
+                    let now = Instant::now();
+                    let tracked;
+                    let frame = match &mut self.protocol_a {
+                        Some(protocol_a) => {
+                            tracked = protocol_a.to_slots(&self.frame);
+                            &tracked
+                        }
+                        None => &self.frame,
+                    };
+                    self.recorder().frame(now, frame);
+                    self.stuck.on_frame(frame);
+                    let outs = machine.on_frame(frame, now);
                     self.frame.clear();
-                    self.apply(&outs, vtp)?;
+                    self.apply(&outs, machine, vtp)?;
+                    if !outs.is_empty() {
+                        let latency = monotonic_now().saturating_sub(event_time(&raw));
+                        machine.metrics_mut().relay_latency.record(latency);
+                    }
+                    if let Some(incident) = machine.take_incident() {
+                        self.dump_incident(incident.reason());
+                    }
                 }
             }
         }
     }
 
     /// Applies the machine's outputs to the actual devices, in order.
-    pub fn apply(&mut self, outputs: &[Output], vtp: &mut VirtualTrackpad) -> io::Result<()> {
+    pub fn apply(
+        &mut self,
+        outputs: &[Output],
+        machine: &GestureMachine,
+        vtp: &mut VirtualTrackpad,
+    ) -> io::Result<()> {
+        self.recorder().outputs(Instant::now(), outputs);
         for output in outputs {
             match output {
                 Output::EmitSynth(evs) => {
-                    let raw: Vec<sys::input_event> = evs.iter().map(to_raw).collect();
-                    self.synth.write(&raw)?;
+                    if let Some(synth) = &self.synth {
+                        let synth = &synth.device;
+                        let raw: Vec<sys::input_event> = match &mut self.protocol_a {
+                            Some(protocol_a) => {
+                                protocol_a.to_protocol_a(evs).iter().map(to_raw).collect()
+                            }
+                            None => evs.iter().map(to_raw).collect(),
+                        };
+                        synth.write(&raw)?;
+                    }
                 }
                 Output::MouseDown => vtp.mouse_down()?,
                 Output::MouseUp => vtp.mouse_up()?,
+                Output::RightDown => vtp.right_down()?,
+                Output::RightUp => vtp.right_up()?,
                 Output::MouseMove { dx, dy } => vtp.mouse_move_relative(*dx, *dy)?,
             }
         }
+        self.stuck
+            .update(vtp.any_down(), machine.lock_latched(), Instant::now());
         Ok(())
     }
 
+    fn recorder(&self) -> MutexGuard<'_, FlightRecorder> {
+        // a poisoned lock only means a panic elsewhere; the history
+        // itself is still intact
+        self.recorder.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// [`dump`](Self::dump) for something the proxy noticed itself:
+    /// skipped if the same reason was dumped within the last minute.
+    fn dump_incident(&self, reason: &str) {
+        if !self.recorder().dump_due(reason, Instant::now()) {
+            debug!("Flight recorder ({reason}) already saved within the last minute.");
+            return;
+        }
+        self.dump(reason);
+    }
+
+    /// Saves the flight recorder's history, so the moments leading up to
+    /// `reason` can be attached to a bug report.
+    pub fn dump(&self, reason: &str) {
+        match self.recorder().dump(reason) {
+            Ok(path) => info!("Flight recorder ({reason}) saved to {}.", path.display()),
+            Err(e) => warn!("Could not save the flight recorder ({reason}): {e}"),
+        }
+    }
+
+    /// The real device's whole state straight from the kernel: its
+    /// slots, every key it has (EVIOCGKEY) and every absolute axis
+    /// (EVIOCGABS).
+    fn snapshot(&self) -> io::Result<Snapshot> {
+        let mut pressed = [0u8; sys::KEY_MAX as usize / 8 + 1];
+        self.real.key_state(&mut pressed)?;
+        let keys = self
+            .real
+            .key_bits()?
+            .iter()
+            .map(|key| {
+                let code = key as usize;
+                (code as u16, i32::from(pressed[code / 8] >> (code % 8) & 1))
+            })
+            .collect();
+        let mut abs = Vec::new();
+        for axis in self.real.absolute_bits()?.iter() {
+            abs.push((axis as u16, self.real.absolute_info(axis)?.value));
+        }
+        Ok(Snapshot {
+            slots: self.slot_snapshot()?,
+            keys,
+            abs,
+        })
+    }
+
     /// Authoritative per-slot state straight from the kernel
-    /// (EVIOCGMTSLOTS), sized to the device's true slot range.
-    fn slot_snapshot(&self) -> io::Result<Vec<(i32, i32, i32)>> {
+    /// (EVIOCGMTSLOTS, for every MT axis the device has), sized to the
+    /// device's true slot range. A protocol A device has no slots to ask
+    /// about: its contact tracking stands in.
+    fn slot_snapshot(&self) -> io::Result<Vec<SlotState>> {
+        if let Some(protocol_a) = &self.protocol_a {
+            return Ok(protocol_a.snapshot());
+        }
         let mut ids = vec![0i32; self.slot_count];
         self.real
             .multi_touch_slots(AbsoluteAxis::MultitouchTrackingId, &mut ids)?;
@@ -307,14 +791,40 @@ impl MtProxy {
         self.real
             .multi_touch_slots(AbsoluteAxis::MultitouchPositionY, &mut ys)?;
 
-        Ok((0..self.slot_count)
-            .map(|s| (ids[s], xs[s], ys[s]))
-            .collect())
+        let mut snapshot: Vec<SlotState> = (0..self.slot_count)
+            .map(|s| SlotState::new(ids[s], xs[s], ys[s]))
+            .collect();
+        let mut values = vec![0i32; self.slot_count];
+        for &axis in &self.mt_extra {
+            self.real.multi_touch_slots(axis, &mut values)?;
+            for (state, &value) in snapshot.iter_mut().zip(&values) {
+                state.set(axis as u16, value);
+            }
+        }
+        Ok(snapshot)
     }
 
-    /// Destroys the synthetic clone (the grab on the real device is
-    /// released automatically when the fd closes on drop).
+    /// Destroys the synthetic clone, then releases the grab on the real
+    /// device, in that order: the compositor's libinput sees the clone go
+    /// away before the real touchpad starts talking to it again. (The
+    /// grab would go with the fd anyway, but not necessarily in order.)
     pub fn destruct(self) -> io::Result<()> {
-        self.synth.dev_destroy()
+        let Some(synth) = self.synth else {
+            return Ok(()); // only observing: nothing to undo
+        };
+        let destroyed = synth.destroy();
+        let ungrabbed = self.real.grab(false);
+        destroyed.and(ungrabbed)
+    }
+
+    /// Lets go of a real device that went away, keeping its clone for
+    /// [`new`](Self::new) to hand on to the device that comes back. The
+    /// clone should have been emptied first ([`lift_all`](Self::lift_all)),
+    /// so the compositor sees every finger lift during the gap.
+    pub fn into_clone(self) -> Option<SyntheticClone> {
+        self.synth
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,108 @@
+//! Device setup: how many slots to track, and when a re-enumerated
+//! touchpad may keep its clone.
+
+use super::*;
+use crate::runtime::gesture::{Timing, ABS_MT_POSITION_X, ABS_MT_POSITION_Y, PX_PER_MM};
+use crate::runtime::protocol_a::SYN_MT_REPORT;
+
+fn fingerprint() -> Fingerprint {
+    Fingerprint {
+        id: InputId {
+            bustype: 0x18,
+            vendor: 0x6cb,
+            product: 0xcdcd,
+            version: 0x100,
+        },
+        name: b"SYNA1234:00 06CB:CDCD Touchpad".to_vec(),
+        codes: vec![(0x00, vec![0x1b]), (0x01, vec![0, 0, 0x01, 0x60])],
+        axes: vec![(0x35, [0, 3000, 0, 0, 30]), (0x36, [0, 2000, 0, 0, 30])],
+        props: vec![0, 2],
+    }
+}
+
+#[test]
+fn only_the_same_hardware_keeps_its_clone() {
+    let before = fingerprint();
+    assert!(before.differences(&fingerprint()).is_empty());
+
+    // a new firmware version is a different device to the compositor
+    let mut after = fingerprint();
+    after.id.version = 0x101;
+    assert_eq!(before.differences(&after), ["id"]);
+
+    // re-enumerated in another mode: fewer codes, another resolution
+    let mut after = fingerprint();
+    after.codes[1].1[3] = 0x20;
+    after.axes[0].1[4] = 12;
+    assert_eq!(before.differences(&after), ["event codes", "axis ranges"]);
+
+    let mut after = fingerprint();
+    after.name = b"SynPS/2 Synaptics TouchPad".to_vec();
+    after.props.pop();
+    assert_eq!(before.differences(&after), ["name", "properties"]);
+}
+
+#[test]
+fn slot_count_follows_the_device_and_protocol_a_gets_every_slot() {
+    let slots = |maximum| {
+        Some(AbsoluteInfo {
+            maximum,
+            ..AbsoluteInfo::default()
+        })
+    };
+    assert_eq!(slot_count_of(true, slots(4)), 5);
+    assert_eq!(slot_count_of(true, slots(1)), 2);
+    assert_eq!(slot_count_of(true, slots(100)), MAX_SLOTS);
+    assert_eq!(slot_count_of(true, None), MAX_SLOTS);
+    // what EVIOCGABS reads for an axis a protocol A device doesn't have
+    assert_eq!(
+        slot_count_of(false, Some(AbsoluteInfo::default())),
+        MAX_SLOTS
+    );
+}
+
+/// A protocol A pad, with the slot count `open` gives it, drags.
+#[test]
+fn a_protocol_a_pad_drags_with_its_slot_count() {
+    let timing = Timing {
+        probe_delay: Duration::from_millis(15),
+        entry_debounce: Duration::from_millis(50),
+        drag_end_delay: Duration::ZERO,
+        press_grace: Duration::from_millis(75),
+        px_per_mm: PX_PER_MM,
+    };
+    let slot_count = slot_count_of(false, Some(AbsoluteInfo::default()));
+    let mut m = GestureMachine::new(timing, 10.0, 10.0, slot_count);
+    // (with the 1 slot the zeroed ABS_MT_SLOT range suggests, all three
+    // contacts would fold into one finger)
+    let mut a = ProtocolA::new();
+    let mut now = Instant::now();
+    let mut outs = Vec::new();
+    for (contacts, ms) in [(1, 0), (2, 5), (3, 5), (3, 45)] {
+        let mut frame = Vec::new();
+        for i in 0..contacts {
+            frame.push(Ev::abs(ABS_MT_POSITION_X, 500 + 100 * i));
+            frame.push(Ev::abs(ABS_MT_POSITION_Y, 500));
+            frame.push(Ev::new(EV_SYN, SYN_MT_REPORT, 0));
+        }
+        frame.push(Ev::syn());
+        now += Duration::from_millis(ms);
+        outs.extend(m.on_frame(&a.to_slots(&frame), now));
+        outs.extend(m.on_tick(now));
+    }
+    assert!(
+        !outs.iter().any(|o| matches!(o, Output::EmitSynth(_))),
+        "a committed drag leaks nothing: {outs:?}"
+    );
+    outs.extend(m.on_tick(now + Duration::from_millis(80)));
+    assert!(outs.contains(&Output::MouseDown), "{outs:?}");
+}
+
+/// What the EVIOCG* ioctls of a reset return once the device has gone
+/// (say, re-enumerated over a suspend) is an unplug, not a failure.
+#[test]
+fn a_reset_on_a_vanished_device_is_an_unplug() {
+    assert!(is_unplug(&io::Error::from_raw_os_error(libc::ENODEV)));
+    assert!(!is_unplug(&io::Error::from_raw_os_error(libc::EIO)));
+    assert!(!is_unplug(&io::Error::other("panicked")));
+}
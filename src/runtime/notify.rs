@@ -0,0 +1,101 @@
+//! systemd service notifications (`sd_notify`), without libsystemd.
+//!
+//! With `Type=notify`, systemd only considers the service started once
+//! it says `READY=1` -- which we do after the real touchpad is grabbed
+//! and both virtual devices exist, not merely once the process is
+//! running. `STATUS=` keeps `systemctl status` saying what's going on
+//! (which device, rediscovering...), and if the unit sets `WatchdogSec=`,
+//! the event loop pings `WATCHDOG=1` at half that interval: a wedged loop
+//! stops pinging, and systemd restarts us.
+//!
+//! The protocol is a datagram of `KEY=value` lines to the socket named
+//! in `$NOTIFY_SOCKET` (a leading `@` meaning the abstract namespace).
+
+use std::ffi::OsString;
+use std::io;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+use tracing::debug;
+
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// The notifier systemd asked for, if it did (`$NOTIFY_SOCKET`).
+    pub fn from_env() -> Option<Self> {
+        Self::from_vars(
+            std::env::var_os("NOTIFY_SOCKET"),
+            std::env::var("WATCHDOG_USEC").ok(),
+            std::env::var("WATCHDOG_PID").ok(),
+        )
+        .inspect_err(|e| debug!("Not notifying systemd: {e}"))
+        .ok()
+        .flatten()
+    }
+
+    /// [`from_env`](Self::from_env), from the values of `NOTIFY_SOCKET`,
+    /// `WATCHDOG_USEC` and `WATCHDOG_PID`.
+    pub fn from_vars(
+        socket: Option<OsString>,
+        watchdog_usec: Option<String>,
+        watchdog_pid: Option<String>,
+    ) -> io::Result<Option<Self>> {
+        let Some(socket) = socket else {
+            return Ok(None);
+        };
+        let bytes = socket.as_bytes();
+        let addr = match bytes.strip_prefix(b"@") {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(&socket)?,
+        };
+        // the watchdog is for us only if it's for our PID (when given)
+        let for_us = watchdog_pid.is_none_or(|pid| pid.parse() == Ok(std::process::id()));
+        let watchdog = watchdog_usec
+            .filter(|_| for_us)
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|&usec| usec > 0)
+            .map(Duration::from_micros);
+        Ok(Some(Notifier {
+            socket: UnixDatagram::unbound()?,
+            addr,
+            watchdog,
+        }))
+    }
+
+    fn send(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            debug!("sd_notify({state:?}) failed: {e}");
+        }
+    }
+
+    pub fn ready(&self, status: &str) {
+        self.send(&format!("READY=1\nSTATUS={status}"));
+    }
+
+    pub fn status(&self, status: &str) {
+        self.send(&format!("STATUS={status}"));
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    pub fn watchdog(&self) {
+        self.send("WATCHDOG=1");
+    }
+
+    /// How often to ping the watchdog, if there is one: half its
+    /// timeout, as systemd recommends.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,77 @@
+//! sd_notify against a local socket standing in for systemd's.
+
+use super::*;
+
+fn listen(name: &str) -> (UnixDatagram, OsString) {
+    let path = std::env::temp_dir().join(format!("3fd-notify-{}-{name}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let server = UnixDatagram::bind(&path).unwrap();
+    server
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    (server, path.into_os_string())
+}
+
+fn recv(server: &UnixDatagram) -> String {
+    let mut buf = [0u8; 512];
+    let n = server.recv(&mut buf).unwrap();
+    String::from_utf8(buf[..n].to_vec()).unwrap()
+}
+
+#[test]
+fn no_socket_means_no_notifier() {
+    assert!(Notifier::from_vars(None, None, None).unwrap().is_none());
+}
+
+#[test]
+fn sends_ready_status_and_watchdog() {
+    let (server, path) = listen("messages");
+    let notifier = Notifier::from_vars(Some(path), None, None)
+        .unwrap()
+        .unwrap();
+    notifier.ready("Proxying /dev/input/event7");
+    assert_eq!(recv(&server), "READY=1\nSTATUS=Proxying /dev/input/event7");
+    notifier.status("Touchpad lost; rediscovering");
+    assert_eq!(recv(&server), "STATUS=Touchpad lost; rediscovering");
+    notifier.watchdog();
+    assert_eq!(recv(&server), "WATCHDOG=1");
+}
+
+#[test]
+fn reaches_abstract_sockets() {
+    let name = format!("3fd-notify-test-{}", std::process::id());
+    let addr = SocketAddr::from_abstract_name(name.as_bytes()).unwrap();
+    let server = UnixDatagram::bind_addr(&addr).unwrap();
+    server
+        .set_read_timeout(Some(Duration::from_secs(1)))
+        .unwrap();
+    let notifier = Notifier::from_vars(Some(format!("@{name}").into()), None, None)
+        .unwrap()
+        .unwrap();
+    notifier.stopping();
+    assert_eq!(recv(&server), "STOPPING=1");
+}
+
+#[test]
+fn watchdog_pings_at_half_the_timeout_and_only_for_our_pid() {
+    let (_server, path) = listen("watchdog");
+    let ours = std::process::id().to_string();
+    let with = |usec: Option<&str>, pid: Option<&str>| {
+        Notifier::from_vars(
+            Some(path.clone()),
+            usec.map(String::from),
+            pid.map(String::from),
+        )
+        .unwrap()
+        .unwrap()
+        .watchdog_interval()
+    };
+    assert_eq!(with(Some("10000000"), None), Some(Duration::from_secs(5)));
+    assert_eq!(
+        with(Some("10000000"), Some(&ours)),
+        Some(Duration::from_secs(5))
+    );
+    assert_eq!(with(Some("10000000"), Some("1")), None);
+    assert_eq!(with(None, None), None);
+    assert_eq!(with(Some("0"), None), None);
+}
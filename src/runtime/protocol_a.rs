@@ -0,0 +1,375 @@
+//! The front end for legacy multitouch protocol A devices.
+//!
+//! A protocol B device (anything with `ABS_MT_SLOT`) tells us which
+//! finger each update belongs to. A protocol A device doesn't: every
+//! frame lists every contact anew, each closed by a `SYN_MT_REPORT`,
+//! anonymous and in no particular order. That's what some older and
+//! virtualized touchpads (and VM/remote-desktop input devices) send.
+//!
+//! [`ProtocolA`] sits between such a device and the gesture machine,
+//! in both directions:
+//!
+//! * [`to_slots`](ProtocolA::to_slots) tracks contacts from frame to
+//!   frame -- by the device's own `ABS_MT_TRACKING_ID` when it sends
+//!   one, otherwise by nearest position -- and turns each frame into
+//!   the protocol B frame the machine expects, with slots, tracking IDs
+//!   of our own and only the axes that changed. (As with the kernel's
+//!   own tracking, one finger lifting in the very frame another lands
+//!   reads as a single finger jumping.)
+//! * [`to_protocol_a`](ProtocolA::to_protocol_a) turns what the machine
+//!   emits for the clone back into protocol A, since the clone has the
+//!   real device's capabilities (no slots) and must mirror it. A frame
+//!   the machine relays as it came is sent as the device sent it; only
+//!   the machine's own frames (releases, corrections, a touch handed
+//!   over mid-way) are written out from the clone's contacts, each with
+//!   all its axes -- and with the device's tracking IDs only if it sends
+//!   them.
+
+use std::collections::VecDeque;
+
+use super::gesture::{Ev, ABS_MT_POSITION_X, ABS_MT_POSITION_Y, ABS_MT_SLOT, ABS_MT_TRACKING_ID};
+use super::gesture::{SlotState, EV_ABS, EV_SYN, MAX_SLOTS, SYN_REPORT};
+use super::gesture::{ABS_MT_TOOL_Y, ABS_MT_TOUCH_MAJOR};
+
+pub const SYN_MT_REPORT: u16 = 0x02;
+
+/// Converted frames remembered for relaying as the device sent them: a
+/// touch is buffered for at most `entryDebounce` before it's relayed,
+/// which this covers at any report rate a touchpad has.
+const RELAYABLE: usize = 256;
+
+/// The ABS_MT_* axes, `ABS_MT_TOUCH_MAJOR` through `ABS_MT_TOOL_Y`.
+fn is_mt_axis(code: u16) -> bool {
+    (ABS_MT_TOUCH_MAJOR..=ABS_MT_TOOL_Y).contains(&code)
+}
+
+/// One contact, as last reported.
+#[derive(Clone, Debug)]
+struct Contact {
+    /// The device's own tracking ID for it, if it sends them.
+    device_id: Option<i32>,
+    /// Every ABS_MT_* value but the tracking ID.
+    axes: Vec<(u16, i32)>,
+}
+
+impl Contact {
+    fn parse(events: &[Ev]) -> Self {
+        let mut contact = Contact {
+            device_id: None,
+            axes: Vec::new(),
+        };
+        for ev in events {
+            if ev.code == ABS_MT_TRACKING_ID {
+                contact.device_id = Some(ev.value);
+            } else if let Some(entry) = contact.axes.iter_mut().find(|a| a.0 == ev.code) {
+                entry.1 = ev.value;
+            } else {
+                contact.axes.push((ev.code, ev.value));
+            }
+        }
+        contact
+    }
+
+    fn axis(&self, code: u16) -> Option<i32> {
+        self.axes.iter().find(|a| a.0 == code).map(|a| a.1)
+    }
+
+    /// Squared distance to `other`, for matching anonymous contacts.
+    fn distance(&self, other: &Contact) -> i64 {
+        let d = |code| {
+            (self.axis(code).unwrap_or(0) as i64 - other.axis(code).unwrap_or(0) as i64).pow(2)
+        };
+        d(ABS_MT_POSITION_X) + d(ABS_MT_POSITION_Y)
+    }
+}
+
+/// A tracked contact: our tracking ID for it, and its last state.
+#[derive(Clone, Debug)]
+struct Tracked {
+    id: i32,
+    contact: Contact,
+}
+
+/// Contact tracking for one protocol A device, and the clone's view of
+/// the same contacts.
+pub struct ProtocolA {
+    /// The real device's contacts, by the slot we gave them.
+    slots: Vec<Option<Tracked>>,
+    next_id: i32,
+    /// Recent frames as converted, with the frame the device sent: a
+    /// converted frame the machine relays unchanged goes to the clone
+    /// as the original.
+    relayable: VecDeque<(Vec<Ev>, Vec<Ev>)>,
+    /// The contacts the clone has been told about, by slot, with the
+    /// device's tracking IDs for them.
+    clone: Vec<Option<Contact>>,
+    clone_slot: usize,
+    /// Whether the clone's last frame listed any contact.
+    clone_touching: bool,
+}
+
+impl ProtocolA {
+    pub fn new() -> Self {
+        ProtocolA {
+            slots: vec![None; MAX_SLOTS],
+            next_id: 0,
+            relayable: VecDeque::with_capacity(RELAYABLE),
+            clone: vec![None; MAX_SLOTS],
+            clone_slot: 0,
+            clone_touching: false,
+        }
+    }
+
+    /// Converts one complete protocol A frame (up to and including its
+    /// SYN_REPORT) into the equivalent slotted frame.
+    pub fn to_slots(&mut self, frame: &[Ev]) -> Vec<Ev> {
+        let mut contacts = Vec::new();
+        let mut current = Vec::new();
+        let mut rest = Vec::new();
+        for &ev in frame {
+            if ev.type_ == EV_SYN && ev.code == SYN_MT_REPORT {
+                // a lone SYN_MT_REPORT just says "no contacts"
+                if !current.is_empty() {
+                    contacts.push(Contact::parse(&current));
+                    current.clear();
+                }
+            } else if ev.type_ == EV_ABS && (is_mt_axis(ev.code) || ev.code == ABS_MT_TRACKING_ID) {
+                current.push(ev);
+            } else if !(ev.type_ == EV_SYN && ev.code == SYN_REPORT) {
+                rest.push(ev);
+            }
+        }
+        if !current.is_empty() {
+            // a last contact the device didn't close: take it anyway
+            contacts.push(Contact::parse(&current));
+        }
+
+        let matched = self.match_contacts(&contacts);
+        let mut out = Vec::new();
+        // lifted first, so a new contact can take over a freed slot
+        for slot in 0..MAX_SLOTS {
+            if self.slots[slot].is_some() && !matched.contains(&Some(slot)) {
+                self.slots[slot] = None;
+                out.push(Ev::abs(ABS_MT_SLOT, slot as i32));
+                out.push(Ev::abs(ABS_MT_TRACKING_ID, -1));
+            }
+        }
+        for (contact, slot) in contacts.into_iter().zip(matched) {
+            match slot {
+                Some(slot) => {
+                    let tracked = self.slots[slot].as_mut().expect("matched to a live slot");
+                    let changed: Vec<_> = contact
+                        .axes
+                        .iter()
+                        .filter(|&&(code, value)| tracked.contact.axis(code) != Some(value))
+                        .map(|&(code, value)| Ev::abs(code, value))
+                        .collect();
+                    if !changed.is_empty() {
+                        out.push(Ev::abs(ABS_MT_SLOT, slot as i32));
+                        out.extend(changed);
+                    }
+                    tracked.contact = contact;
+                }
+                None => {
+                    let Some(slot) = self.slots.iter().position(Option::is_none) else {
+                        continue; // more contacts than we can track
+                    };
+                    let id = self.take_id();
+                    out.push(Ev::abs(ABS_MT_SLOT, slot as i32));
+                    out.push(Ev::abs(ABS_MT_TRACKING_ID, id));
+                    out.extend(
+                        contact
+                            .axes
+                            .iter()
+                            .map(|&(code, value)| Ev::abs(code, value)),
+                    );
+                    self.slots[slot] = Some(Tracked { id, contact });
+                }
+            }
+        }
+        out.extend(rest);
+        out.push(Ev::syn());
+        if self.relayable.len() == RELAYABLE {
+            self.relayable.pop_front();
+        }
+        self.relayable.push_back((out.clone(), frame.to_vec()));
+        out
+    }
+
+    /// A tracking ID for a new contact. They wrap at 0xffff as the
+    /// kernel's do, skipping any still down.
+    fn take_id(&mut self) -> i32 {
+        loop {
+            let id = self.next_id;
+            self.next_id = (self.next_id + 1) % 0x10000;
+            if !self.slots.iter().flatten().any(|tracked| tracked.id == id) {
+                return id;
+            }
+        }
+    }
+
+    /// Which live slot each of `contacts` continues, if any: by the
+    /// device's tracking ID where it sends one, otherwise the nearest
+    /// pairs first.
+    fn match_contacts(&self, contacts: &[Contact]) -> Vec<Option<usize>> {
+        let mut matched = vec![None; contacts.len()];
+        let mut taken = [false; MAX_SLOTS];
+        let mut pairs = Vec::new();
+        for (i, contact) in contacts.iter().enumerate() {
+            for (slot, tracked) in self.slots.iter().enumerate() {
+                let Some(tracked) = tracked else { continue };
+                match (contact.device_id, tracked.contact.device_id) {
+                    (Some(a), Some(b)) if a == b && !taken[slot] && matched[i].is_none() => {
+                        matched[i] = Some(slot);
+                        taken[slot] = true;
+                    }
+                    (None, None) => pairs.push((contact.distance(&tracked.contact), i, slot)),
+                    _ => {}
+                }
+            }
+        }
+        pairs.sort_unstable();
+        for (_, i, slot) in pairs {
+            if matched[i].is_none() && !taken[slot] {
+                matched[i] = Some(slot);
+                taken[slot] = true;
+            }
+        }
+        matched
+    }
+
+    /// The tracked contacts as a resync snapshot. There's nothing to ask
+    /// the kernel: a protocol A device keeps no per-contact state, and
+    /// its next frame lists every contact again anyway.
+    pub fn snapshot(&self) -> Vec<SlotState> {
+        self.slots
+            .iter()
+            .map(|tracked| {
+                let mut state = SlotState::UP;
+                if let Some(t) = tracked {
+                    state.tracking_id = t.id;
+                    for &(code, value) in &t.contact.axes {
+                        state.set(code, value);
+                    }
+                }
+                state
+            })
+            .collect()
+    }
+
+    /// Converts slotted events meant for the clone (one or more frames,
+    /// as the machine emits them) into protocol A frames.
+    pub fn to_protocol_a(&mut self, evs: &[Ev]) -> Vec<Ev> {
+        let mut out = Vec::new();
+        let mut rest = Vec::new();
+        let mut start = 0;
+        for (i, &ev) in evs.iter().enumerate() {
+            if ev.type_ == EV_ABS && ev.code == ABS_MT_SLOT {
+                self.clone_slot = (ev.value.max(0) as usize).min(MAX_SLOTS - 1);
+            } else if ev.type_ == EV_ABS && ev.code == ABS_MT_TRACKING_ID {
+                let device_id = self.slots[self.clone_slot]
+                    .as_ref()
+                    .filter(|tracked| tracked.id == ev.value)
+                    .and_then(|tracked| tracked.contact.device_id);
+                self.clone[self.clone_slot] = (ev.value >= 0).then(|| Contact {
+                    device_id,
+                    axes: Vec::new(),
+                });
+            } else if ev.type_ == EV_ABS && is_mt_axis(ev.code) {
+                if let Some(contact) = &mut self.clone[self.clone_slot] {
+                    match contact.axes.iter_mut().find(|a| a.0 == ev.code) {
+                        Some(entry) => entry.1 = ev.value,
+                        None => contact.axes.push((ev.code, ev.value)),
+                    }
+                }
+            } else if ev.type_ == EV_SYN && ev.code == SYN_REPORT {
+                let touching = self.clone.iter().any(Option::is_some);
+                let frame = &evs[start..=i];
+                start = i + 1;
+                if let Some(original) = self.take_original(frame) {
+                    out.extend(original);
+                    rest.clear();
+                    self.clone_touching = touching;
+                    continue;
+                }
+                for contact in self.clone.iter().flatten() {
+                    if let Some(id) = contact.device_id {
+                        out.push(Ev::abs(ABS_MT_TRACKING_ID, id));
+                    }
+                    out.extend(
+                        contact
+                            .axes
+                            .iter()
+                            .map(|&(code, value)| Ev::abs(code, value)),
+                    );
+                    out.push(Ev::new(EV_SYN, SYN_MT_REPORT, 0));
+                }
+                if !touching && self.clone_touching {
+                    // the last contact left
+                    out.push(Ev::new(EV_SYN, SYN_MT_REPORT, 0));
+                }
+                self.clone_touching = touching;
+                out.append(&mut rest);
+                out.push(ev);
+            } else {
+                rest.push(ev);
+            }
+        }
+        out
+    }
+
+    /// The frame the device sent, if `frame` is its conversion and it
+    /// lists just the contacts the clone now has. Older frames than that
+    /// were never relayed, and are forgotten.
+    fn take_original(&mut self, frame: &[Ev]) -> Option<Vec<Ev>> {
+        let at = self
+            .relayable
+            .iter()
+            .position(|(slotted, original)| slotted == frame && self.lists_the_clone(original))?;
+        self.relayable.drain(..at);
+        self.relayable.pop_front().map(|(_, original)| original)
+    }
+
+    /// Whether protocol A frame `original` lists exactly the clone's
+    /// contacts, axis for axis.
+    fn lists_the_clone(&self, original: &[Ev]) -> bool {
+        let mut listed = Vec::new();
+        let mut current = Vec::new();
+        for ev in original {
+            if ev.type_ == EV_SYN && ev.code == SYN_MT_REPORT {
+                if !current.is_empty() {
+                    current.sort_unstable();
+                    listed.push(std::mem::take(&mut current));
+                }
+            } else if ev.type_ == EV_ABS && is_mt_axis(ev.code) {
+                current.push((ev.code, ev.value));
+            }
+        }
+        if !current.is_empty() {
+            current.sort_unstable();
+            listed.push(current);
+        }
+        let mut clone: Vec<_> = self
+            .clone
+            .iter()
+            .flatten()
+            .map(|contact| {
+                let mut axes = contact.axes.clone();
+                axes.sort_unstable();
+                axes
+            })
+            .collect();
+        listed.sort_unstable();
+        clone.sort_unstable();
+        listed == clone
+    }
+}
+
+impl Default for ProtocolA {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,310 @@
+//! Protocol A contact tracking, both ways.
+
+use std::time::{Duration, Instant};
+
+use super::*;
+use crate::runtime::gesture::{GestureMachine, Output, Timing, ABS_MT_PRESSURE, EV_KEY, PX_PER_MM};
+
+const BTN_TOUCH: u16 = 0x14a;
+
+/// One protocol A frame: a contact per `(x, y)`, each closed by a
+/// SYN_MT_REPORT, then the SYN_REPORT.
+fn frame_a(contacts: &[(i32, i32)]) -> Vec<Ev> {
+    let mut f = Vec::new();
+    for &(x, y) in contacts {
+        f.push(Ev::abs(ABS_MT_POSITION_X, x));
+        f.push(Ev::abs(ABS_MT_POSITION_Y, y));
+        f.push(Ev::new(EV_SYN, SYN_MT_REPORT, 0));
+    }
+    if contacts.is_empty() {
+        f.push(Ev::new(EV_SYN, SYN_MT_REPORT, 0));
+    }
+    f.push(Ev::syn());
+    f
+}
+
+/// `(slot, tracking_id)` of every touchdown and liftoff in `frame`.
+fn ids(frame: &[Ev]) -> Vec<(i32, i32)> {
+    let mut slot = 0;
+    let mut out = Vec::new();
+    for ev in frame {
+        match ev.code {
+            ABS_MT_SLOT => slot = ev.value,
+            ABS_MT_TRACKING_ID => out.push((slot, ev.value)),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[test]
+fn anonymous_contacts_keep_their_slots_by_position() {
+    let mut a = ProtocolA::new();
+    let f = a.to_slots(&frame_a(&[(100, 100), (500, 100)]));
+    assert_eq!(ids(&f), [(0, 0), (1, 1)]);
+    assert_eq!(f.last(), Some(&Ev::syn()));
+
+    // reported in the other order, and moved a little: same fingers
+    let f = a.to_slots(&frame_a(&[(510, 110), (105, 100)]));
+    assert!(ids(&f).is_empty(), "{f:?}");
+    assert_eq!(
+        f,
+        [
+            Ev::abs(ABS_MT_SLOT, 1),
+            Ev::abs(ABS_MT_POSITION_X, 510),
+            Ev::abs(ABS_MT_POSITION_Y, 110),
+            Ev::abs(ABS_MT_SLOT, 0),
+            Ev::abs(ABS_MT_POSITION_X, 105),
+            Ev::syn(),
+        ]
+    );
+
+    // the left finger lifts, then a new one lands: it gets the free
+    // slot and a fresh tracking ID
+    let f = a.to_slots(&frame_a(&[(515, 110)]));
+    assert_eq!(ids(&f), [(0, -1)]);
+    let f = a.to_slots(&frame_a(&[(900, 300), (515, 110)]));
+    assert_eq!(ids(&f), [(0, 2)]);
+
+    let f = a.to_slots(&frame_a(&[]));
+    assert_eq!(ids(&f), [(0, -1), (1, -1)]);
+    assert_eq!(a.snapshot()[0], SlotState::UP);
+}
+
+#[test]
+fn wrapped_tracking_ids_skip_contacts_still_down() {
+    let mut a = ProtocolA::new();
+    // a finger that stays down while 0xffff others come and go
+    a.to_slots(&frame_a(&[(100, 100)]));
+    a.next_id = 0xffff;
+    let f = a.to_slots(&frame_a(&[(100, 100), (900, 900)]));
+    assert_eq!(ids(&f), [(1, 0xffff)]);
+    a.to_slots(&frame_a(&[(100, 100)]));
+    let f = a.to_slots(&frame_a(&[(100, 100), (900, 900)]));
+    assert_eq!(ids(&f), [(1, 1)], "0 is still down in slot 0");
+}
+
+#[test]
+fn device_tracking_ids_win_over_position() {
+    let mut a = ProtocolA::new();
+    let contact = |id, x| {
+        [
+            Ev::abs(ABS_MT_TRACKING_ID, id),
+            Ev::abs(ABS_MT_POSITION_X, x),
+            Ev::abs(ABS_MT_POSITION_Y, 0),
+            Ev::new(EV_SYN, SYN_MT_REPORT, 0),
+        ]
+    };
+    a.to_slots(&cat(&[&contact(7, 100), &contact(8, 500), &[Ev::syn()]]));
+    // they crossed over: positions say swap, the IDs say otherwise
+    let f = a.to_slots(&cat(&[&contact(7, 480), &contact(8, 120), &[Ev::syn()]]));
+    assert!(ids(&f).is_empty(), "{f:?}");
+    assert_eq!(a.snapshot()[0].x, 480);
+    assert_eq!(a.snapshot()[1].x, 120);
+}
+
+fn cat(parts: &[&[Ev]]) -> Vec<Ev> {
+    parts.iter().flat_map(|p| p.iter().copied()).collect()
+}
+
+#[test]
+fn other_events_ride_along_and_extra_contacts_are_dropped() {
+    let mut a = ProtocolA::new();
+    let mut f = frame_a(&[(1, 1)]);
+    f.insert(f.len() - 1, Ev::new(EV_KEY, BTN_TOUCH, 1));
+    let out = a.to_slots(&f);
+    assert_eq!(out[out.len() - 2], Ev::new(EV_KEY, BTN_TOUCH, 1));
+    assert!(!out
+        .iter()
+        .any(|e| e.code == SYN_MT_REPORT && e.type_ == EV_SYN));
+
+    let many: Vec<_> = (0..20).map(|i| (i * 1000, 0)).collect();
+    let out = a.to_slots(&frame_a(&many));
+    assert_eq!(ids(&out).len(), MAX_SLOTS - 1);
+    assert!(a.snapshot().iter().all(|s| s.tracking_id >= 0));
+}
+
+/// MSC_TIMESTAMP and the keys keep their order both ways, after the
+/// contacts.
+#[test]
+fn msc_events_keep_their_order() {
+    const EV_MSC: u16 = 0x04;
+    const MSC_TIMESTAMP: u16 = 0x05;
+    let stamp = Ev::new(EV_MSC, MSC_TIMESTAMP, 7000);
+    let touch = Ev::new(EV_KEY, BTN_TOUCH, 1);
+    let mut a = ProtocolA::new();
+    let mut f = frame_a(&[(1, 1)]);
+    f.insert(0, stamp);
+    f.insert(f.len() - 1, touch);
+    let out = a.to_slots(&f);
+    assert_eq!(out[out.len() - 3..], [stamp, touch, Ev::syn()]);
+
+    // relayed as it came, it goes to the clone as the device sent it
+    assert_eq!(a.to_protocol_a(&out), f);
+}
+
+#[test]
+fn the_clone_gets_every_contact_in_every_frame() {
+    let mut a = ProtocolA::new();
+    let touchdown = cat(&[
+        &[
+            Ev::abs(ABS_MT_SLOT, 0),
+            Ev::abs(ABS_MT_TRACKING_ID, 4),
+            Ev::abs(ABS_MT_POSITION_X, 100),
+            Ev::abs(ABS_MT_PRESSURE, 30),
+            Ev::abs(ABS_MT_SLOT, 1),
+            Ev::abs(ABS_MT_TRACKING_ID, 5),
+            Ev::abs(ABS_MT_POSITION_X, 500),
+            Ev::new(EV_KEY, BTN_TOUCH, 1),
+        ],
+        &[Ev::syn()],
+    ]);
+    let out = a.to_protocol_a(&touchdown);
+    let mt_report = Ev::new(EV_SYN, SYN_MT_REPORT, 0);
+    assert_eq!(
+        out,
+        [
+            Ev::abs(ABS_MT_POSITION_X, 100),
+            Ev::abs(ABS_MT_PRESSURE, 30),
+            mt_report,
+            Ev::abs(ABS_MT_POSITION_X, 500),
+            mt_report,
+            Ev::new(EV_KEY, BTN_TOUCH, 1),
+            Ev::syn(),
+        ]
+    );
+
+    // only slot 0 moves, but slot 1 is listed again too
+    let out = a.to_protocol_a(&[
+        Ev::abs(ABS_MT_SLOT, 0),
+        Ev::abs(ABS_MT_POSITION_X, 110),
+        Ev::syn(),
+    ]);
+    assert_eq!(out.iter().filter(|&&e| e == mt_report).count(), 2);
+    assert!(out.contains(&Ev::abs(ABS_MT_POSITION_X, 110)));
+    assert!(out.contains(&Ev::abs(ABS_MT_PRESSURE, 30)));
+
+    // everything lifts: one empty contact list, then nothing at all
+    let lift = [
+        Ev::abs(ABS_MT_SLOT, 0),
+        Ev::abs(ABS_MT_TRACKING_ID, -1),
+        Ev::abs(ABS_MT_SLOT, 1),
+        Ev::abs(ABS_MT_TRACKING_ID, -1),
+        Ev::new(EV_KEY, BTN_TOUCH, 0),
+        Ev::syn(),
+    ];
+    assert_eq!(
+        a.to_protocol_a(&lift),
+        [mt_report, Ev::new(EV_KEY, BTN_TOUCH, 0), Ev::syn()]
+    );
+    assert_eq!(a.to_protocol_a(&[Ev::syn()]), [Ev::syn()]);
+}
+
+#[test]
+fn relayed_frames_go_to_the_clone_as_the_device_sent_them() {
+    let mut a = ProtocolA::new();
+    let first = frame_a(&[(100, 100), (500, 100)]);
+    let held = frame_a(&[(110, 100), (500, 100)]);
+    let moved = frame_a(&[(505, 110), (120, 100)]);
+    let first_b = a.to_slots(&first);
+    let _ = a.to_slots(&held);
+    let moved_b = a.to_slots(&moved);
+
+    assert_eq!(a.to_protocol_a(&first_b), first);
+    // `held` was swallowed; `moved`, relayed, still comes out verbatim,
+    // contact order and all
+    assert_eq!(a.to_protocol_a(&moved_b), moved);
+}
+
+#[test]
+fn a_device_without_tracking_ids_gets_none_on_the_clone() {
+    let mut a = ProtocolA::new();
+    let f = a.to_slots(&frame_a(&[(100, 100), (500, 100)]));
+    a.to_protocol_a(&f);
+    // the machine lifts one contact of its own accord
+    let out = a.to_protocol_a(&[
+        Ev::abs(ABS_MT_SLOT, 1),
+        Ev::abs(ABS_MT_TRACKING_ID, -1),
+        Ev::syn(),
+    ]);
+    assert_eq!(
+        out,
+        [
+            Ev::abs(ABS_MT_POSITION_X, 100),
+            Ev::abs(ABS_MT_POSITION_Y, 100),
+            Ev::new(EV_SYN, SYN_MT_REPORT, 0),
+            Ev::syn(),
+        ]
+    );
+    // and hands it back, under a tracking ID of ours
+    let out = a.to_protocol_a(&[
+        Ev::abs(ABS_MT_SLOT, 1),
+        Ev::abs(ABS_MT_TRACKING_ID, 1),
+        Ev::abs(ABS_MT_POSITION_X, 500),
+        Ev::abs(ABS_MT_POSITION_Y, 100),
+        Ev::syn(),
+    ]);
+    assert!(!out.iter().any(|e| e.code == ABS_MT_TRACKING_ID), "{out:?}");
+}
+
+#[test]
+fn a_synthesized_frame_carries_the_devices_own_tracking_ids() {
+    let mut a = ProtocolA::new();
+    let contact = |id, x| {
+        [
+            Ev::abs(ABS_MT_TRACKING_ID, id),
+            Ev::abs(ABS_MT_POSITION_X, x),
+            Ev::new(EV_SYN, SYN_MT_REPORT, 0),
+        ]
+    };
+    let f = a.to_slots(&cat(&[&contact(7, 100), &contact(8, 500), &[Ev::syn()]]));
+    assert_eq!(ids(&f), [(0, 0), (1, 1)]);
+    // replayed in a flush with a correction folded in: written out anew
+    let mut flush = f.clone();
+    flush.insert(flush.len() - 1, Ev::abs(ABS_MT_SLOT, 0));
+    flush.insert(flush.len() - 1, Ev::abs(ABS_MT_POSITION_X, 90));
+    let out = a.to_protocol_a(&flush);
+    assert_eq!(
+        out,
+        cat(&[
+            &[
+                Ev::abs(ABS_MT_TRACKING_ID, 7),
+                Ev::abs(ABS_MT_POSITION_X, 90)
+            ],
+            &[Ev::new(EV_SYN, SYN_MT_REPORT, 0)],
+            &contact(8, 500),
+            &[Ev::syn()],
+        ])
+    );
+}
+
+/// End to end: a 3-finger protocol A touch, fed through the tracker,
+/// is a drag to the same machine that serves protocol B pads.
+#[test]
+fn a_protocol_a_three_finger_touch_drags() {
+    let timing = Timing {
+        probe_delay: Duration::from_millis(15),
+        entry_debounce: Duration::from_millis(50),
+        drag_end_delay: Duration::ZERO,
+        press_grace: Duration::from_millis(75),
+        px_per_mm: PX_PER_MM,
+    };
+    let mut m = GestureMachine::new(timing, 10.0, 10.0, MAX_SLOTS);
+    let mut a = ProtocolA::new();
+    let mut now = Instant::now();
+    let mut outs = Vec::new();
+    let mut feed = |contacts: &[(i32, i32)], ms: u64, outs: &mut Vec<Output>| {
+        now += Duration::from_millis(ms);
+        outs.extend(m.on_frame(&a.to_slots(&frame_a(contacts)), now));
+        outs.extend(m.on_tick(now));
+    };
+    feed(&[(500, 500)], 0, &mut outs);
+    feed(&[(500, 500), (600, 500)], 5, &mut outs);
+    feed(&[(500, 500), (600, 500), (700, 500)], 5, &mut outs);
+    feed(&[(500, 500), (600, 500), (700, 500)], 45, &mut outs);
+    feed(&[(530, 500), (630, 500), (730, 500)], 10, &mut outs);
+    assert!(outs.contains(&Output::MouseDown), "{outs:?}");
+    assert!(!outs.iter().any(|o| matches!(o, Output::EmitSynth(_))));
+    feed(&[], 10, &mut outs);
+    assert_eq!(outs.last(), Some(&Output::MouseUp));
+}
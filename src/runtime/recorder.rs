@@ -0,0 +1,241 @@
+//! The flight recorder: the last few seconds of raw input frames and the
+//! outputs they produced, kept in memory at all times.
+//!
+//! The bugs that matter here (a stuck tool bit, a phantom click) are
+//! over in a fraction of a second, long before anyone turns the log
+//! level up. So the runtime always keeps a short, bounded history, and
+//! writes it to a timestamped file in the state directory the moment
+//! something suspicious happens: a drag aborted after its button was
+//! already pressed, a kernel resync, a panic -- or whenever the user
+//! asks with `SIGUSR1`. That file is what a bug report should attach.
+//!
+//! Automatic dumps are limited to one per reason per [`DUMP_INTERVAL`],
+//! and only the newest [`KEEP_DUMPS`] files are kept, so a flaky pad
+//! that drops events all day can't fill the disk.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use super::gesture::{Ev, Output, Snapshot, MT_EXTRA_AXES};
+use crate::init::config::get_state_dir;
+
+/// How much history is kept.
+const WINDOW: Duration = Duration::from_secs(5);
+/// Hard cap on entries, whatever their age: a pad reporting at a few
+/// hundred Hz fills the window with well under this.
+const MAX_ENTRIES: usize = 8192;
+/// Least time between two automatic dumps for the same reason: the
+/// first history of a recurring incident is as good as the fiftieth.
+pub const DUMP_INTERVAL: Duration = Duration::from_secs(60);
+/// Dumps kept in the state directory; older ones are deleted.
+pub const KEEP_DUMPS: usize = 20;
+
+enum Record {
+    /// A complete frame read from the real device.
+    Frame(Vec<Ev>),
+    /// What the machine asked for in response to a frame or a tick.
+    Outputs(Vec<Output>),
+    /// The authoritative device state re-read after a SYN_DROPPED.
+    Resync(Snapshot),
+}
+
+pub struct FlightRecorder {
+    entries: VecDeque<(Instant, Record)>,
+    /// When each reason was last dumped automatically.
+    last_dumps: Vec<(String, Instant)>,
+}
+
+/// The recorder is shared between the I/O shell, which fills it, and
+/// the panic hook, which must be able to dump it from anywhere.
+pub type SharedRecorder = Arc<Mutex<FlightRecorder>>;
+
+impl FlightRecorder {
+    pub fn shared() -> SharedRecorder {
+        Arc::new(Mutex::new(FlightRecorder {
+            entries: VecDeque::with_capacity(MAX_ENTRIES),
+            last_dumps: Vec::new(),
+        }))
+    }
+
+    pub fn frame(&mut self, now: Instant, frame: &[Ev]) {
+        self.push(now, Record::Frame(frame.to_vec()));
+    }
+
+    pub fn outputs(&mut self, now: Instant, outputs: &[Output]) {
+        if !outputs.is_empty() {
+            self.push(now, Record::Outputs(outputs.to_vec()));
+        }
+    }
+
+    pub fn resync(&mut self, now: Instant, snapshot: &Snapshot) {
+        self.push(now, Record::Resync(snapshot.clone()));
+    }
+
+    fn push(&mut self, now: Instant, record: Record) {
+        while let Some((at, _)) = self.entries.front() {
+            if self.entries.len() < MAX_ENTRIES && now.saturating_duration_since(*at) <= WINDOW {
+                break;
+            }
+            self.entries.pop_front();
+        }
+        self.entries.push_back((now, record));
+    }
+
+    /// The history as text, one entry per line, stamped in seconds
+    /// before `now`.
+    pub fn render(&self, reason: &str, now: Instant) -> String {
+        let mut s = String::new();
+        let _ = writeln!(
+            s,
+            "# linux-3-finger-drag {} flight recorder",
+            env!("CARGO_PKG_VERSION")
+        );
+        let _ = writeln!(s, "# reason: {reason}");
+        let _ = writeln!(s, "# dumped at: {}", chrono::Local::now().to_rfc3339());
+        let _ = writeln!(s, "# times are seconds before the dump");
+        for (at, record) in &self.entries {
+            let ago = now.saturating_duration_since(*at).as_secs_f64();
+            let _ = match record {
+                Record::Frame(evs) => writeln!(s, "-{ago:.6} frame  {}", events(evs)),
+                Record::Outputs(outs) => {
+                    let outs: Vec<String> = outs.iter().map(output).collect();
+                    writeln!(s, "-{ago:.6} output {}", outs.join(" | "))
+                }
+                Record::Resync(snapshot) => {
+                    let slots: Vec<String> = snapshot
+                        .slots
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, state)| state.tracking_id >= 0)
+                        .map(|(slot, state)| {
+                            let mut entry = format!(
+                                "{slot}:id={},x={},y={}",
+                                state.tracking_id, state.x, state.y
+                            );
+                            for (code, value) in MT_EXTRA_AXES.iter().zip(state.extra) {
+                                if let Some(value) = value {
+                                    let _ = write!(entry, ",{code:#x}={value}");
+                                }
+                            }
+                            entry
+                        })
+                        .collect();
+                    let keys: Vec<String> = snapshot
+                        .keys
+                        .iter()
+                        .filter(|&&(_, value)| value != 0)
+                        .map(|(code, _)| format!("{code:#x}"))
+                        .collect();
+                    writeln!(
+                        s,
+                        "-{ago:.6} resync [{}] keys [{}]",
+                        slots.join(" "),
+                        keys.join(" ")
+                    )
+                }
+            };
+        }
+        s
+    }
+
+    /// Whether an automatic dump for `reason` may be written at `now`
+    /// (see [`DUMP_INTERVAL`]); if so, it counts as written.
+    pub fn dump_due(&mut self, reason: &str, now: Instant) -> bool {
+        match self.last_dumps.iter_mut().find(|(r, _)| r == reason) {
+            Some((_, last)) if now.saturating_duration_since(*last) < DUMP_INTERVAL => false,
+            Some((_, last)) => {
+                *last = now;
+                true
+            }
+            None => {
+                self.last_dumps.push((reason.to_string(), now));
+                true
+            }
+        }
+    }
+
+    /// Writes the history to a new timestamped file in the state
+    /// directory and returns its path, pruning the oldest dumps past
+    /// [`KEEP_DUMPS`].
+    pub fn dump(&self, reason: &str) -> io::Result<PathBuf> {
+        let dir = get_state_dir()?;
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!(
+            "flight-{}-{reason}.txt",
+            chrono::Local::now().format("%Y%m%dT%H%M%S%.3f")
+        ));
+        std::fs::write(&path, self.render(reason, Instant::now()))?;
+        prune(&dir, KEEP_DUMPS);
+        Ok(path)
+    }
+}
+
+/// Deletes all but the newest `keep` dumps in `dir`. Their names start
+/// with the timestamp, so name order is age order. Best effort: a dump
+/// that can't be deleted is simply left.
+fn prune(dir: &Path, keep: usize) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut dumps: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("flight-") && name.ends_with(".txt"))
+        })
+        .collect();
+    dumps.sort();
+    let excess = dumps.len().saturating_sub(keep);
+    for path in &dumps[..excess] {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+fn events(evs: &[Ev]) -> String {
+    let evs: Vec<String> = evs
+        .iter()
+        .map(|ev| {
+            let kind = match ev.type_ {
+                0x00 => "SYN".to_string(),
+                0x01 => "KEY".to_string(),
+                0x03 => "ABS".to_string(),
+                0x04 => "MSC".to_string(),
+                other => format!("{other:#04x}"),
+            };
+            format!("{kind}:{:#04x}={}", ev.code, ev.value)
+        })
+        .collect();
+    evs.join(" ")
+}
+
+fn output(out: &Output) -> String {
+    match out {
+        Output::EmitSynth(evs) => format!("synth[{}]", events(evs)),
+        other => format!("{other:?}"),
+    }
+}
+
+/// Dumps `recorder` on panic, before the default hook reports the panic.
+/// If the panic struck while the recorder itself was locked, there is
+/// nothing consistent to dump, so none is attempted.
+pub fn install_panic_hook(recorder: SharedRecorder) {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        if let Ok(rec) = recorder.try_lock() {
+            match rec.dump("panic") {
+                Ok(path) => eprintln!("Flight recorder dumped to {}", path.display()),
+                Err(e) => eprintln!("Could not dump the flight recorder: {e}"),
+            }
+        }
+        default_hook(info);
+    }));
+}
+
+#[cfg(test)]
+mod tests;
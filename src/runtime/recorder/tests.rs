@@ -0,0 +1,42 @@
+use super::*;
+
+#[test]
+fn automatic_dumps_are_rate_limited_per_reason() {
+    let t0 = Instant::now();
+    let shared = FlightRecorder::shared();
+    let mut rec = shared.lock().unwrap();
+    assert!(rec.dump_due("resync", t0));
+    assert!(!rec.dump_due("resync", t0 + Duration::from_secs(59)));
+    // another reason has its own limit
+    assert!(rec.dump_due("stuck-button", t0 + Duration::from_secs(1)));
+    assert!(rec.dump_due("resync", t0 + DUMP_INTERVAL));
+    assert!(!rec.dump_due("resync", t0 + DUMP_INTERVAL + Duration::from_secs(1)));
+}
+
+#[test]
+fn only_the_newest_dumps_are_kept() {
+    let dir = std::env::temp_dir().join(format!("3fd-recorder-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    for minute in 10..15 {
+        let name = format!("flight-20260101T10{minute}00.000-resync.txt");
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+    std::fs::write(dir.join("06cb-cdcd.json"), "{}").unwrap();
+
+    prune(&dir, 2);
+    let mut left: Vec<String> = std::fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    left.sort();
+    assert_eq!(
+        left,
+        [
+            "06cb-cdcd.json",
+            "flight-20260101T101300.000-resync.txt",
+            "flight-20260101T101400.000-resync.txt",
+        ]
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,108 @@
+//! A last line of defence against a stuck virtual button.
+//!
+//! A held left button that nothing releases makes the whole desktop
+//! unusable: every click becomes a drag. The gesture machine is tested
+//! hard against that, but this watch doesn't take its word for it. It
+//! looks only at what the real touchpad reports (`BTN_TOUCH`) and what
+//! the virtual mouse actually did, and if the button has been down with
+//! no finger on the pad for longer than drag-lock could explain (plus
+//! [`MARGIN`]), the button is overdue for release -- whatever the
+//! machine believes.
+//!
+//! A drag locked by a click (`clickDuringDrag = "toggle-lock"`) holds
+//! the button for as long as the user likes. Whether one holds is the
+//! machine's to say -- it's the machine that decides what a click does
+//! -- so while it does, the watch allows [`LATCHED_LIMIT`] instead: no
+//! lock the machine believes in keeps the button down past that.
+
+use std::time::{Duration, Instant};
+
+use super::gesture::{Ev, SlotState, EV_KEY};
+
+/// Slack on top of `dragEndDelay` before a release is forced: the
+/// machine's own timers fire well within it.
+pub const MARGIN: Duration = Duration::from_secs(2);
+
+/// How long a drag locked by a click may hold the button with no finger
+/// down, machine or not.
+pub const LATCHED_LIMIT: Duration = Duration::from_secs(10 * 60);
+
+const BTN_TOUCH: u16 = 0x14a;
+
+pub struct StuckButtonWatch {
+    limit: Duration,
+    touching: bool,
+    /// Since when the button has been held with no finger down.
+    idle_since: Option<Instant>,
+    /// The machine has the held button locked by a click.
+    latched: bool,
+}
+
+impl StuckButtonWatch {
+    /// A watch allowing for drag-lock holding the button `drag_lock`.
+    pub fn new(drag_lock: Duration) -> Self {
+        StuckButtonWatch {
+            limit: drag_lock + MARGIN,
+            touching: false,
+            idle_since: None,
+            latched: false,
+        }
+    }
+
+    /// After a config reload.
+    pub fn set_drag_lock(&mut self, drag_lock: Duration) {
+        self.limit = drag_lock + MARGIN;
+    }
+
+    /// Follows a frame from the real device.
+    pub fn on_frame(&mut self, frame: &[Ev]) {
+        for ev in frame {
+            if ev.type_ == EV_KEY && ev.code == BTN_TOUCH {
+                self.touching = ev.value != 0;
+            }
+        }
+    }
+
+    /// Follows a resync snapshot.
+    pub fn on_resync(&mut self, slots: &[SlotState]) {
+        self.touching = slots.iter().any(|s| s.tracking_id >= 0);
+    }
+
+    /// Notes whether the virtual button is down, and whether the machine
+    /// has it locked by a click, as of `now`.
+    pub fn update(&mut self, button_down: bool, latched: bool, now: Instant) {
+        if latched != self.latched {
+            // the other limit applies, from now
+            self.latched = latched;
+            self.idle_since = None;
+        }
+        if button_down && !self.touching {
+            self.idle_since.get_or_insert(now);
+        } else {
+            self.idle_since = None;
+        }
+    }
+
+    /// When the button will be overdue for release, if it's held idle.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.idle_since.map(|since| since + self.limit())
+    }
+
+    /// How long the button has been held idle, if it is overdue at `now`.
+    pub fn overdue(&self, now: Instant) -> Option<Duration> {
+        self.idle_since
+            .map(|since| now.saturating_duration_since(since))
+            .filter(|&held| held >= self.limit())
+    }
+
+    fn limit(&self) -> Duration {
+        if self.latched {
+            self.limit.max(LATCHED_LIMIT)
+        } else {
+            self.limit
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,97 @@
+use super::*;
+
+const TOUCH_DOWN: [Ev; 2] = [Ev::new(EV_KEY, BTN_TOUCH, 1), Ev::syn()];
+const TOUCH_UP: [Ev; 2] = [Ev::new(EV_KEY, BTN_TOUCH, 0), Ev::syn()];
+
+fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+#[test]
+fn held_with_fingers_down_is_never_overdue() {
+    let t0 = Instant::now();
+    let mut watch = StuckButtonWatch::new(Duration::ZERO);
+    watch.on_frame(&TOUCH_DOWN);
+    watch.update(true, false, t0);
+    assert_eq!(watch.deadline(), None);
+    assert_eq!(watch.overdue(t0 + Duration::from_secs(600)), None);
+}
+
+#[test]
+fn held_with_the_pad_empty_is_overdue_after_drag_lock_plus_margin() {
+    let t0 = Instant::now();
+    let mut watch = StuckButtonWatch::new(ms(800));
+    watch.on_frame(&TOUCH_DOWN);
+    watch.update(true, false, t0);
+    watch.on_frame(&TOUCH_UP);
+    watch.update(true, false, t0 + ms(100));
+    // later updates don't push the deadline out
+    watch.update(true, false, t0 + ms(500));
+    let due = t0 + ms(100) + ms(800) + MARGIN;
+    assert_eq!(watch.deadline(), Some(due));
+    assert_eq!(watch.overdue(due - ms(1)), None);
+    assert_eq!(watch.overdue(due), Some(ms(800) + MARGIN));
+}
+
+#[test]
+fn a_release_or_a_new_touch_clears_it() {
+    let t0 = Instant::now();
+    let mut watch = StuckButtonWatch::new(Duration::ZERO);
+    watch.update(true, false, t0);
+    assert!(watch.deadline().is_some());
+    watch.update(false, false, t0 + ms(10));
+    assert_eq!(watch.deadline(), None);
+
+    watch.update(true, false, t0 + ms(20));
+    watch.on_frame(&TOUCH_DOWN);
+    watch.update(true, false, t0 + ms(30));
+    assert_eq!(watch.deadline(), None);
+}
+
+#[test]
+fn follows_resync_and_reloaded_drag_lock() {
+    let t0 = Instant::now();
+    let mut watch = StuckButtonWatch::new(Duration::ZERO);
+    watch.on_resync(&[SlotState::UP, SlotState::new(7, 100, 100)]);
+    watch.update(true, false, t0);
+    assert_eq!(watch.deadline(), None);
+
+    watch.on_resync(&[SlotState::UP, SlotState::UP]);
+    watch.set_drag_lock(Duration::from_secs(5));
+    watch.update(true, false, t0);
+    assert_eq!(watch.deadline(), Some(t0 + Duration::from_secs(5) + MARGIN));
+}
+
+#[test]
+fn a_drag_the_machine_has_locked_gets_the_latched_limit() {
+    let t0 = Instant::now();
+    let mut watch = StuckButtonWatch::new(Duration::ZERO);
+    watch.on_frame(&TOUCH_DOWN);
+    watch.update(true, false, t0);
+    watch.on_frame(&TOUCH_UP);
+    watch.update(true, true, t0 + ms(10));
+    let due = t0 + ms(10) + LATCHED_LIMIT;
+    assert_eq!(watch.deadline(), Some(due));
+    assert_eq!(watch.overdue(due - ms(1)), None);
+
+    // unlocked, the button is on the usual clock again
+    watch.update(true, false, t0 + ms(20));
+    assert_eq!(watch.deadline(), Some(t0 + ms(20) + MARGIN));
+}
+
+/// A machine wrongly convinced its drag is locked holds the button only
+/// so long.
+#[test]
+fn a_latched_button_is_still_released_in_the_end() {
+    let t0 = Instant::now();
+    let mut watch = StuckButtonWatch::new(ms(800));
+    watch.update(true, true, t0);
+    // the machine still says latched, every time it's asked
+    watch.update(true, true, t0 + Duration::from_secs(300));
+    assert_eq!(watch.overdue(t0 + LATCHED_LIMIT), Some(LATCHED_LIMIT));
+
+    // and a long drag-lock isn't cut short by it
+    watch.set_drag_lock(LATCHED_LIMIT * 2);
+    assert_eq!(watch.overdue(t0 + LATCHED_LIMIT), None);
+    assert!(watch.overdue(t0 + LATCHED_LIMIT * 2 + MARGIN).is_some());
+}
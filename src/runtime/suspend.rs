@@ -0,0 +1,61 @@
+//! Noticing that the system slept.
+//!
+//! A suspend can land mid-drag, and what the gesture machine holds is
+//! stale on resume: slots that lifted long ago, a drag-lock or press
+//! deadline on a monotonic clock that didn't count the sleep, maybe the
+//! button held. The shell resets it from a fresh snapshot instead (see
+//! [`GestureMachine::reset`](super::gesture::GestureMachine::reset)).
+//!
+//! Two ways to find out. logind announces sleep and resume
+//! (`logind.rs`), when there's a system bus to hear it on. And with or
+//! without it, on every wakeup the event loop compares two clocks:
+//! `CLOCK_BOOTTIME` counts time suspended, `CLOCK_MONOTONIC` doesn't, so
+//! any growth in the gap between them is time the system spent asleep.
+
+use std::time::Duration;
+
+/// Growth in BOOTTIME - MONOTONIC below this is clock noise, not sleep.
+pub const MIN_SLEEP: Duration = Duration::from_millis(500);
+
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut ts: libc::timespec = unsafe { std::mem::zeroed() };
+    unsafe { libc::clock_gettime(id, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+fn asleep_so_far() -> Duration {
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+pub struct SuspendClock {
+    /// BOOTTIME - MONOTONIC as of the last check: total time asleep.
+    asleep: Duration,
+}
+
+impl Default for SuspendClock {
+    fn default() -> Self {
+        SuspendClock::starting_at(asleep_so_far())
+    }
+}
+
+impl SuspendClock {
+    /// A clock that has seen `asleep` of sleep since boot.
+    pub fn starting_at(asleep: Duration) -> Self {
+        SuspendClock { asleep }
+    }
+
+    /// How long the system slept since the last check, if it did.
+    pub fn check(&mut self) -> Option<Duration> {
+        self.advance(asleep_so_far())
+    }
+
+    /// [`check`](Self::check), given BOOTTIME - MONOTONIC now.
+    pub fn advance(&mut self, asleep: Duration) -> Option<Duration> {
+        let slept = asleep.saturating_sub(self.asleep);
+        self.asleep = self.asleep.max(asleep);
+        (slept >= MIN_SLEEP).then_some(slept)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,32 @@
+use super::*;
+
+fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+#[test]
+fn reports_each_sleep_once() {
+    let mut clock = SuspendClock::starting_at(ms(10_000));
+    assert_eq!(clock.advance(ms(10_000)), None);
+    assert_eq!(clock.advance(ms(70_000)), Some(ms(60_000)));
+    assert_eq!(clock.advance(ms(70_000)), None);
+}
+
+#[test]
+fn ignores_clock_noise() {
+    let mut clock = SuspendClock::starting_at(ms(0));
+    assert_eq!(clock.advance(ms(3)), None);
+    assert_eq!(clock.advance(ms(3) + MIN_SLEEP - ms(1)), None);
+    // noise doesn't accumulate into a phantom sleep either
+    assert_eq!(clock.advance(ms(10) + MIN_SLEEP - ms(1)), None);
+    assert_eq!(
+        clock.advance(ms(10) + MIN_SLEEP * 3),
+        Some(MIN_SLEEP * 2 + ms(1))
+    );
+}
+
+#[test]
+fn a_live_system_has_not_slept_between_checks() {
+    let mut clock = SuspendClock::default();
+    assert_eq!(clock.check(), None);
+}
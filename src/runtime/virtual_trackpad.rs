@@ -1,5 +1,5 @@
 //! The virtual mouse that carries out the drag: a minimal uinput device
-//! with one button and relative motion. All *timing* concerns (debounce
+//! with two buttons and relative motion. All *timing* concerns (debounce
 //! windows, drag-lock) live in the gesture machine -- this device just
 //! writes what it's told, synchronously, in order.
 //!
@@ -21,8 +21,22 @@ use libc::O_NONBLOCK;
 use tracing::{debug, error};
 
 pub struct VirtualTrackpad {
-    handle: UInputHandle<File>,
+    /// None when only observing (`--observe`): the button state is still
+    /// tracked, but nothing is written anywhere.
+    handle: Option<UInputHandle<File>>,
     pub mouse_is_down: bool,
+    /// The right button, for a drag switched to it by a click.
+    pub right_is_down: bool,
+}
+
+/// A stand-in for the virtual mouse that creates no device, for
+/// `--observe`.
+pub fn observer() -> VirtualTrackpad {
+    VirtualTrackpad {
+        handle: None,
+        mouse_is_down: false,
+        right_is_down: false,
+    }
 }
 
 pub fn start_handler() -> Result<VirtualTrackpad, std::io::Error> {
@@ -46,6 +60,7 @@ pub fn start_handler() -> Result<VirtualTrackpad, std::io::Error> {
 
     uhandle.set_evbit(EventKind::Key)?;
     uhandle.set_keybit(Key::ButtonLeft)?;
+    uhandle.set_keybit(Key::ButtonRight)?;
 
     uhandle.set_evbit(EventKind::Relative)?;
     uhandle.set_relbit(RelativeAxis::X)?;
@@ -65,8 +80,9 @@ pub fn start_handler() -> Result<VirtualTrackpad, std::io::Error> {
     thread::sleep(time::Duration::from_millis(500));
 
     Ok(VirtualTrackpad {
-        handle: uhandle,
+        handle: Some(uhandle),
         mouse_is_down: false,
+        right_is_down: false,
     })
 }
 
@@ -82,37 +98,68 @@ impl VirtualTrackpad {
         .into_raw()
     }
 
-    pub fn mouse_down(&mut self) -> Result<(), std::io::Error> {
+    fn write(&self, events: &[input_linux::sys::input_event]) -> Result<(), std::io::Error> {
+        match &self.handle {
+            Some(handle) => handle.write(events).map(drop),
+            None => Ok(()),
+        }
+    }
+
+    fn button(&self, key: Key, pressed: bool) -> Result<(), std::io::Error> {
         let events = [
             InputEvent::from(KeyEvent::new(
                 VirtualTrackpad::ZERO,
-                Key::ButtonLeft,
-                KeyState::pressed(true),
+                key,
+                KeyState::pressed(pressed),
             ))
             .into_raw(),
             Self::syn(),
         ];
-        self.handle.write(&events)?;
+        self.write(&events)
+    }
+
+    pub fn mouse_down(&mut self) -> Result<(), std::io::Error> {
+        self.button(Key::ButtonLeft, true)?;
         self.mouse_is_down = true;
         Ok(())
     }
 
     pub fn mouse_up(&mut self) -> Result<(), std::io::Error> {
-        let events = [
-            InputEvent::from(KeyEvent::new(
-                VirtualTrackpad::ZERO,
-                Key::ButtonLeft,
-                KeyState::pressed(false),
-            ))
-            .into_raw(),
-            Self::syn(),
-        ];
-        self.handle.write(&events)?;
+        self.button(Key::ButtonLeft, false)?;
         self.mouse_is_down = false;
         debug!("virtual mouse button released");
         Ok(())
     }
 
+    pub fn right_down(&mut self) -> Result<(), std::io::Error> {
+        self.button(Key::ButtonRight, true)?;
+        self.right_is_down = true;
+        Ok(())
+    }
+
+    pub fn right_up(&mut self) -> Result<(), std::io::Error> {
+        self.button(Key::ButtonRight, false)?;
+        self.right_is_down = false;
+        debug!("virtual right button released");
+        Ok(())
+    }
+
+    /// Whether either button is held.
+    pub fn any_down(&self) -> bool {
+        self.mouse_is_down || self.right_is_down
+    }
+
+    /// Releases whichever buttons are held.
+    pub fn release_all(&mut self) -> Result<(), std::io::Error> {
+        if self.mouse_is_down {
+            self.mouse_up()?;
+        }
+        if self.right_is_down {
+            self.right_up()?;
+        }
+        Ok(())
+    }
+
     /// Whole-pixel relative motion. Sub-pixel remainders are carried by
     /// the gesture machine, so nothing is lost to truncation here.
     pub fn mouse_move_relative(&mut self, dx: i32, dy: i32) -> Result<(), std::io::Error> {
@@ -131,11 +178,14 @@ impl VirtualTrackpad {
             .into_raw(),
             Self::syn(),
         ];
-        self.handle.write(&events)?;
+        self.write(&events)?;
         Ok(())
     }
 
     pub fn destruct(self) -> Result<(), std::io::Error> {
-        self.handle.dev_destroy()
+        match self.handle {
+            Some(handle) => handle.dev_destroy(),
+            None => Ok(()),
+        }
     }
 }